@@ -0,0 +1,375 @@
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use lopdf::content::Operation;
+use image::imageops::FilterType;
+use std::collections::HashMap;
+use crate::compress::{self, GraphicsState, Matrix, IDENTITY};
+use crate::pages;
+
+// Segments each Bézier curve is flattened into.
+const CURVE_STEPS: usize = 8;
+// Image samples at least this light count as paper when trimming by pixels.
+const PAPER_LEVEL: u8 = 235;
+// Images are searched for ink at most this size.
+const PIXEL_SIZE: u32 = 1000;
+// Glyph width, in thousandths of the font size, for fonts that give none; on the wide side so text is not cut off.
+const DEFAULT_WIDTH: f32 = 600.0;
+
+// Something painted on a page, in default user space.
+pub enum Mark {
+    // Subpaths as polylines, with the stroke width if the path is stroked.
+    Path { subpaths: Vec<Vec<(f32, f32)>>, fill: bool, stroke: Option<f32> },
+    // The corners of the box a run of text occupies.
+    Text([(f32, f32); 4]),
+    // An image drawn into the unit square, and its XObject unless it is inline.
+    Image { matrix: Matrix, id: Option<ObjectId> },
+}
+
+impl Mark {
+    fn points(&self) -> Vec<(f32, f32)> {
+        match self {
+            Mark::Path { subpaths, .. } => subpaths.concat(),
+            Mark::Text(corners) => corners.to_vec(),
+            Mark::Image { matrix, .. } => [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].iter().map(|&(x, y)| pages::transform(matrix, x, y)).collect(),
+        }
+    }
+}
+
+// Everything visibly painted by the page's content, leaving out white fills and invisible text.
+pub fn page_marks(doc: &Document, page_id: ObjectId) -> Vec<Mark> {
+    let mut tracer = Tracer::default();
+    compress::visit_page(doc, page_id, false, &mut |op, state| tracer.trace(doc, op, state));
+    tracer.marks
+}
+
+// The smallest rectangle holding the page's painted content, or None for a blank page. With `pixels`, white
+// borders inside images are left out too, which is what trims scanned pages.
+pub fn content_bounds(doc: &Document, page_id: ObjectId, pixels: bool) -> Option<[f32; 4]> {
+    let mut bounds: Option<[f32; 4]> = None;
+    let mut add = |(x, y): (f32, f32), pad: f32| {
+        let b = bounds.get_or_insert([x, y, x, y]);
+        *b = [b[0].min(x - pad), b[1].min(y - pad), b[2].max(x + pad), b[3].max(y + pad)];
+    };
+    for mark in page_marks(doc, page_id) {
+        match &mark {
+            Mark::Image { matrix, id: Some(id) } if pixels => {
+                let Some(ink) = doc.get_object(*id).and_then(Object::as_stream).ok().and_then(|s| ink_box(doc, s)) else {
+                    for point in mark.points() { add(point, 0.0); }
+                    continue;
+                };
+                let Some([x0, y0, x1, y1]) = ink else { continue };
+                for (x, y) in [(x0, y0), (x1, y0), (x0, y1), (x1, y1)] { add(pages::transform(matrix, x, y), 0.0); }
+            }
+            Mark::Path { stroke, .. } => for point in mark.points() { add(point, stroke.unwrap_or(0.0) / 2.0); },
+            _ => for point in mark.points() { add(point, 0.0); },
+        }
+    }
+    bounds
+}
+
+// The inked part of an image within its unit square, Some(None) when it is blank paper, or None when it cannot
+// be decoded.
+fn ink_box(doc: &Document, stream: &lopdf::Stream) -> Option<Option<[f32; 4]>> {
+    let img = compress::decode_image(doc, stream)?;
+    let img = if img.width() > PIXEL_SIZE || img.height() > PIXEL_SIZE { img.resize(PIXEL_SIZE, PIXEL_SIZE, FilterType::Triangle) } else { img }.to_luma8();
+    let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, pixel) in img.enumerate_pixels() {
+        if pixel.0[0] >= PAPER_LEVEL { continue; }
+        left = left.min(x);
+        top = top.min(y);
+        right = right.max(x + 1);
+        bottom = bottom.max(y + 1);
+    }
+    if left == u32::MAX { return Some(None); }
+    let (w, h) = (img.width() as f32, img.height() as f32);
+    // Image rows run from the top of the unit square down.
+    Some(Some([left as f32 / w, 1.0 - bottom as f32 / h, right as f32 / w, 1.0 - top as f32 / h]))
+}
+
+// Horizontal metrics of a font, in text space units per unit of font size.
+struct FontMetrics {
+    two_byte: bool,
+    widths: HashMap<u32, f32>,
+    default_width: f32,
+    ascent: f32,
+    descent: f32,
+}
+
+fn font_metrics(doc: &Document, font_id: ObjectId) -> FontMetrics {
+    let mut metrics = FontMetrics { two_byte: false, widths: HashMap::new(), default_width: DEFAULT_WIDTH / 1000.0, ascent: 0.9, descent: -0.25 };
+    let Ok(font) = doc.get_dictionary(font_id) else { return metrics };
+    let number = |o: &Object| doc.dereference(o).ok().and_then(|(_, o)| o.as_float().ok());
+    let array = |dict: &Dictionary, key: &[u8]| dict.get(key).ok().and_then(|a| doc.dereference(a).ok()?.1.as_array().ok()).cloned().unwrap_or_default();
+    // Type 3 glyph widths are in glyph space, which the font matrix maps to text space.
+    let scale = array(font, b"FontMatrix").first().and_then(number).unwrap_or(0.001);
+    let subtype = font.get(b"Subtype").and_then(Object::as_name).ok();
+    let descendant = if subtype == Some(b"Type0") {
+        metrics.two_byte = true;
+        font.get(b"DescendantFonts").ok().and_then(|d| doc.dereference(d).ok()?.1.as_array().ok()?.first())
+            .and_then(|d| compress::resolve_dict(doc, d))
+    } else {
+        None
+    };
+    if let Some(cid_font) = descendant {
+        metrics.default_width = cid_font.get(b"DW").ok().and_then(number).unwrap_or(1000.0) * scale;
+        let w = array(cid_font, b"W");
+        let mut i = 0;
+        while i + 1 < w.len() {
+            let Some(first) = number(&w[i]) else { break };
+            match doc.dereference(&w[i + 1]).map(|(_, o)| o) {
+                Ok(Object::Array(list)) => {
+                    for (cid, width) in (first as u32..).zip(list) { if let Some(width) = number(width) { metrics.widths.insert(cid, width * scale); } }
+                    i += 2;
+                }
+                _ => {
+                    let (Some(last), Some(width)) = (number(&w[i + 1]), w.get(i + 2).and_then(number)) else { break };
+                    if last - first > 65535.0 { break; }
+                    for cid in first as u32..=last as u32 { metrics.widths.insert(cid, width * scale); }
+                    i += 3;
+                }
+            }
+        }
+    } else {
+        let first = font.get(b"FirstChar").ok().and_then(number).unwrap_or(0.0) as u32;
+        for (code, width) in (first..).zip(array(font, b"Widths")) { if let Some(width) = number(&width) { metrics.widths.insert(code, width * scale); } }
+    }
+    let descriptor = descendant.unwrap_or(font).get(b"FontDescriptor").ok().and_then(|d| compress::resolve_dict(doc, d));
+    if let Some(descriptor) = descriptor {
+        if let Some(width) = descriptor.get(b"MissingWidth").ok().and_then(number) && descendant.is_none() { metrics.default_width = width * scale; }
+        if let Some(ascent) = descriptor.get(b"Ascent").ok().and_then(number).filter(|&a| a > 0.0) { metrics.ascent = ascent * scale; }
+        if let Some(descent) = descriptor.get(b"Descent").ok().and_then(number).filter(|&d| d < 0.0) { metrics.descent = descent * scale; }
+    }
+    metrics
+}
+
+// Graphics and text state saved by q and restored by Q.
+#[derive(Clone, Copy)]
+struct Style {
+    line_width: f32,
+    white_fill: bool,
+    white_stroke: bool,
+    char_spacing: f32,
+    word_spacing: f32,
+    scaling: f32,
+    leading: f32,
+    rise: f32,
+    font_size: f32,
+    invisible: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style { line_width: 1.0, white_fill: false, white_stroke: false, char_spacing: 0.0, word_spacing: 0.0, scaling: 1.0, leading: 0.0, rise: 0.0, font_size: 0.0, invisible: false }
+    }
+}
+
+#[derive(Default)]
+struct Tracer {
+    marks: Vec<Mark>,
+    style: Style,
+    saved: Vec<Style>,
+    path: Vec<Vec<(f32, f32)>>,
+    // Current point in user space, for curves that reuse it as a control point.
+    current: (f32, f32),
+    text_matrix: Matrix,
+    line_matrix: Matrix,
+    fonts: HashMap<ObjectId, FontMetrics>,
+}
+
+impl Tracer {
+    fn trace(&mut self, doc: &Document, op: &Operation, state: &GraphicsState) {
+        let n: Vec<f32> = op.operands.iter().filter_map(|o| o.as_float().ok()).collect();
+        let ctm = &state.ctm;
+        let style = &mut self.style;
+        match (op.operator.as_str(), &n[..]) {
+            ("q", _) => self.saved.push(*style),
+            ("Q", _) => if let Some(s) = self.saved.pop() { *style = s },
+            ("w", &[width]) => style.line_width = width,
+            ("g", &[gray]) => style.white_fill = gray >= 1.0,
+            ("G", &[gray]) => style.white_stroke = gray >= 1.0,
+            ("rg", &[r, g, b]) => style.white_fill = r.min(g).min(b) >= 1.0,
+            ("RG", &[r, g, b]) => style.white_stroke = r.min(g).min(b) >= 1.0,
+            ("k", &[c, m, y, k]) => style.white_fill = c.max(m).max(y).max(k) <= 0.0,
+            ("K", &[c, m, y, k]) => style.white_stroke = c.max(m).max(y).max(k) <= 0.0,
+            ("cs" | "sc" | "scn", _) => style.white_fill = false,
+            ("CS" | "SC" | "SCN", _) => style.white_stroke = false,
+            ("m", &[x, y]) => {
+                self.path.push(vec![pages::transform(ctm, x, y)]);
+                self.current = (x, y);
+            }
+            ("l", &[x, y]) => self.line_to(ctm, &[(x, y)]),
+            ("c", &[x1, y1, x2, y2, x3, y3]) => self.curve_to(ctm, (x1, y1), (x2, y2), (x3, y3)),
+            ("v", &[x2, y2, x3, y3]) => self.curve_to(ctm, self.current, (x2, y2), (x3, y3)),
+            ("y", &[x1, y1, x3, y3]) => self.curve_to(ctm, (x1, y1), (x3, y3), (x3, y3)),
+            ("h", _) => if let Some(first) = self.path.last().and_then(|p| p.first()).copied() { self.path.last_mut().unwrap().push(first) },
+            ("re", &[x, y, w, h]) => {
+                self.path.push(vec![pages::transform(ctm, x, y)]);
+                self.current = (x, y);
+                self.line_to(ctm, &[(x + w, y), (x + w, y + h), (x, y + h), (x, y)]);
+            }
+            ("S" | "s" | "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" | "n", _) => {
+                let operator = op.operator.as_str();
+                let fill = !matches!(operator, "S" | "s" | "n") && !style.white_fill;
+                let stroke = matches!(operator, "S" | "s" | "B" | "B*" | "b" | "b*") && !style.white_stroke;
+                let subpaths = std::mem::take(&mut self.path);
+                if fill || stroke {
+                    let stroke = stroke.then(|| style.line_width * (ctm[0] * ctm[3] - ctm[1] * ctm[2]).abs().sqrt());
+                    self.marks.push(Mark::Path { subpaths, fill, stroke });
+                }
+            }
+            ("BT", _) => {
+                self.text_matrix = IDENTITY;
+                self.line_matrix = IDENTITY;
+            }
+            ("Tc", &[spacing]) => style.char_spacing = spacing,
+            ("Tw", &[spacing]) => style.word_spacing = spacing,
+            ("Tz", &[scale]) => style.scaling = scale / 100.0,
+            ("TL", &[leading]) => style.leading = leading,
+            ("Ts", &[rise]) => style.rise = rise,
+            ("Tr", &[mode]) => style.invisible = mode == 3.0 || mode == 7.0,
+            ("Tf", _) => style.font_size = op.operands.get(1).and_then(|o| o.as_float().ok()).unwrap_or(0.0),
+            ("Td", &[x, y]) => self.next_line(x, y),
+            ("TD", &[x, y]) => {
+                style.leading = -y;
+                self.next_line(x, y);
+            }
+            ("Tm", _) => if let Some(m) = compress::to_matrix(&op.operands) {
+                self.text_matrix = m;
+                self.line_matrix = m;
+            },
+            ("T*", _) => self.next_line(0.0, -self.style.leading),
+            ("Tj" | "'" | "\"" | "TJ", _) => {
+                match op.operator.as_str() {
+                    "'" => self.next_line(0.0, -self.style.leading),
+                    "\"" => {
+                        if let [word, char, ..] = n[..] { (self.style.word_spacing, self.style.char_spacing) = (word, char); }
+                        self.next_line(0.0, -self.style.leading);
+                    }
+                    _ => {}
+                }
+                let items: Vec<&Object> = match op.operands.last() {
+                    Some(Object::Array(items)) if op.operator == "TJ" => items.iter().collect(),
+                    Some(text) => vec![text],
+                    None => return,
+                };
+                self.show_text(doc, &items, state);
+            }
+            ("Do", _) => {
+                let Some(name) = op.operands.first().and_then(|o| o.as_name().ok()) else { return };
+                let Some((id, stream)) = compress::xobject(doc, state.resources, name) else { return };
+                if stream.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Image") { self.marks.push(Mark::Image { matrix: *ctm, id: Some(id) }); }
+            }
+            ("BI", _) => self.marks.push(Mark::Image { matrix: *ctm, id: None }),
+            _ => {}
+        }
+    }
+
+    fn line_to(&mut self, ctm: &Matrix, points: &[(f32, f32)]) {
+        if self.path.is_empty() { self.path.push(vec![pages::transform(ctm, self.current.0, self.current.1)]); }
+        let subpath = self.path.last_mut().unwrap();
+        for &(x, y) in points { subpath.push(pages::transform(ctm, x, y)); }
+        if let Some(&last) = points.last() { self.current = last; }
+    }
+
+    fn curve_to(&mut self, ctm: &Matrix, a: (f32, f32), b: (f32, f32), end: (f32, f32)) {
+        let start = self.current;
+        let points: Vec<(f32, f32)> = (1..=CURVE_STEPS).map(|i| {
+            let t = i as f32 / CURVE_STEPS as f32;
+            let u = 1.0 - t;
+            let blend = |p: f32, q: f32, r: f32, s: f32| u * u * u * p + 3.0 * u * u * t * q + 3.0 * u * t * t * r + t * t * t * s;
+            (blend(start.0, a.0, b.0, end.0), blend(start.1, a.1, b.1, end.1))
+        }).collect();
+        self.line_to(ctm, &points);
+    }
+
+    fn next_line(&mut self, x: f32, y: f32) {
+        self.line_matrix = compress::multiply(&[1.0, 0.0, 0.0, 1.0, x, y], &self.line_matrix);
+        self.text_matrix = self.line_matrix;
+    }
+
+    // Adds the box covered by the strings and moves the text matrix past them.
+    fn show_text(&mut self, doc: &Document, items: &[&Object], state: &GraphicsState) {
+        let Some(font_id) = state.font else { return };
+        let metrics = self.fonts.entry(font_id).or_insert_with(|| font_metrics(doc, font_id));
+        let style = self.style;
+        let (mut x, mut left, mut right) = (0.0f32, 0.0f32, 0.0f32);
+        for item in items {
+            match item {
+                Object::String(bytes, _) => {
+                    let codes: Vec<u32> = if metrics.two_byte {
+                        bytes.chunks_exact(2).map(|c| u32::from(u16::from_be_bytes([c[0], c[1]]))).collect()
+                    } else {
+                        bytes.iter().map(|&c| u32::from(c)).collect()
+                    };
+                    for code in codes {
+                        let width = metrics.widths.get(&code).copied().unwrap_or(metrics.default_width);
+                        let word = if !metrics.two_byte && code == 32 { style.word_spacing } else { 0.0 };
+                        x += (width * style.font_size + style.char_spacing + word) * style.scaling;
+                        left = left.min(x);
+                        right = right.max(x);
+                    }
+                }
+                other => if let Ok(adjust) = other.as_float() { x -= adjust / 1000.0 * style.font_size * style.scaling },
+            }
+        }
+        let (bottom, top) = (style.rise + metrics.descent * style.font_size, style.rise + metrics.ascent * style.font_size);
+        let m = compress::multiply(&self.text_matrix, &state.ctm);
+        if !style.invisible && right > left {
+            self.marks.push(Mark::Text([(left, bottom), (right, bottom), (right, top), (left, top)].map(|(x, y)| pages::transform(&m, x, y))));
+        }
+        self.text_matrix = compress::multiply(&[1.0, 0.0, 0.0, 1.0, x, 0.0], &self.text_matrix);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Stream};
+    use image::{GrayImage, Luma};
+    use crate::pdf::tests::sample_document;
+
+    // The sample page with its content replaced, and an image in the left half white and the right half black.
+    fn page_drawing(content: &str) -> (Document, ObjectId) {
+        let mut doc = sample_document(1);
+        let page_id = doc.page_iter().next().unwrap();
+        let img = GrayImage::from_fn(20, 10, |x, _| Luma([if x < 10 { 255 } else { 0 }]));
+        let image_id = doc.add_object(Stream::new(dictionary! {
+            "Type" => "XObject", "Subtype" => "Image", "Width" => 20, "Height" => 10, "ColorSpace" => "DeviceGray", "BitsPerComponent" => 8,
+        }, img.into_raw()));
+        let content_id = doc.get_page_contents(page_id)[0];
+        doc.get_object_mut(content_id).unwrap().as_stream_mut().unwrap().set_plain_content(content.as_bytes().to_vec());
+        let resources = doc.get_dictionary_mut(page_id).unwrap().get_mut(b"Resources").unwrap().as_dict_mut().unwrap();
+        resources.set("XObject", dictionary! { "Im1" => image_id });
+        (doc, page_id)
+    }
+
+    fn assert_bounds(bounds: Option<[f32; 4]>, expected: [f32; 4]) {
+        let bounds = bounds.unwrap();
+        assert!(bounds.iter().zip(expected).all(|(a, b)| (a - b).abs() < 0.01), "{:?} is not {:?}", bounds, expected);
+    }
+
+    #[test]
+    fn stroked_paths_reach_half_the_line_width_out() {
+        let (doc, page_id) = page_drawing("5 w 100 100 m 200 150 l S");
+        assert_bounds(content_bounds(&doc, page_id, false), [97.5, 97.5, 202.5, 152.5]);
+    }
+
+    #[test]
+    fn text_covers_its_advance_and_the_font_height() {
+        // Helvetica has no Widths here, so each glyph takes the default 600 units.
+        let (doc, page_id) = page_drawing("BT /F1 10 Tf 300 400 Td (AB) Tj ET");
+        assert_bounds(content_bounds(&doc, page_id, false), [300.0, 397.5, 312.0, 409.0]);
+    }
+
+    #[test]
+    fn images_cover_their_placement_or_only_their_ink() {
+        let (doc, page_id) = page_drawing("q 50 0 0 40 400 500 cm /Im1 Do Q");
+        assert_bounds(content_bounds(&doc, page_id, false), [400.0, 500.0, 450.0, 540.0]);
+        assert_bounds(content_bounds(&doc, page_id, true), [425.0, 500.0, 450.0, 540.0]);
+    }
+
+    #[test]
+    fn blank_pages_have_no_bounds() {
+        let (doc, page_id) = page_drawing("q 1 g Q");
+        assert_eq!(content_bounds(&doc, page_id, false), None);
+    }
+}
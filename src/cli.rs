@@ -0,0 +1,415 @@
+use std::path::{Path, PathBuf};
+use crate::pdf;
+use crate::stamp::{self, ImageStamp, Position, StandardFont, TextStamp};
+
+const USAGE: &str = "Usage:
+  rusty-pdf                                        Start the graphical interface
+  rusty-pdf extract <input> <output> <pages>       Extract pages (e.g. \"4, 9, 12-15\") into a new PDF
+  rusty-pdf split <input> <output_dir|file.zip>    Write every page to its own PDF, or into one ZIP archive
+  rusty-pdf spreads <input> <output> [pages]       Cut two-page spreads into single pages, left half first
+  rusty-pdf rotate <input> <output> <deg> [pages]  Rotate pages by 90, 180, 270 or -90 degrees, or to portrait or landscape
+  rusty-pdf crop <input> <output> <how> [pages]    Crop by margins in points (\"36\" or \"left,bottom,right,top\"), or \"auto\" to trim to the content
+  rusty-pdf fit <input> <output> <paper> [pages]   Put pages on a3, a4, a5, letter, legal or a custom size (\"500x700\" points, \"210x297mm\")
+  rusty-pdf n-up <input> <output> <n> [pages]      Put 2, 4, 6, 8, 9 or 16 pages, or a grid such as \"3x2\" (columns x rows), on each sheet
+  rusty-pdf booklet <input> <output> [pages]       Impose pages as a saddle-stitched booklet, two per side of landscape sheets
+  rusty-pdf poster <input> <output> [pages]        Tile large pages across several sheets to tape together
+  rusty-pdf watermark <input> <output> <text>      Draw text such as \"DRAFT\" across pages, optionally followed by the pages to mark
+  rusty-pdf stamp <input> <output> <logo> [pages]  Draw an image file such as a logo, with transparency, on pages
+  rusty-pdf number <input> <output> [pages]        Number pages, e.g. \"Page {n} of {total}\" footers or Bates numbers
+  rusty-pdf headers <input> <output> [pages]       Add headers and footers, given with --header and --footer
+  rusty-pdf overlay <input> <output> <stamp>       Draw pages of the stamp PDF, such as a letterhead, on pages, optionally followed by the pages to stamp
+  rusty-pdf compress <input> <output> [options]    Compress a PDF
+  rusty-pdf batch compress <output_dir> <inputs>   Compress several PDFs in parallel
+  rusty-pdf batch number <output_dir> <inputs>     Number the pages of several PDFs, continuing from one file to the next
+  rusty-pdf check-linearized <input>               Report whether a PDF is linearized for fast web view
+
+Output options:
+  --linearize                                      Write linearized (fast web view) output
+
+Spreads options:
+  --right-to-left                                  Put the right half first, for right-to-left books
+  --horizontal                                     Cut into top and bottom halves instead
+
+Rotate options:
+  --detect-text                                    With portrait or landscape, turn scanned pages so their text reads upright
+  --bake                                           Draw the rotation into the page content and reset Rotate to 0 (angle 0 bakes only)
+
+Crop options:
+  --padding <pt>                                   With auto, keep this much space around the content
+  --pixels                                         With auto, also trim white borders inside images, such as scans
+  --media-box                                      Also set the MediaBox, discarding the trimmed area
+
+Fit options:
+  --fit-mode <mode>                                fit (default, scale to fit whole), fill (scale to cover the paper) or center (keep size)
+
+N-up options:
+  --paper <size>                                   Sheet size as for fit (default: the size of the first page)
+  --margin <pt> / --gutter <pt>                    Space around the sheet edges (default 18) and between pages (default 9)
+  --column-major                                   Fill columns top to bottom instead of rows left to right
+  --borders                                        Outline each page
+
+Booklet options:
+  --paper <size>                                   Sheet size as for fit (default: two pages side by side)
+  --signature <n>                                  Pages per folded signature, a multiple of 4 (default: all pages)
+  --creep <pt>                                     Move pages on the innermost sheet this far toward the spine
+
+Poster options:
+  --paper <size>                                   Sheet size as for fit (default a4)
+  --scale <n>                                      Enlarge pages by this factor first (default 1)
+  --overlap <pt> / --margin <pt>                   Area repeated on neighbouring tiles, and unprinted sheet border (default 18 each)
+  --no-cut-marks / --no-labels                     Leave out the corner cut marks, or the tile labels
+
+Watermark options:
+  --font <name>                                    helvetica (default), helvetica-bold, times, times-bold, courier or courier-bold
+  --size <pt> / --color <#rrggbb>                  Font size (default 72) and color (default #808080)
+  --opacity <n>                                    From 0 (invisible) to 1 (opaque), default 0.3
+  --angle <deg>                                    Counter-clockwise rotation of the text (default 45)
+  --position <pos>                                 center (default), top, bottom, left, right, top-left, top-right, bottom-left or bottom-right
+  --margin <pt>                                    Distance from the page edges when not centered (default 36)
+  --under                                          Draw under the page content instead of over it
+
+Stamp options:
+  --width <pt>                                     Width of the image on the page (default 144); the height keeps its proportions
+  --position <pos> / --offset <x,y>                Where to put the image as for watermark (default bottom-right), then how far
+                                                   to move it right and up (default -36,36)
+  --opacity <n> / --angle <deg> / --under          As for watermark (defaults 1, 0 and over the content)
+
+Number options:
+  --format <template>                              Text with {n} for the number and {total} for the last number (default \"{n}\")
+  --start <n> / --digits <n>                       First number (default 1), and digits to pad numbers to with zeros
+  --prefix <text> / --suffix <text>                Text around each number, e.g. --prefix ACME- --digits 6 for ACME-000123
+  --font, --size, --color, --position, --margin    As for watermark (defaults helvetica, 10, black, bottom, 36)
+
+Headers options:
+  --header <text> / --footer <text>                \"left|center|right\", or one text to center; tokens {filename}, {title},
+                                                   {date}, {page} and {pages} are filled in
+  --first-header / --first-footer <text>           Different texts on the first page
+  --even-header / --even-footer <text>             Different texts on even pages
+  --font, --size, --color, --margin, --under       As for watermark (defaults helvetica, 9, black, 36)
+
+Overlay options:
+  --mode <mode>                                    repeat (default, the stamp's first page on every page), match (stamp page n on
+                                                   page n) or first-other (stamp page 1 on the first selected page, 2 on the rest)
+  --under                                          Draw the stamp under the page content, as for a letterhead
+
+Compress options:
+  --preset <name>                                  screen (72 dpi), ebook (150 dpi), print (300 dpi), lossless or compatibility
+  --object-streams / --classic-xref                Pack objects into object streams, or write a classic xref table
+  --dpi <n>                                        Downsample images drawn above 1.5x this resolution
+  --quality <n>                                    JPEG quality (1-100) for recompressed images
+  --metadata <mode>                                keep, info (remove the document info, the default) or all (also XMP, PieceInfo, thumbnails)
+  --subset-fonts                                   Subset embedded fonts to the glyphs in use and drop unused fonts
+  --max-deflate                                    Re-deflate streams with zopfli and PNG image predictors (slow, lossless)
+
+Parallel work uses all cores; set RAYON_NUM_THREADS to limit the thread count.";
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = args.to_vec();
+    let compress_options = take_compress_options(&mut args)?;
+    let output_options = pdf::OutputOptions { linearize: take_switch(&mut args, "--linearize") };
+    let right_to_left = take_switch(&mut args, "--right-to-left");
+    let split_direction = if take_switch(&mut args, "--horizontal") { pdf::SplitDirection::Horizontal } else { pdf::SplitDirection::Vertical };
+    let detect_text = take_switch(&mut args, "--detect-text");
+    let bake = take_switch(&mut args, "--bake");
+    let padding = take_flag(&mut args, "--padding")?.unwrap_or(0.0);
+    let pixels = take_switch(&mut args, "--pixels");
+    let media_box = take_switch(&mut args, "--media-box");
+    let mut n_up_options = pdf::NUpOptions {
+        paper: take_flag::<String>(&mut args, "--paper")?.map(|name| pdf::PaperSize::from_name(&name).ok_or_else(|| format!("Unknown paper size: {}", name))).transpose()?,
+        column_major: take_switch(&mut args, "--column-major"),
+        borders: take_switch(&mut args, "--borders"),
+        ..Default::default()
+    };
+    let margin_flag = take_flag(&mut args, "--margin")?;
+    if let Some(margin) = margin_flag { n_up_options.margin = margin; }
+    if let Some(gutter) = take_flag(&mut args, "--gutter")? { n_up_options.gutter = gutter; }
+    let booklet_options = pdf::BookletOptions {
+        paper: n_up_options.paper,
+        signature: take_flag(&mut args, "--signature")?.unwrap_or(0),
+        creep: take_flag(&mut args, "--creep")?.unwrap_or(0.0),
+    };
+    let mut poster_options = pdf::PosterOptions {
+        paper: n_up_options.paper.unwrap_or(pdf::PaperSize::A4),
+        cut_marks: !take_switch(&mut args, "--no-cut-marks"),
+        labels: !take_switch(&mut args, "--no-labels"),
+        ..Default::default()
+    };
+    if let Some(scale) = take_flag(&mut args, "--scale")? { poster_options.scale = scale; }
+    if let Some(overlap) = take_flag(&mut args, "--overlap")? { poster_options.overlap = overlap; }
+    if let Some(margin) = margin_flag { poster_options.margin = margin; }
+    let stamp_flags = take_stamp_flags(&mut args, margin_flag)?;
+    let image_width = take_flag(&mut args, "--width")?;
+    let offset = take_flag::<String>(&mut args, "--offset")?.map(|text| match text.split(',').map(|v| v.trim().parse()).collect::<Result<Vec<f32>, _>>().as_deref() {
+        Ok([x, y]) => Ok((*x, *y)),
+        _ => Err(format!("Invalid offset: {}", text)),
+    }).transpose()?;
+    let mut numbering_options = pdf::NumberingOptions::default();
+    numbering_options.style = stamp_flags.apply(numbering_options.style);
+    if let Some(template) = take_flag(&mut args, "--format")? { numbering_options.template = template; }
+    if let Some(start) = take_flag(&mut args, "--start")? { numbering_options.start = start; }
+    if let Some(digits) = take_flag(&mut args, "--digits")? { numbering_options.digits = digits; }
+    if let Some(prefix) = take_flag(&mut args, "--prefix")? { numbering_options.prefix = prefix; }
+    if let Some(suffix) = take_flag(&mut args, "--suffix")? { numbering_options.suffix = suffix; }
+    let mut header_options = pdf::HeaderFooterOptions::default();
+    header_options.style = stamp_flags.apply(header_options.style);
+    let mut take_texts = |header: &str, footer: &str| -> Result<Option<pdf::HeaderFooter>, Box<dyn std::error::Error>> {
+        let (header, footer) = (take_flag::<String>(&mut args, header)?, take_flag::<String>(&mut args, footer)?);
+        if header.is_none() && footer.is_none() { return Ok(None); }
+        Ok(Some(pdf::HeaderFooter { header: split_texts(header.as_deref()), footer: split_texts(footer.as_deref()) }))
+    };
+    header_options.all = take_texts("--header", "--footer")?.unwrap_or_default();
+    header_options.first = take_texts("--first-header", "--first-footer")?;
+    header_options.even = take_texts("--even-header", "--even-footer")?;
+    let overlay_mode = match take_flag::<String>(&mut args, "--mode")? {
+        Some(name) => pdf::OverlayMode::from_name(&name).ok_or_else(|| format!("Unknown overlay mode: {}", name))?,
+        None => pdf::OverlayMode::Repeat,
+    };
+    let fit_mode = match take_flag::<String>(&mut args, "--fit-mode")? {
+        Some(name) => pdf::FitMode::from_name(&name).ok_or_else(|| format!("Unknown fit mode: {}", name))?,
+        None => pdf::FitMode::Fit,
+    };
+    match args.first().map(String::as_str) {
+        Some("extract") => {
+            let [input, output, pages] = expect_args(&args[1..])?;
+            let pages = pdf::parse_page_ranges(pages)?;
+            let count = pdf::extract_pages(&PathBuf::from(input), PathBuf::from(output), pages, &output_options)?;
+            println!("Extracted {} pages.", count);
+        }
+        Some("split") => {
+            let [input, output_dir] = expect_args(&args[1..])?;
+            let target = if output_dir.ends_with(".zip") { pdf::SplitOutput::Zip } else { pdf::SplitOutput::Folder };
+            let count = pdf::split_pdf(&PathBuf::from(input), &PathBuf::from(output_dir), target, &output_options)?;
+            println!("Split into {} pages.", count);
+        }
+        Some("spreads") => {
+            let (rest, pages) = if args.len() == 4 { (&args[1..3], pdf::parse_page_ranges(&args[3])?) } else { (&args[1..], Vec::new()) };
+            let [input, output] = expect_args(rest)?;
+            let count = pdf::split_spreads(&PathBuf::from(input), PathBuf::from(output), split_direction, right_to_left, &pages, &output_options)?;
+            println!("The document now has {} pages.", count);
+        }
+        Some("rotate") => {
+            let (rest, pages) = if args.len() == 5 { (&args[1..4], pdf::parse_page_ranges(&args[4])?) } else { (&args[1..], Vec::new()) };
+            let [input, output, angle] = expect_args(rest)?;
+            let rotation = match pdf::Rotation::from_name(angle).ok_or_else(|| format!("Invalid angle: {}", angle))? {
+                pdf::Rotation::AutoOrient { target, .. } => pdf::Rotation::AutoOrient { target, detect_text },
+                rotation => rotation,
+            };
+            let count = pdf::rotate_pdf(&PathBuf::from(input), PathBuf::from(output), rotation, &pages, bake, &output_options)?;
+            println!("Rotated {} pages.", count);
+        }
+        Some("crop") => {
+            let (rest, pages) = if args.len() == 5 { (&args[1..4], pdf::parse_page_ranges(&args[4])?) } else { (&args[1..], Vec::new()) };
+            let [input, output, how] = expect_args(rest)?;
+            let crop = if how == "auto" { pdf::Crop::Auto { padding, pixels } } else { pdf::Crop::Margins(parse_margins(how)?) };
+            let count = pdf::crop_pdf(&PathBuf::from(input), PathBuf::from(output), crop, &pages, media_box, &output_options)?;
+            println!("Cropped {} pages.", count);
+        }
+        Some("fit") => {
+            let (rest, pages) = if args.len() == 5 { (&args[1..4], pdf::parse_page_ranges(&args[4])?) } else { (&args[1..], Vec::new()) };
+            let [input, output, paper] = expect_args(rest)?;
+            let paper = pdf::PaperSize::from_name(paper).ok_or_else(|| format!("Unknown paper size: {}", paper))?;
+            let count = pdf::fit_to_paper(&PathBuf::from(input), PathBuf::from(output), paper, fit_mode, &pages, &output_options)?;
+            println!("Resized {} pages.", count);
+        }
+        Some("n-up") => {
+            let (rest, pages) = if args.len() == 5 { (&args[1..4], pdf::parse_page_ranges(&args[4])?) } else { (&args[1..], Vec::new()) };
+            let [input, output, grid] = expect_args(rest)?;
+            let parsed = match grid.split_once('x') {
+                Some((columns, rows)) => columns.parse().ok().zip(rows.parse().ok()),
+                None => grid.parse().ok().and_then(pdf::NUpOptions::grid),
+            };
+            (n_up_options.columns, n_up_options.rows) = parsed.filter(|&(c, r)| c > 0 && r > 0).ok_or_else(|| format!("Invalid grid: {}", grid))?;
+            let count = pdf::n_up(&PathBuf::from(input), PathBuf::from(output), &n_up_options, &pages, &output_options)?;
+            println!("Wrote {} sheets.", count);
+        }
+        Some("booklet") => {
+            let (rest, pages) = if args.len() == 4 { (&args[1..3], pdf::parse_page_ranges(&args[3])?) } else { (&args[1..], Vec::new()) };
+            let [input, output] = expect_args(rest)?;
+            let count = pdf::booklet(&PathBuf::from(input), PathBuf::from(output), &booklet_options, &pages, &output_options)?;
+            println!("Wrote {} sheets.", count);
+        }
+        Some("poster") => {
+            let (rest, pages) = if args.len() == 4 { (&args[1..3], pdf::parse_page_ranges(&args[3])?) } else { (&args[1..], Vec::new()) };
+            let [input, output] = expect_args(rest)?;
+            let count = pdf::poster(&PathBuf::from(input), PathBuf::from(output), &poster_options, &pages, &output_options)?;
+            println!("Wrote {} tiles.", count);
+        }
+        Some("watermark") => {
+            let (rest, pages) = if args.len() == 5 { (&args[1..4], pdf::parse_page_ranges(&args[4])?) } else { (&args[1..], Vec::new()) };
+            let [input, output, text] = expect_args(rest)?;
+            let defaults = TextStamp { text: text.to_string(), size: 72.0, color: [0.5; 3], opacity: 0.3, rotation: 45.0, ..Default::default() };
+            let count = pdf::watermark(&PathBuf::from(input), PathBuf::from(output), &stamp_flags.apply(defaults), &pages, &output_options)?;
+            println!("Watermarked {} pages.", count);
+        }
+        Some("stamp") => {
+            let (rest, pages) = if args.len() == 5 { (&args[1..4], pdf::parse_page_ranges(&args[4])?) } else { (&args[1..], Vec::new()) };
+            let [input, output, image] = expect_args(rest)?;
+            let defaults = ImageStamp::default();
+            let image_stamp = ImageStamp {
+                width: image_width.unwrap_or(defaults.width),
+                opacity: stamp_flags.opacity.unwrap_or(defaults.opacity),
+                rotation: stamp_flags.angle.unwrap_or(defaults.rotation),
+                position: stamp_flags.position.unwrap_or(defaults.position),
+                offset: offset.unwrap_or(defaults.offset),
+                under: stamp_flags.under,
+            };
+            let count = pdf::image_stamp(&PathBuf::from(input), PathBuf::from(output), Path::new(image), &image_stamp, &pages, &output_options)?;
+            println!("Stamped {} pages.", count);
+        }
+        Some("number") => {
+            let (rest, pages) = if args.len() == 4 { (&args[1..3], pdf::parse_page_ranges(&args[3])?) } else { (&args[1..], Vec::new()) };
+            let [input, output] = expect_args(rest)?;
+            let count = pdf::number_pages(&PathBuf::from(input), PathBuf::from(output), &numbering_options, &pages, &output_options)?;
+            println!("Numbered {} pages.", count);
+        }
+        Some("headers") => {
+            let (rest, pages) = if args.len() == 4 { (&args[1..3], pdf::parse_page_ranges(&args[3])?) } else { (&args[1..], Vec::new()) };
+            let [input, output] = expect_args(rest)?;
+            let count = pdf::headers_footers(&PathBuf::from(input), PathBuf::from(output), &header_options, &pages, &output_options)?;
+            println!("Added headers and footers to {} pages.", count);
+        }
+        Some("overlay") => {
+            let (rest, pages) = if args.len() == 5 { (&args[1..4], pdf::parse_page_ranges(&args[4])?) } else { (&args[1..], Vec::new()) };
+            let [input, output, stamp_pdf] = expect_args(rest)?;
+            let count = pdf::overlay(&PathBuf::from(input), Path::new(stamp_pdf), PathBuf::from(output), overlay_mode, stamp_flags.under, &pages, &output_options)?;
+            println!("Stamped {} pages.", count);
+        }
+        Some("compress") => {
+            let [input, output] = expect_args(&args[1..])?;
+            let report = pdf::compress_pdf(&PathBuf::from(input), PathBuf::from(output), &compress_options, &output_options)?;
+            println!("{}", report.summary());
+        }
+        Some("batch") => {
+            if args.len() < 4 { return Err(format!("Expected an operation, an output folder and input files.\n{}", USAGE).into()); }
+            let inputs: Vec<PathBuf> = args[3..].iter().map(PathBuf::from).collect();
+            let results = match args[1].as_str() {
+                "compress" => pdf::batch_process(&inputs, Path::new(&args[2]), |i, o| {
+                    let report = pdf::compress_pdf(i, o, &compress_options, &output_options)?;
+                    if report.new_size > report.original_size { eprintln!("Warning: {} came out larger than the original.", i.display()); }
+                    Ok(())
+                }),
+                "number" => pdf::number_batch(&inputs, Path::new(&args[2]), &numbering_options, &output_options),
+                other => return Err(format!("Unknown batch operation: {}", other).into()),
+            };
+            let failed = results.iter().filter(|r| r.is_err()).count();
+            for result in results {
+                match result {
+                    Ok(output) => println!("Wrote {}", output.display()),
+                    Err(e) => eprintln!("Failed {}", e),
+                }
+            }
+            if failed > 0 { return Err(format!("{} of {} files failed.", failed, inputs.len()).into()); }
+        }
+        Some("check-linearized") => {
+            let [input] = expect_args(&args[1..])?;
+            println!("{}", pdf::check_linearized(&PathBuf::from(input))?.summary());
+        }
+        Some("help") | Some("--help") | Some("-h") => println!("{}", USAGE),
+        Some(other) => return Err(format!("Unknown command: {}\n{}", other, USAGE).into()),
+        None => println!("{}", USAGE),
+    }
+    Ok(())
+}
+
+fn expect_args<const N: usize>(args: &[String]) -> Result<[&str; N], Box<dyn std::error::Error>> {
+    if args.len() != N { return Err(format!("Expected {} arguments, got {}.\n{}", N, args.len(), USAGE).into()); }
+    Ok(std::array::from_fn(|i| args[i].as_str()))
+}
+
+// One margin for every side, or four as left, bottom, right, top.
+fn parse_margins(text: &str) -> Result<[f32; 4], Box<dyn std::error::Error>> {
+    let values: Vec<f32> = text.split(',').map(|v| v.trim().parse()).collect::<Result<_, _>>().map_err(|_| format!("Invalid margins: {}", text))?;
+    match values[..] {
+        [all] => Ok([all; 4]),
+        [left, bottom, right, top] => Ok([left, bottom, right, top]),
+        _ => Err(format!("Expected one or four margins, got {}.", values.len()).into()),
+    }
+}
+
+// Text settings given on the command line, which override each command's own defaults.
+struct StampFlags {
+    font: Option<StandardFont>,
+    size: Option<f32>,
+    color: Option<[f32; 3]>,
+    opacity: Option<f32>,
+    angle: Option<f32>,
+    position: Option<Position>,
+    margin: Option<f32>,
+    under: bool,
+}
+
+impl StampFlags {
+    fn apply(&self, stamp: TextStamp) -> TextStamp {
+        TextStamp {
+            font: self.font.unwrap_or(stamp.font),
+            size: self.size.unwrap_or(stamp.size),
+            color: self.color.unwrap_or(stamp.color),
+            opacity: self.opacity.unwrap_or(stamp.opacity),
+            rotation: self.angle.unwrap_or(stamp.rotation),
+            position: self.position.unwrap_or(stamp.position),
+            margin: self.margin.unwrap_or(stamp.margin),
+            under: self.under || stamp.under,
+            ..stamp
+        }
+    }
+}
+
+fn take_stamp_flags(args: &mut Vec<String>, margin: Option<f32>) -> Result<StampFlags, Box<dyn std::error::Error>> {
+    Ok(StampFlags {
+        font: take_flag::<String>(args, "--font")?.map(|name| StandardFont::from_name(&name).ok_or_else(|| format!("Unknown font: {}", name))).transpose()?,
+        size: take_flag(args, "--size")?,
+        color: take_flag::<String>(args, "--color")?.map(|text| stamp::parse_color(&text).ok_or_else(|| format!("Invalid color: {}", text))).transpose()?,
+        opacity: take_flag::<f32>(args, "--opacity")?.map(|o| o.clamp(0.0, 1.0)),
+        angle: take_flag(args, "--angle")?,
+        position: take_flag::<String>(args, "--position")?.map(|name| Position::from_name(&name).ok_or_else(|| format!("Unknown position: {}", name))).transpose()?,
+        margin,
+        under: take_switch(args, "--under"),
+    })
+}
+
+// Left, center and right texts from "left|center|right", with a single text centered.
+fn split_texts(text: Option<&str>) -> [String; 3] {
+    let parts: Vec<&str> = text.unwrap_or_default().split('|').collect();
+    match parts[..] {
+        [center] => [String::new(), center.to_string(), String::new()],
+        _ => std::array::from_fn(|i| parts.get(i).unwrap_or(&"").to_string()),
+    }
+}
+
+fn take_compress_options(args: &mut Vec<String>) -> Result<pdf::CompressOptions, Box<dyn std::error::Error>> {
+    let mut options = match take_flag::<String>(args, "--preset")? {
+        Some(name) => pdf::CompressPreset::from_name(&name).ok_or_else(|| format!("Unknown preset: {}", name))?.options(),
+        None => pdf::CompressOptions::default(),
+    };
+    if let Some(dpi) = take_flag(args, "--dpi")? {
+        options.optimize_images = true;
+        options.image_dpi = dpi;
+        options.image_dpi_threshold = dpi * 3 / 2;
+    }
+    if let Some(quality) = take_flag::<u8>(args, "--quality")? {
+        options.optimize_images = true;
+        options.jpeg_quality = quality.clamp(1, 100);
+    }
+    if let Some(mode) = take_flag::<String>(args, "--metadata")? {
+        options.metadata = pdf::MetadataMode::from_name(&mode).ok_or_else(|| format!("Unknown metadata mode: {}", mode))?;
+    }
+    if take_switch(args, "--subset-fonts") { options.subset_fonts = true; }
+    if take_switch(args, "--max-deflate") { options.max_deflate = true; }
+    if take_switch(args, "--object-streams") { options.object_streams = true; }
+    if take_switch(args, "--classic-xref") { options.object_streams = false; }
+    Ok(options)
+}
+
+fn take_switch(args: &mut Vec<String>, flag: &str) -> bool {
+    let Some(pos) = args.iter().position(|a| a == flag) else { return false };
+    args.remove(pos);
+    true
+}
+
+fn take_flag<T: std::str::FromStr>(args: &mut Vec<String>, flag: &str) -> Result<Option<T>, Box<dyn std::error::Error>> {
+    let Some(pos) = args.iter().position(|a| a == flag) else { return Ok(None) };
+    let value = args.get(pos + 1).ok_or_else(|| format!("Missing value for {}", flag))?;
+    let value = value.parse().map_err(|_| format!("Invalid value for {}: {}", flag, value))?;
+    args.drain(pos..pos + 2);
+    Ok(Some(value))
+}
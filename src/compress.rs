@@ -0,0 +1,1018 @@
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use lopdf::content::{Content, Operation};
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashSet};
+use std::num::NonZeroU64;
+use crate::pdf::{CompressOptions, MetadataMode};
+
+pub type Matrix = [f32; 6];
+pub const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+// Channel difference below which an RGB image is treated as gray.
+const GRAY_TOLERANCE: i16 = 6;
+// Images with few colors, or made almost entirely of paper and ink, are kept lossless.
+const LINE_ART_COLORS: usize = 64;
+const LINE_ART_EXTREMES: f32 = 0.9;
+// Streams above this many bytes are deflated at zlib's best level instead of with zopfli.
+const ZOPFLI_LIMIT: usize = 1024 * 1024;
+
+#[derive(Clone, Debug, Default)]
+pub struct SizeBreakdown {
+    pub images: u64,
+    pub fonts: u64,
+    pub content: u64,
+    pub metadata: u64,
+    pub other: u64,
+}
+
+impl SizeBreakdown {
+    pub fn categories(&self) -> [(&'static str, u64); 5] {
+        [("Images", self.images), ("Fonts", self.fonts), ("Content", self.content), ("Metadata", self.metadata), ("Other", self.other)]
+    }
+}
+
+// Approximate serialized size of every object, grouped by what it is used for. Object streams and cross-reference
+// streams are left out, since the objects they hold are counted on their own.
+pub fn size_breakdown(doc: &Document) -> SizeBreakdown {
+    let mut font_parts = HashSet::new();
+    let content_ids: HashSet<ObjectId> = doc.page_iter().flat_map(|id| doc.get_page_contents(id)).collect();
+    let mut metadata_ids = HashSet::new();
+    if let Ok(info) = doc.trailer.get(b"Info").and_then(Object::as_reference) { metadata_ids.insert(info); }
+    for object in doc.objects.values() {
+        let Ok(dict) = object.as_dict() else { continue };
+        if dict.has_type(b"Font") || dict.has_type(b"FontDescriptor") {
+            for key in [&b"FontFile"[..], b"FontFile2", b"FontFile3", b"ToUnicode", b"CIDSet", b"CIDToGIDMap", b"Widths", b"W"] {
+                if let Ok(id) = dict.get(key).and_then(Object::as_reference) { font_parts.insert(id); }
+            }
+        }
+        if let Ok(id) = dict.get(b"PieceInfo").and_then(Object::as_reference) { metadata_ids.insert(id); }
+    }
+    let mut sizes = SizeBreakdown::default();
+    for (id, object) in &doc.objects {
+        if object.type_name().is_ok_and(|name| name == b"ObjStm" || name == b"XRef") { continue; }
+        let size = object_size(object);
+        let dict = match object {
+            Object::Stream(stream) => Some(&stream.dict),
+            Object::Dictionary(dict) => Some(dict),
+            _ => None,
+        };
+        let subtype = dict.and_then(|d| d.get(b"Subtype").and_then(Object::as_name).ok());
+        let slot = if subtype == Some(b"Image") {
+            &mut sizes.images
+        } else if font_parts.contains(id) || dict.is_some_and(|d| d.has_type(b"Font") || d.has_type(b"FontDescriptor")) {
+            &mut sizes.fonts
+        } else if metadata_ids.contains(id) || dict.is_some_and(|d| d.has_type(b"Metadata")) {
+            &mut sizes.metadata
+        } else if content_ids.contains(id) || subtype == Some(b"Form") {
+            &mut sizes.content
+        } else {
+            &mut sizes.other
+        };
+        *slot += size;
+    }
+    sizes
+}
+
+fn object_size(object: &Object) -> u64 {
+    match object {
+        Object::Null => 4,
+        Object::Boolean(_) => 5,
+        Object::Integer(n) => n.to_string().len() as u64,
+        Object::Real(_) => 6,
+        Object::Name(name) => name.len() as u64 + 1,
+        Object::String(text, _) => text.len() as u64 + 2,
+        Object::Reference(_) => 8,
+        Object::Array(items) => 2 + items.iter().map(|o| object_size(o) + 1).sum::<u64>(),
+        Object::Dictionary(dict) => dictionary_size(dict),
+        Object::Stream(stream) => stream_size(stream),
+    }
+}
+
+fn stream_size(stream: &Stream) -> u64 {
+    dictionary_size(&stream.dict) + stream.content.len() as u64 + 17
+}
+
+fn dictionary_size(dict: &Dictionary) -> u64 {
+    4 + dict.iter().map(|(k, v)| k.len() as u64 + 2 + object_size(v)).sum::<u64>()
+}
+
+// StripInfo drops the document information dictionary from the trailer; StripAll also drops XMP metadata streams
+// wherever they are attached, private application data (PieceInfo) and page thumbnails.
+pub fn strip_metadata(doc: &mut Document, mode: MetadataMode) {
+    if mode == MetadataMode::Keep { return; }
+    doc.trailer.remove(b"Info");
+    if mode == MetadataMode::StripInfo { return; }
+    for object in doc.objects.values_mut() {
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &mut stream.dict,
+            _ => continue,
+        };
+        dict.remove(b"Metadata");
+        // LastModified only exists to date the PieceInfo entries.
+        if dict.remove(b"PieceInfo").is_some() { dict.remove(b"LastModified"); }
+        if dict.has_type(b"Page") { dict.remove(b"Thumb"); }
+    }
+}
+
+pub fn optimize_images(doc: &mut Document, options: &CompressOptions) -> usize {
+    let resolutions = image_resolutions(doc);
+    let source = &*doc;
+    let replacements: Vec<(ObjectId, Stream)> = resolutions.par_iter().filter_map(|(&id, &dpi)| {
+        let stream = source.get_object(id).ok()?.as_stream().ok()?;
+        optimize_image(source, stream, dpi, options).map(|s| (id, s))
+    }).collect();
+    let count = replacements.len();
+    for (id, stream) in replacements { doc.objects.insert(id, Object::Stream(stream)); }
+    count
+}
+
+fn optimize_image(doc: &Document, stream: &Stream, dpi: f32, options: &CompressOptions) -> Option<Stream> {
+    let mut img = decode_image(doc, stream)?;
+    if dpi > options.image_dpi_threshold as f32 {
+        let scale = options.image_dpi as f32 / dpi;
+        let width = ((img.width() as f32 * scale).round() as u32).max(1);
+        let height = ((img.height() as f32 * scale).round() as u32).max(1);
+        img = img.resize_exact(width, height, FilterType::Triangle);
+    }
+    let gray = match &img { DynamicImage::ImageLuma8(_) => true, _ => looks_gray(&img.to_rgb8()) };
+    let img = if gray { DynamicImage::ImageLuma8(img.to_luma8()) } else { DynamicImage::ImageRgb8(img.to_rgb8()) };
+
+    let mut dict = stream.dict.clone();
+    dict.set("Width", img.width() as i64);
+    dict.set("Height", img.height() as i64);
+    dict.set("ColorSpace", if gray { "DeviceGray" } else { "DeviceRGB" });
+    dict.set("BitsPerComponent", 8);
+    dict.remove(b"Filter");
+    dict.remove(b"DecodeParms");
+    let optimized = if is_line_art(&img) {
+        let mut flate = Stream::new(dict, img.into_bytes());
+        flate.compress().ok()?;
+        flate
+    } else {
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, options.jpeg_quality)
+            .encode(img.as_bytes(), img.width(), img.height(), img.color().into()).ok()?;
+        dict.set("Filter", "DCTDecode");
+        Stream::new(dict, jpeg)
+    };
+    (optimized.content.len() < stream.content.len()).then_some(optimized)
+}
+
+pub fn decode_image(doc: &Document, stream: &Stream) -> Option<DynamicImage> {
+    let dict = &stream.dict;
+    if dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false) { return None; }
+    // Decode arrays and color-key masks depend on exact sample values.
+    if dict.has(b"Decode") || dict.get(b"Mask").is_ok_and(|m| m.as_array().is_ok()) { return None; }
+    if dict.get(b"BitsPerComponent").and_then(Object::as_i64).ok()? != 8 { return None; }
+    let components = color_components(doc, dict.get(b"ColorSpace").ok()?)?;
+    let width = u32::try_from(dict.get(b"Width").and_then(Object::as_i64).ok()?).ok()?;
+    let height = u32::try_from(dict.get(b"Height").and_then(Object::as_i64).ok()?).ok()?;
+    let filters = stream.filters().unwrap_or_default();
+    if filters == [b"DCTDecode"] {
+        let img = image::load_from_memory_with_format(&stream.content, ImageFormat::Jpeg).ok()?;
+        return (img.width() == width && img.height() == height).then_some(img);
+    }
+    let mut data = plain_content(stream)?;
+    let len = (width as usize).checked_mul(height as usize)?.checked_mul(components as usize)?;
+    if data.len() < len { return None; }
+    data.truncate(len);
+    if components == 1 {
+        GrayImage::from_raw(width, height, data).map(DynamicImage::ImageLuma8)
+    } else {
+        RgbImage::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+    }
+}
+
+// The Predictor in a stream's DecodeParms dictionary, together with the dictionary.
+fn predictor(stream: &Stream) -> Option<(i64, &Dictionary)> {
+    let params = stream.dict.get(b"DecodeParms").and_then(Object::as_dict).ok()?;
+    let predictor = params.get(b"Predictor").and_then(Object::as_i64).ok().filter(|&p| p > 1)?;
+    Some((predictor, params))
+}
+
+// Decompressed bytes with the predictor still applied: lopdf misdecodes the PNG Average filter and ignores the
+// TIFF predictor, so it is kept out of their decoding.
+fn predicted_content(stream: &Stream) -> Option<Vec<u8>> {
+    let mut raw = stream.clone();
+    if let Ok(Object::Dictionary(p)) = raw.dict.get_mut(b"DecodeParms") { p.remove(b"Predictor"); }
+    raw.decompressed_content().ok()
+}
+
+fn plain_content(stream: &Stream) -> Option<Vec<u8>> {
+    let Some((predictor, params)) = predictor(stream) else {
+        // Parameters for a chain of filters may hold a predictor that isn't undone here.
+        if stream.dict.get(b"DecodeParms").is_ok_and(|p| p.as_array().is_ok()) { return None; }
+        return stream.get_plain_content().ok();
+    };
+    if !matches!(stream.filters().ok()?.as_slice(), [b"FlateDecode" | b"LZWDecode"]) { return None; }
+    let data = predicted_content(stream)?;
+    let param = |key: &[u8], default: i64| params.get(key).and_then(Object::as_i64).unwrap_or(default);
+    let (colors, bits, columns) = (param(b"Colors", 1), param(b"BitsPerComponent", 8), param(b"Columns", 1));
+    if !(1..=32).contains(&colors) || !matches!(bits, 1 | 2 | 4 | 8 | 16) || columns < 1 { return None; }
+    let bpp = ((colors * bits) as usize).div_ceil(8);
+    let row_len = usize::try_from(columns).ok()?.checked_mul((colors * bits) as usize)?.div_ceil(8);
+    match predictor {
+        2 if bits == 8 => Some(tiff_unpredict(data, row_len, bpp)),
+        10..=15 => png_unpredict(&data, row_len, bpp),
+        _ => None,
+    }
+}
+
+fn tiff_unpredict(mut data: Vec<u8>, row_len: usize, bpp: usize) -> Vec<u8> {
+    for row in data.chunks_mut(row_len) {
+        for i in bpp..row.len() { row[i] = row[i].wrapping_add(row[i - bpp]); }
+    }
+    data
+}
+
+fn png_unpredict(data: &[u8], row_len: usize, bpp: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut above = vec![0; row_len];
+    for chunk in data.chunks(row_len + 1) {
+        let (&filter, row) = chunk.split_first()?;
+        let mut row = row.to_vec();
+        for i in 0..row.len() {
+            let (a, b, c) = if i >= bpp { (row[i - bpp], above[i], above[i - bpp]) } else { (0, above[i], 0) };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return None,
+            };
+            row[i] = row[i].wrapping_add(predicted);
+        }
+        above[..row.len()].copy_from_slice(&row);
+        out.extend(row);
+    }
+    Some(out)
+}
+
+fn color_components(doc: &Document, color_space: &Object) -> Option<u32> {
+    match doc.dereference(color_space).ok()?.1 {
+        Object::Name(name) if name == b"DeviceGray" => Some(1),
+        Object::Name(name) if name == b"DeviceRGB" => Some(3),
+        Object::Array(items) if items.first().and_then(|o| o.as_name().ok()) == Some(b"ICCBased") => {
+            let profile = doc.dereference(items.get(1)?).ok()?.1.as_stream().ok()?;
+            match profile.dict.get(b"N").and_then(Object::as_i64).ok()? {
+                1 => Some(1),
+                3 => Some(3),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn looks_gray(img: &RgbImage) -> bool {
+    img.pixels().all(|p| {
+        let [r, g, b] = p.0.map(i16::from);
+        (r - g).abs() <= GRAY_TOLERANCE && (g - b).abs() <= GRAY_TOLERANCE
+    })
+}
+
+fn is_line_art(img: &DynamicImage) -> bool {
+    let channels = img.color().channel_count() as usize;
+    let mut colors = HashSet::new();
+    let mut extremes = 0;
+    for pixel in img.as_bytes().chunks_exact(channels) {
+        if colors.len() <= LINE_ART_COLORS { colors.insert(pixel); }
+        if pixel.iter().all(|&c| !(16..=239).contains(&c)) { extremes += 1; }
+    }
+    let total = (img.width() * img.height()).max(1) as f32;
+    colors.len() <= LINE_ART_COLORS || extremes as f32 / total >= LINE_ART_EXTREMES
+}
+
+// Re-encodes every stream lopdf can decode and keeps whichever of the old and new encodings is smaller.
+// Exhaustive mode deflates with zopfli and also tries PNG predictors on image samples.
+pub fn recompress_streams(doc: &mut Document, exhaustive: bool) -> usize {
+    let source = &*doc;
+    let replacements: Vec<(ObjectId, Stream)> = source.objects.par_iter().filter_map(|(&id, object)| {
+        recompress_stream(source, object.as_stream().ok()?, exhaustive).map(|s| (id, s))
+    }).collect();
+    let count = replacements.len();
+    for (id, stream) in replacements { doc.objects.insert(id, Object::Stream(stream)); }
+    count
+}
+
+fn recompress_stream(doc: &Document, stream: &Stream, exhaustive: bool) -> Option<Stream> {
+    if !stream.allows_compression { return None; }
+    let filters = stream.filters().unwrap_or_default();
+    if !filters.iter().all(|f| matches!(*f, b"FlateDecode" | b"LZWDecode" | b"ASCII85Decode")) { return None; }
+    let params = match stream.dict.get(b"DecodeParms") {
+        Err(_) => None,
+        Ok(_) if filters.is_empty() => None,
+        Ok(Object::Dictionary(params)) if filters.len() == 1 => Some(params),
+        Ok(_) => return None,
+    };
+    // Predicted data is re-deflated as it is, with the same parameters.
+    let predictor = params.and_then(|p| p.get(b"Predictor").and_then(Object::as_i64).ok()).filter(|&p| p > 1);
+    let data = if filters.is_empty() {
+        stream.content.clone()
+    } else if predictor.is_some() {
+        predicted_content(stream)?
+    } else {
+        stream.decompressed_content().ok()?
+    };
+    let mut dict = stream.dict.clone();
+    dict.remove(b"Filter");
+    dict.remove(b"DecodeParms");
+
+    // Bytes to deflate, with their predictor parameters.
+    let mut inputs = Vec::new();
+    let mut candidates = Vec::new();
+    if let (Some(_), Some(params)) = (predictor, params) {
+        let params: Dictionary = params.iter().filter(|(k, _)| k.as_slice() != b"EarlyChange").map(|(k, v)| (k.clone(), v.clone())).collect();
+        inputs.push((data, Some(params)));
+    } else {
+        if exhaustive && let Some((row_len, bpp, params)) = image_rows(doc, &stream.dict, data.len()) {
+            inputs.push((png_predict(&data, row_len, bpp), Some(params)));
+        }
+        candidates.push(Stream::new(dict.clone(), data.clone()));
+        inputs.push((data, None));
+    }
+    let mut best: Option<(Stream, Vec<u8>, Option<Dictionary>)> = None;
+    for (input, params) in inputs {
+        let Some(deflated) = deflate_stream(&dict, &input, params.clone(), false) else { continue };
+        if best.as_ref().is_none_or(|(b, _, _)| stream_size(&deflated) < stream_size(b)) { best = Some((deflated, input, params)); }
+    }
+    if let Some((deflated, input, params)) = best {
+        // Zopfli is slow, so it only re-deflates the input that wins at the regular level.
+        if exhaustive { candidates.extend(deflate_stream(&dict, &input, params, true)); }
+        candidates.push(deflated);
+    }
+    let best = candidates.into_iter().min_by_key(stream_size)?;
+    (stream_size(&best) < stream_size(stream)).then_some(best)
+}
+
+fn deflate_stream(dict: &Dictionary, data: &[u8], params: Option<Dictionary>, exhaustive: bool) -> Option<Stream> {
+    let mut deflated = Stream::new(dict.clone(), data.to_vec());
+    if exhaustive {
+        let mut content = Vec::new();
+        zopfli::compress(zopfli_options(data.len())?, zopfli::Format::Zlib, data, &mut content).ok()?;
+        deflated.dict.set("Filter", "FlateDecode");
+        deflated.set_content(content);
+    } else {
+        deflated.compress().ok()?;
+        if !deflated.is_compressed() { return None; }
+    }
+    if let Some(params) = params { deflated.dict.set("DecodeParms", params); }
+    Some(deflated)
+}
+
+// Zopfli manages well under a megabyte per second, and past the first iterations rarely gains more than a few
+// bytes, so larger streams get fewer iterations.
+fn zopfli_options(len: usize) -> Option<zopfli::Options> {
+    let iterations = if len < 64 * 1024 { 15 } else if len < ZOPFLI_LIMIT { 5 } else { return None };
+    Some(zopfli::Options { iteration_count: NonZeroU64::new(iterations).unwrap(), ..Default::default() })
+}
+
+// Row length, bytes per pixel and predictor parameters for 8 and 16 bit samples that exactly fill the image.
+fn image_rows(doc: &Document, dict: &Dictionary, len: usize) -> Option<(usize, usize, Dictionary)> {
+    if dict.get(b"Subtype").and_then(Object::as_name).ok()? != b"Image" { return None; }
+    let bits = dict.get(b"BitsPerComponent").and_then(Object::as_i64).ok().filter(|&b| b == 8 || b == 16)?;
+    let colors = color_channels(doc, dict.get(b"ColorSpace").ok()?)?;
+    let width = dict.get(b"Width").and_then(Object::as_i64).ok().filter(|&w| w > 0)?;
+    let height = dict.get(b"Height").and_then(Object::as_i64).ok().filter(|&h| h > 0)?;
+    let row_len = (width * colors * bits / 8) as usize;
+    if row_len * height as usize != len { return None; }
+    let params = dictionary! { "Predictor" => 15, "Colors" => colors, "BitsPerComponent" => bits, "Columns" => width };
+    Some((row_len, (colors * bits / 8) as usize, params))
+}
+
+// Samples per pixel for the color spaces an image may use.
+fn color_channels(doc: &Document, color_space: &Object) -> Option<i64> {
+    match doc.dereference(color_space).ok()?.1 {
+        Object::Name(name) => match name.as_slice() {
+            b"DeviceGray" => Some(1),
+            b"DeviceRGB" => Some(3),
+            b"DeviceCMYK" => Some(4),
+            _ => None,
+        },
+        Object::Array(items) => match items.first()?.as_name().ok()? {
+            b"ICCBased" => doc.dereference(items.get(1)?).ok()?.1.as_stream().ok()?.dict.get(b"N").and_then(Object::as_i64).ok(),
+            b"Indexed" | b"Separation" | b"CalGray" => Some(1),
+            b"CalRGB" | b"Lab" => Some(3),
+            b"DeviceN" => Some(doc.dereference(items.get(1)?).ok()?.1.as_array().ok()?.len() as i64),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// PNG predictor 15: each row gets the filter with the smallest sum of absolute residuals. Average is left
+// out because lopdf decodes it incorrectly.
+fn png_predict(data: &[u8], row_len: usize, bpp: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / row_len);
+    let mut above = vec![0; row_len];
+    for row in data.chunks_exact(row_len) {
+        let best = [0, 1, 2, 4].into_iter().map(|filter| png_filter(filter, row, &above, bpp))
+            .min_by_key(|f| f[1..].iter().map(|&b| (b as i8).unsigned_abs() as u64).sum::<u64>()).unwrap();
+        out.extend(best);
+        above.copy_from_slice(row);
+    }
+    out
+}
+
+fn png_filter(filter: u8, row: &[u8], above: &[u8], bpp: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(row.len() + 1);
+    out.push(filter);
+    for (i, &x) in row.iter().enumerate() {
+        let (a, b, c) = if i >= bpp { (row[i - bpp], above[i], above[i - bpp]) } else { (0, above[i], 0) };
+        let predicted = match filter {
+            0 => 0,
+            1 => a,
+            2 => b,
+            _ => paeth(a, b, c),
+        };
+        out.push(x.wrapping_sub(predicted));
+    }
+    out
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+}
+
+// Lowest effective resolution each image XObject is drawn at across all pages.
+pub fn image_resolutions(doc: &Document) -> BTreeMap<ObjectId, f32> {
+    let mut resolutions = BTreeMap::new();
+    for page_id in doc.page_iter() {
+        visit_page(doc, page_id, false, &mut |op, state| {
+            if op.operator != "Do" { return; }
+            let Some(name) = op.operands.first().and_then(|o| o.as_name().ok()) else { return };
+            let Some((id, stream)) = xobject(doc, state.resources, name) else { return };
+            if stream.dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Image") { return; }
+            let width = stream.dict.get(b"Width").and_then(Object::as_float).unwrap_or(0.0);
+            let height = stream.dict.get(b"Height").and_then(Object::as_float).unwrap_or(0.0);
+            let ctm = state.ctm;
+            let (w_pts, h_pts) = (ctm[0].hypot(ctm[1]), ctm[2].hypot(ctm[3]));
+            if w_pts <= 0.0 || h_pts <= 0.0 { return; }
+            let dpi = (width * 72.0 / w_pts).min(height * 72.0 / h_pts);
+            resolutions.entry(id).and_modify(|d: &mut f32| *d = d.min(dpi)).or_insert(dpi);
+        });
+    }
+    resolutions
+}
+
+#[derive(Clone, Copy)]
+pub struct GraphicsState<'a> {
+    pub ctm: Matrix,
+    pub resources: Option<&'a Dictionary>,
+    pub font: Option<ObjectId>,
+}
+
+// Calls `visit` for every operator drawn on a page, descending into form XObjects and tiling patterns,
+// and optionally into annotation appearance streams.
+pub fn visit_page<'a, F: FnMut(&Operation, &GraphicsState<'a>)>(doc: &'a Document, page_id: ObjectId, annotations: bool, visit: &mut F) {
+    let resources = page_resources(doc, page_id);
+    let state = GraphicsState { ctm: IDENTITY, resources, font: None };
+    if let Some(content) = page_content(doc, page_id) { visit_content(doc, &content, state, 0, visit); }
+    if !annotations { return; }
+    let Ok(annots) = doc.get_dictionary(page_id).and_then(|p| p.get(b"Annots")) else { return };
+    let Ok((_, Object::Array(annots))) = doc.dereference(annots) else { return };
+    for annot in annots {
+        let Some(appearances) = resolve_dict(doc, annot).and_then(|a| a.get(b"AP").ok()).and_then(|ap| resolve_dict(doc, ap)) else { continue };
+        for (_, appearance) in appearances.iter() {
+            let streams: Vec<&Stream> = match doc.dereference(appearance) {
+                Ok((_, Object::Stream(stream))) => vec![stream],
+                Ok((_, Object::Dictionary(states))) => states.iter().filter_map(|(_, s)| doc.dereference(s).ok()?.1.as_stream().ok()).collect(),
+                _ => continue,
+            };
+            for stream in streams { visit_form(doc, stream, state, 0, visit); }
+        }
+    }
+}
+
+pub fn visit_content<'a, F: FnMut(&Operation, &GraphicsState<'a>)>(doc: &'a Document, content: &[u8], state: GraphicsState<'a>, depth: u32, visit: &mut F) {
+    let Ok(content) = Content::decode(content) else { return };
+    let mut state = state;
+    let mut saved = Vec::new();
+    for op in &content.operations {
+        visit(op, &state);
+        match op.operator.as_str() {
+            "q" => saved.push(state),
+            "Q" => if let Some(s) = saved.pop() { state = s },
+            "cm" => if let Some(m) = to_matrix(&op.operands) { state.ctm = multiply(&m, &state.ctm) },
+            "Tf" => {
+                let name = op.operands.first().and_then(|o| o.as_name().ok());
+                state.font = name.and_then(|name| resource_id(doc, state.resources, b"Font", name));
+            }
+            "Do" => {
+                let Some(name) = op.operands.first().and_then(|o| o.as_name().ok()) else { continue };
+                let Some((_, stream)) = xobject(doc, state.resources, name) else { continue };
+                if stream.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Form") {
+                    visit_form(doc, stream, state, depth, visit);
+                }
+            }
+            "scn" | "SCN" => {
+                let Some(name) = op.operands.last().and_then(|o| o.as_name().ok()) else { continue };
+                let Some(id) = resource_id(doc, state.resources, b"Pattern", name) else { continue };
+                let Ok(pattern) = doc.get_object(id).and_then(Object::as_stream) else { continue };
+                // Tiling patterns are positioned relative to the page, not the current transformation.
+                visit_form(doc, pattern, GraphicsState { ctm: IDENTITY, ..state }, depth, visit);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn visit_form<'a, F: FnMut(&Operation, &GraphicsState<'a>)>(doc: &'a Document, stream: &'a Stream, state: GraphicsState<'a>, depth: u32, visit: &mut F) {
+    if depth >= 8 { return; }
+    let matrix = stream.dict.get(b"Matrix").and_then(Object::as_array).ok().and_then(|m| to_matrix(m)).unwrap_or(IDENTITY);
+    let resources = stream.dict.get(b"Resources").ok().and_then(|r| resolve_dict(doc, r)).or(state.resources);
+    let Ok(content) = stream.get_plain_content() else { return };
+    visit_content(doc, &content, GraphicsState { ctm: multiply(&matrix, &state.ctm), resources, ..state }, depth + 1, visit);
+}
+
+// Resource categories whose entries content streams refer to by name.
+const NAMED_RESOURCES: [&[u8]; 7] = [b"Font", b"XObject", b"ExtGState", b"ColorSpace", b"Pattern", b"Shading", b"Properties"];
+
+type ResourceNames = BTreeMap<&'static [u8], HashSet<Vec<u8>>>;
+
+// Rewrites the resources of every page and form XObject to the entries its content names, so pruning can
+// reclaim whatever a shared resource dictionary listed for other pages. Returns the number rewritten.
+pub fn strip_unused_resources(doc: &mut Document) -> usize {
+    let mut rewrites = Vec::new();
+    for page_id in doc.page_iter() {
+        if let Some(resources) = used_page_resources(doc, page_id) { rewrites.push((page_id, resources)); }
+    }
+    for (&id, object) in &doc.objects {
+        let Object::Stream(stream) = object else { continue };
+        if stream.dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Form") { continue; }
+        let Some(resources) = stream.dict.get(b"Resources").ok().and_then(|r| resolve_dict(doc, r)) else { continue };
+        let Ok(content) = stream.get_plain_content() else { continue };
+        let mut used = ResourceNames::new();
+        if !resource_names(doc, &content, resources, 0, &mut used) { continue; }
+        rewrites.push((id, filter_resources(doc, resources, &used)));
+    }
+    let count = rewrites.len();
+    for (id, resources) in rewrites {
+        match doc.objects.get_mut(&id) {
+            Some(Object::Dictionary(page)) => page.set("Resources", resources),
+            Some(Object::Stream(form)) => form.dict.set("Resources", resources),
+            _ => {}
+        }
+    }
+    count
+}
+
+// The page's resources, inherited ones included, cut down to what its content and appearance streams name.
+// None if some content could not be parsed, in which case everything has to stay.
+pub fn used_page_resources(doc: &Document, page_id: ObjectId) -> Option<Dictionary> {
+    let resources = page_resources(doc, page_id)?;
+    let mut used = ResourceNames::new();
+    if !resource_names(doc, &page_content(doc, page_id)?, resources, 0, &mut used) { return None; }
+    // Appearance streams without resources of their own fall back to the page's.
+    let annots = doc.get_dictionary(page_id).ok()?.get(b"Annots").ok().and_then(|a| doc.dereference(a).ok());
+    if let Some((_, Object::Array(annots))) = annots {
+        for annot in annots {
+            let Some(appearances) = resolve_dict(doc, annot).and_then(|a| a.get(b"AP").ok()).and_then(|ap| resolve_dict(doc, ap)) else { continue };
+            for (_, appearance) in appearances.iter() {
+                let streams: Vec<&Stream> = match doc.dereference(appearance) {
+                    Ok((_, Object::Stream(stream))) => vec![stream],
+                    Ok((_, Object::Dictionary(states))) => states.iter().filter_map(|(_, s)| doc.dereference(s).ok()?.1.as_stream().ok()).collect(),
+                    _ => continue,
+                };
+                for stream in streams.into_iter().filter(|s| !s.dict.has(b"Resources")) {
+                    if !resource_names(doc, &stream.get_plain_content().ok()?, resources, 1, &mut used) { return None; }
+                }
+            }
+        }
+    }
+    Some(filter_resources(doc, resources, &used))
+}
+
+// Collects the names a content stream uses from `resources`, following form XObjects and Type 3 glyphs
+// that have no resources of their own. Returns false if any of that content could not be parsed.
+fn resource_names(doc: &Document, content: &[u8], resources: &Dictionary, depth: u32, used: &mut ResourceNames) -> bool {
+    if depth >= 8 { return false; }
+    let Some(content) = decode_whole(content) else { return false };
+    for op in &content.operations {
+        let name = |index: usize| op.operands.get(index).and_then(|o| o.as_name().ok());
+        let (category, name) = match op.operator.as_str() {
+            "Tf" => (&b"Font"[..], name(0)),
+            "Do" => (&b"XObject"[..], name(0)),
+            "gs" => (&b"ExtGState"[..], name(0)),
+            "cs" | "CS" => (&b"ColorSpace"[..], name(0)),
+            "scn" | "SCN" => (&b"Pattern"[..], op.operands.last().and_then(|o| o.as_name().ok())),
+            "sh" => (&b"Shading"[..], name(0)),
+            "BDC" | "DP" => (&b"Properties"[..], name(1)),
+            "BI" => {
+                let dict = op.operands.first().and_then(|o| o.as_stream().ok()).map(|s| &s.dict);
+                (&b"ColorSpace"[..], dict.and_then(|d| d.get(b"CS").or_else(|_| d.get(b"ColorSpace")).and_then(Object::as_name).ok()))
+            }
+            _ => continue,
+        };
+        let Some(name) = name else { continue };
+        if !used.entry(category).or_default().insert(name.to_vec()) { continue; }
+        let Some(id) = resource_id(doc, Some(resources), category, name) else { continue };
+        let Ok(object) = doc.get_object(id) else { continue };
+        let inherits = match object {
+            Object::Stream(form) => !form.dict.has(b"Resources") && form.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Form"),
+            Object::Dictionary(font) => !font.has(b"Resources") && font.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Type3"),
+            _ => false,
+        };
+        if !inherits { continue; }
+        let streams: Vec<&Stream> = match object {
+            Object::Stream(form) => vec![form],
+            Object::Dictionary(font) => font.get(b"CharProcs").ok().and_then(|p| resolve_dict(doc, p)).into_iter()
+                .flat_map(|procs| procs.iter().filter_map(|(_, p)| doc.dereference(p).ok()?.1.as_stream().ok()))
+                .collect(),
+            _ => Vec::new(),
+        };
+        for stream in streams {
+            let Ok(inner) = stream.get_plain_content() else { return false };
+            if !resource_names(doc, &inner, resources, depth + 1, used) { return false; }
+        }
+    }
+    true
+}
+
+// lopdf stops at the first token it cannot read and returns the operations before it, so a marker operator is
+// appended that only comes back last when everything before it was read.
+fn decode_whole(content: &[u8]) -> Option<Content> {
+    let mut marked = content.to_vec();
+    marked.extend(b"\nrustypdfEnd\n");
+    let mut content = Content::decode(&marked).ok()?;
+    (content.operations.pop()?.operator == "rustypdfEnd").then_some(content)
+}
+
+fn filter_resources(doc: &Document, resources: &Dictionary, used: &ResourceNames) -> Dictionary {
+    let mut filtered = Dictionary::new();
+    for (category, entries) in resources.iter() {
+        let Some(&named) = NAMED_RESOURCES.iter().find(|c| **c == category.as_slice()) else {
+            filtered.set(category.clone(), entries.clone());
+            continue;
+        };
+        let (Some(names), Some(entries)) = (used.get(named), resolve_dict(doc, entries)) else { continue };
+        let kept: Dictionary = entries.iter().filter(|(name, _)| names.contains(*name)).map(|(n, v)| (n.clone(), v.clone())).collect();
+        if !kept.is_empty() { filtered.set(category.clone(), kept); }
+    }
+    filtered
+}
+
+// Page content with each stream on its own line, so operators at stream boundaries are not run together.
+pub fn page_content(doc: &Document, page_id: ObjectId) -> Option<Vec<u8>> {
+    let mut content = Vec::new();
+    for id in doc.get_page_contents(page_id) {
+        content.extend(doc.get_object(id).and_then(Object::as_stream).ok()?.get_plain_content().ok()?);
+        content.push(b'\n');
+    }
+    Some(content)
+}
+
+pub fn multiply(m: &Matrix, n: &Matrix) -> Matrix {
+    [
+        m[0] * n[0] + m[1] * n[2], m[0] * n[1] + m[1] * n[3],
+        m[2] * n[0] + m[3] * n[2], m[2] * n[1] + m[3] * n[3],
+        m[4] * n[0] + m[5] * n[2] + n[4], m[4] * n[1] + m[5] * n[3] + n[5],
+    ]
+}
+
+pub fn to_matrix(operands: &[Object]) -> Option<Matrix> {
+    if operands.len() != 6 { return None; }
+    let mut m = IDENTITY;
+    for (slot, value) in m.iter_mut().zip(operands) { *slot = value.as_float().ok()?; }
+    Some(m)
+}
+
+pub fn page_resources(doc: &Document, page_id: ObjectId) -> Option<&Dictionary> {
+    let mut node = doc.get_dictionary(page_id).ok()?;
+    for _ in 0..64 {
+        if let Ok(resources) = node.get(b"Resources") { return resolve_dict(doc, resources); }
+        node = doc.get_dictionary(node.get(b"Parent").and_then(Object::as_reference).ok()?).ok()?;
+    }
+    None
+}
+
+pub fn resolve_dict<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Dictionary> {
+    doc.dereference(object).ok()?.1.as_dict().ok()
+}
+
+pub fn resource_id(doc: &Document, resources: Option<&Dictionary>, category: &[u8], name: &[u8]) -> Option<ObjectId> {
+    resolve_dict(doc, resources?.get(category).ok()?)?.get(name).and_then(Object::as_reference).ok()
+}
+
+pub fn xobject<'a>(doc: &'a Document, resources: Option<&'a Dictionary>, name: &[u8]) -> Option<(ObjectId, &'a Stream)> {
+    let id = resource_id(doc, resources, b"XObject", name)?;
+    Some((id, doc.get_object(id).ok()?.as_stream().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::tests::sample_document;
+
+    #[test]
+    fn breakdown_leaves_out_object_and_xref_streams() {
+        let mut doc = sample_document(3);
+        let before = size_breakdown(&doc);
+        doc.add_object(Stream::new(dictionary! { "Type" => "ObjStm", "N" => 2, "First" => 8 }, vec![b' '; 500]));
+        doc.add_object(Stream::new(dictionary! { "Type" => "XRef", "Size" => 20 }, vec![0; 300]));
+        let after = size_breakdown(&doc);
+        assert_eq!(before.categories(), after.categories());
+        assert!(before.content > 0 && before.fonts > 0);
+    }
+
+    // A one-page document drawing each image at the given size in points, named Im1, Im2, ...
+    fn image_document(images: Vec<(Stream, f32, f32)>) -> (Document, Vec<ObjectId>) {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let mut xobjects = Dictionary::new();
+        let mut content = String::new();
+        let mut ids = Vec::new();
+        for (i, (image, width, height)) in images.into_iter().enumerate() {
+            let id = doc.add_object(image);
+            xobjects.set(format!("Im{}", i + 1), id);
+            content.push_str(&format!("q {} 0 0 {} 0 0 cm /Im{} Do Q\n", width, height, i + 1));
+            ids.push(id);
+        }
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.into_bytes()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page", "Parent" => pages_id, "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Contents" => content_id, "Resources" => dictionary! { "XObject" => xobjects },
+        });
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => vec![page_id.into()], "Count" => 1 }));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        (doc, ids)
+    }
+
+    // Uncompressed samples, so any re-encoding comes out smaller.
+    fn raw_image(img: &DynamicImage) -> Stream {
+        let color_space = if img.color().channel_count() == 1 { "DeviceGray" } else { "DeviceRGB" };
+        Stream::new(dictionary! {
+            "Type" => "XObject", "Subtype" => "Image", "Width" => img.width() as i64, "Height" => img.height() as i64,
+            "ColorSpace" => color_space, "BitsPerComponent" => 8,
+        }, img.as_bytes().to_vec())
+    }
+
+    fn photo(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(40 + x * 160 / width) as u8, (40 + y * 160 / height) as u8, (60 + (x + y) % 120) as u8])
+        }))
+    }
+
+    fn image_stream(doc: &Document, id: ObjectId) -> &Stream {
+        doc.get_object(id).unwrap().as_stream().unwrap()
+    }
+
+    #[test]
+    fn resolution_comes_from_the_smallest_placement() {
+        let image = raw_image(&photo(300, 150));
+        let (mut doc, ids) = image_document(vec![(image, 72.0, 36.0)]);
+        assert_eq!(image_resolutions(&doc)[&ids[0]], 300.0);
+        // Drawn a second time at twice the size, the image only needs half the resolution.
+        let content_id = doc.get_page_contents(doc.page_iter().next().unwrap())[0];
+        let content = doc.get_object_mut(content_id).unwrap().as_stream_mut().unwrap();
+        content.content.extend(b"q 144 0 0 72 0 0 cm /Im1 Do Q\n");
+        assert_eq!(image_resolutions(&doc)[&ids[0]], 150.0);
+    }
+
+    #[test]
+    fn downsamples_only_above_the_threshold() {
+        let (mut doc, ids) = image_document(vec![(raw_image(&photo(300, 300)), 72.0, 72.0), (raw_image(&photo(300, 300)), 144.0, 144.0)]);
+        let options = CompressOptions { optimize_images: true, ..CompressOptions::default() };
+        assert_eq!(optimize_images(&mut doc, &options), 2);
+        // 300 dpi is over the 225 dpi threshold and comes down to 150; 150 dpi is left at full size.
+        assert_eq!(image_stream(&doc, ids[0]).dict.get(b"Width").unwrap().as_i64().unwrap(), 150);
+        assert_eq!(image_stream(&doc, ids[1]).dict.get(b"Width").unwrap().as_i64().unwrap(), 300);
+    }
+
+    #[test]
+    fn line_art_is_deflated_and_photos_become_jpeg() {
+        let stripes = DynamicImage::ImageRgb8(RgbImage::from_fn(200, 200, |x, _| if x % 8 < 4 { image::Rgb([0, 0, 0]) } else { image::Rgb([255, 255, 255]) }));
+        let tinted = DynamicImage::ImageRgb8(RgbImage::from_fn(200, 200, |x, y| {
+            let v = (30 + (x + y) % 190) as u8;
+            image::Rgb([v, v.saturating_add(3), v.saturating_sub(2)])
+        }));
+        let images = [&stripes, &photo(200, 200), &tinted].map(|img| (raw_image(img), 144.0, 144.0));
+        let (mut doc, ids) = image_document(images.to_vec());
+        optimize_images(&mut doc, &CompressOptions::default());
+        let dict = |i: usize| &image_stream(&doc, ids[i]).dict;
+        assert_eq!(dict(0).get(b"Filter").unwrap().as_name().unwrap(), b"FlateDecode");
+        // Stripes in pure black and white need only one channel.
+        assert_eq!(dict(0).get(b"ColorSpace").unwrap().as_name().unwrap(), b"DeviceGray");
+        assert_eq!(dict(1).get(b"Filter").unwrap().as_name().unwrap(), b"DCTDecode");
+        assert_eq!(dict(1).get(b"ColorSpace").unwrap().as_name().unwrap(), b"DeviceRGB");
+        // Channels within the gray tolerance of each other turn the RGB image into a gray one.
+        assert_eq!(dict(2).get(b"ColorSpace").unwrap().as_name().unwrap(), b"DeviceGray");
+        assert_eq!(decode_image(&doc, image_stream(&doc, ids[2])).unwrap().color(), image::ColorType::L8);
+    }
+
+    // Flate-compressed samples run through a PNG predictor using the given row filter, or the TIFF predictor.
+    fn predicted_image(img: &RgbImage, png_filter: Option<u8>) -> Stream {
+        let (row_len, bpp) = (img.width() as usize * 3, 3);
+        let mut data = Vec::new();
+        let mut above = vec![0u8; row_len];
+        for row in img.as_raw().chunks_exact(row_len) {
+            let left = |i: usize| if i >= bpp { row[i - bpp] } else { 0 };
+            match png_filter {
+                Some(3) => {
+                    data.push(3);
+                    data.extend((0..row_len).map(|i| row[i].wrapping_sub(((left(i) as u16 + above[i] as u16) / 2) as u8)));
+                }
+                Some(filter) => data.extend(super::png_filter(filter, row, &above, bpp)),
+                None => data.extend((0..row_len).map(|i| row[i].wrapping_sub(left(i)))),
+            }
+            above.copy_from_slice(row);
+        }
+        let mut stream = raw_image(&DynamicImage::ImageRgb8(RgbImage::new(img.width(), img.height())));
+        stream.set_plain_content(data);
+        stream.compress().unwrap();
+        let predictor = if png_filter.is_some() { 12 } else { 2 };
+        stream.dict.set("DecodeParms", dictionary! { "Predictor" => predictor, "Colors" => 3, "BitsPerComponent" => 8, "Columns" => img.width() as i64 });
+        stream
+    }
+
+    #[test]
+    fn predicted_images_survive_a_compress_round_trip() {
+        let img = photo(23, 17).to_rgb8();
+        let streams = [Some(3), Some(4), Some(1), None].map(|filter| (predicted_image(&img, filter), 23.0, 17.0));
+        let (doc, ids) = image_document(streams.to_vec());
+        for &id in &ids { assert_eq!(decode_image(&doc, image_stream(&doc, id)).unwrap().to_rgb8(), img); }
+
+        let input = crate::pdf::tests::temp_path("predicted-images.pdf");
+        let output = crate::pdf::tests::temp_path("predicted-images-out.pdf");
+        doc.clone().save(&input).unwrap();
+        let options = CompressOptions { max_deflate: true, object_streams: true, ..CompressOptions::default() };
+        crate::pdf::compress_pdf(&input.clone(), output.clone(), &options, &crate::pdf::OutputOptions::default()).unwrap();
+        let compressed = Document::load(&output).unwrap();
+        let images: Vec<&Stream> = compressed.objects.values().filter_map(|o| o.as_stream().ok())
+            .filter(|s| s.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Image")).collect();
+        assert_eq!(images.len(), 4);
+        for stream in images { assert_eq!(decode_image(&compressed, stream).unwrap().to_rgb8(), img); }
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn rejects_image_sizes_that_overflow_or_lack_samples() {
+        let mut huge = raw_image(&photo(4, 4));
+        huge.dict.set("Width", i64::from(u32::MAX));
+        huge.dict.set("Height", i64::from(u32::MAX));
+        let mut short = raw_image(&photo(4, 4));
+        short.dict.set("Height", 5);
+        let doc = Document::with_version("1.5");
+        assert!(decode_image(&doc, &huge).is_none());
+        assert!(decode_image(&doc, &short).is_none());
+    }
+
+    fn form(content: &str, resources: Option<Dictionary>) -> Stream {
+        let mut dict = dictionary! { "Type" => "XObject", "Subtype" => "Form", "BBox" => vec![0.into(), 0.into(), 100.into(), 100.into()] };
+        if let Some(resources) = resources { dict.set("Resources", resources); }
+        Stream::new(dict, content.as_bytes().to_vec())
+    }
+
+    fn names(doc: &Document, resources: &Dictionary, category: &[u8]) -> Vec<String> {
+        resources.get(category).ok().and_then(|r| resolve_dict(doc, r))
+            .map(|d| d.iter().map(|(name, _)| String::from_utf8_lossy(name).to_string()).collect()).unwrap_or_default()
+    }
+
+    #[test]
+    fn keeps_resources_named_by_nested_forms_glyphs_and_operands() {
+        let mut doc = sample_document(1);
+        let page_id = doc.page_iter().next().unwrap();
+        let font = |doc: &mut Document| doc.add_object(crate::stamp::StandardFont::Helvetica.dictionary());
+        let (f1, f2, f3, f4) = (font(&mut doc), font(&mut doc), font(&mut doc), font(&mut doc));
+        let image = |doc: &mut Document| doc.add_object(raw_image(&photo(2, 2)));
+        let (glyph_image, unused_image) = (image(&mut doc), image(&mut doc));
+        let gs = |doc: &mut Document| doc.add_object(dictionary! { "Type" => "ExtGState", "CA" => 0.5 });
+        let (gs1, gs2) = (gs(&mut doc), gs(&mut doc));
+        let shading = |doc: &mut Document| doc.add_object(dictionary! { "ShadingType" => 2, "ColorSpace" => "DeviceGray",
+            "Coords" => vec![0.into(), 0.into(), 1.into(), 0.into()], "Function" => dictionary! { "FunctionType" => 2, "Domain" => vec![0.into(), 1.into()], "N" => 1 } });
+        let (sh1, sh2) = (shading(&mut doc), shading(&mut doc));
+        let pattern = |doc: &mut Document, shading: ObjectId| doc.add_object(dictionary! { "PatternType" => 2, "Shading" => shading });
+        let (p1, p2) = (pattern(&mut doc, sh1), pattern(&mut doc, sh2));
+
+        // A form with its own resources, drawn only from inside a form that uses the page's.
+        let inner = doc.add_object(form("BT /F3 9 Tf (in) Tj ET", Some(dictionary! { "Font" => dictionary! { "F3" => f3, "F4" => f4 } })));
+        let outer = doc.add_object(form("/GS1 gs /Sh1 sh /Pattern cs /P1 scn 0 0 10 10 re f /Inner Do", None));
+        let glyph = doc.add_object(Stream::new(dictionary! {}, b"0 0 d0 q 10 0 0 10 0 0 cm /Glyph Do Q".to_vec()));
+        let type3 = doc.add_object(dictionary! {
+            "Type" => "Font", "Subtype" => "Type3", "FontMatrix" => vec![0.001.into(), 0.into(), 0.into(), 0.001.into(), 0.into(), 0.into()],
+            "FontBBox" => vec![0.into(), 0.into(), 1000.into(), 1000.into()], "FirstChar" => 97, "LastChar" => 97, "Widths" => vec![1000.into()],
+            "Encoding" => dictionary! { "Differences" => vec![97.into(), "a".into()] }, "CharProcs" => dictionary! { "a" => glyph },
+        });
+        doc.get_dictionary_mut(page_id).unwrap().set("Resources", dictionary! {
+            "Font" => dictionary! { "F1" => f1, "F2" => f2, "T3" => type3 },
+            "XObject" => dictionary! { "Outer" => outer, "Inner" => inner, "Glyph" => glyph_image, "Unused" => unused_image },
+            "ExtGState" => dictionary! { "GS1" => gs1, "GS2" => gs2 },
+            "Shading" => dictionary! { "Sh1" => sh1, "Sh2" => sh2 },
+            "Pattern" => dictionary! { "P1" => p1, "P2" => p2 },
+            "ProcSet" => vec!["PDF".into(), "Text".into()],
+        });
+        let content_id = doc.get_page_contents(page_id)[0];
+        doc.get_object_mut(content_id).unwrap().as_stream_mut().unwrap()
+            .set_plain_content(b"BT /F1 24 Tf 72 700 Td (Page 1) Tj /T3 12 Tf (a) Tj ET /Outer Do".to_vec());
+
+        assert!(strip_unused_resources(&mut doc) >= 2);
+        let resources = page_resources(&doc, page_id).unwrap();
+        assert_eq!(names(&doc, resources, b"Font"), ["F1", "T3"]);
+        assert_eq!(names(&doc, resources, b"XObject"), ["Outer", "Inner", "Glyph"]);
+        assert_eq!(names(&doc, resources, b"ExtGState"), ["GS1"]);
+        assert_eq!(names(&doc, resources, b"Shading"), ["Sh1"]);
+        assert_eq!(names(&doc, resources, b"Pattern"), ["P1"]);
+        // Categories that hold no names are left alone.
+        assert!(resources.has(b"ProcSet"));
+        let inner = doc.get_object(inner).unwrap().as_stream().unwrap();
+        assert_eq!(names(&doc, inner.dict.get(b"Resources").unwrap().as_dict().unwrap(), b"Font"), ["F3"]);
+    }
+
+    #[test]
+    fn keeps_every_resource_when_content_cannot_be_read_to_the_end() {
+        // lopdf reads the operators up to the stray parenthesis and stops, which must not count as a full read.
+        let mut doc = sample_document(1);
+        let page_id = doc.page_iter().next().unwrap();
+        let unused = doc.add_object(crate::stamp::StandardFont::Helvetica.dictionary());
+        let mut resources = page_resources(&doc, page_id).unwrap().clone();
+        resources.get_mut(b"Font").unwrap().as_dict_mut().unwrap().set("F9", unused);
+        doc.get_dictionary_mut(page_id).unwrap().set("Resources", resources);
+        let content_id = doc.get_page_contents(page_id)[0];
+        doc.get_object_mut(content_id).unwrap().as_stream_mut().unwrap().set_plain_content(b"BT /F1 12 Tf ) /F9 12 Tf (x) Tj ET".to_vec());
+        strip_unused_resources(&mut doc);
+        assert_eq!(names(&doc, page_resources(&doc, page_id).unwrap(), b"Font"), ["F1", "F9"]);
+    }
+
+    // A document with every kind of metadata strip_metadata knows about.
+    fn document_with_metadata() -> (Document, ObjectId) {
+        let mut doc = sample_document(1);
+        let info = doc.add_object(dictionary! { "Title" => Object::string_literal("Report") });
+        doc.trailer.set("Info", info);
+        let xmp = doc.add_object(Stream::new(dictionary! { "Type" => "Metadata", "Subtype" => "XML" }, b"<x:xmpmeta/>".to_vec()));
+        let catalog_id = doc.trailer.get(b"Root").unwrap().as_reference().unwrap();
+        doc.get_dictionary_mut(catalog_id).unwrap().set("Metadata", xmp);
+        let thumb = doc.add_object(raw_image(&photo(2, 2)));
+        let page_id = doc.page_iter().next().unwrap();
+        let page = doc.get_dictionary_mut(page_id).unwrap();
+        page.set("PieceInfo", dictionary! { "Editor" => dictionary! { "Private" => 1 } });
+        page.set("LastModified", Object::string_literal("D:20260101000000Z"));
+        page.set("Thumb", thumb);
+        (doc, catalog_id)
+    }
+
+    fn metadata_left(doc: &Document, catalog_id: ObjectId) -> [bool; 5] {
+        let page = doc.get_dictionary(doc.page_iter().next().unwrap()).unwrap();
+        [doc.trailer.has(b"Info"), doc.get_dictionary(catalog_id).unwrap().has(b"Metadata"), page.has(b"PieceInfo"), page.has(b"LastModified"), page.has(b"Thumb")]
+    }
+
+    #[test]
+    fn keeping_metadata_changes_nothing() {
+        let (mut doc, catalog_id) = document_with_metadata();
+        strip_metadata(&mut doc, MetadataMode::Keep);
+        assert_eq!(metadata_left(&doc, catalog_id), [true; 5]);
+    }
+
+    #[test]
+    fn stripping_info_only_drops_the_trailer_info() {
+        let (mut doc, catalog_id) = document_with_metadata();
+        strip_metadata(&mut doc, MetadataMode::StripInfo);
+        assert_eq!(metadata_left(&doc, catalog_id), [false, true, true, true, true]);
+        assert!(doc.trailer.has(b"Root") && doc.trailer.has(b"ID"));
+    }
+
+    #[test]
+    fn stripping_everything_drops_xmp_piece_info_and_thumbnails() {
+        let (mut doc, catalog_id) = document_with_metadata();
+        strip_metadata(&mut doc, MetadataMode::StripAll);
+        assert_eq!(metadata_left(&doc, catalog_id), [false; 5]);
+        assert!(doc.trailer.has(b"Root") && doc.trailer.has(b"ID"));
+    }
+
+    #[test]
+    fn png_predictors_round_trip_image_samples() {
+        let doc = Document::with_version("1.5");
+        // A smooth gradient, where the predictors pay off over plain deflate.
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(64, 48, |x, y| image::Rgb([(x * 4) as u8, (y * 5) as u8, ((x + y) * 2) as u8])));
+        let raw = raw_image(&img);
+        let plain = recompress_stream(&doc, &raw, false).unwrap();
+        let predicted = recompress_stream(&doc, &raw, true).unwrap();
+        let params = predicted.dict.get(b"DecodeParms").unwrap().as_dict().unwrap();
+        assert_eq!(params.get(b"Predictor").unwrap().as_i64().unwrap(), 15);
+        assert!(stream_size(&predicted) < stream_size(&plain));
+        assert_eq!(plain_content(&predicted).unwrap(), img.as_bytes());
+        assert_eq!(decode_image(&doc, &predicted).unwrap(), img);
+    }
+
+    #[test]
+    fn keeps_the_smaller_of_the_old_and_new_encoding() {
+        let doc = Document::with_version("1.5");
+        let raw = raw_image(&photo(40, 30));
+        let recompressed = recompress_stream(&doc, &raw, false).unwrap();
+        assert!(stream_size(&recompressed) < stream_size(&raw));
+        // Already deflated as well as it can be, so there is nothing to gain.
+        assert!(recompress_stream(&doc, &recompressed, false).is_none());
+        // Noise does not deflate, so the unfiltered original stays.
+        let mut state = 1u32;
+        let noise: Vec<u8> = (0..4096).map(|_| { state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345); (state >> 16) as u8 }).collect();
+        assert!(recompress_stream(&doc, &Stream::new(dictionary! {}, noise), true).is_none());
+    }
+}
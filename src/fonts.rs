@@ -0,0 +1,1032 @@
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use lopdf::content::Operation;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::collections::btree_map::Entry;
+use ttf_parser::GlyphId;
+use crate::compress::{self, GraphicsState, IDENTITY};
+use crate::pdf;
+
+// Tables a PDF viewer reads from an embedded TrueType program; hinting tables stay so glyphs render the same.
+const TRUETYPE_TABLES: [&[u8; 4]; 14] = [
+    b"OS/2", b"cmap", b"cvt ", b"fpgm", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp", b"post", b"prep", b"vhea", b"vmtx",
+];
+// Unicode values of WinAnsiEncoding codes 0x80-0x9F; the other codes match Latin-1.
+pub const WIN_ANSI_HIGH: [u16; 32] = [
+    0x20AC, 0, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, 0x02C6, 0x2030, 0x0160, 0x2039, 0x0152, 0, 0x017D, 0,
+    0, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014, 0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0, 0x017E, 0x0178,
+];
+// Type 2 charstring that draws nothing, used in place of dropped CFF glyphs.
+const EMPTY_CHARSTRING: &[u8] = &[14];
+// Standard glyph names of the printable ASCII codes in WinAnsiEncoding and MacRomanEncoding. StandardEncoding
+// differs only in using quoteright and quoteleft for 0x27 and 0x60.
+const ASCII_GLYPH_NAMES: [&str; 95] = [
+    "space", "exclam", "quotedbl", "numbersign", "dollar", "percent", "ampersand", "quotesingle", "parenleft", "parenright",
+    "asterisk", "plus", "comma", "hyphen", "period", "slash", "zero", "one", "two", "three", "four", "five", "six", "seven",
+    "eight", "nine", "colon", "semicolon", "less", "equal", "greater", "question", "at", "A", "B", "C", "D", "E", "F", "G",
+    "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z", "bracketleft",
+    "backslash", "bracketright", "asciicircum", "underscore", "grave", "a", "b", "c", "d", "e", "f", "g", "h", "i", "j",
+    "k", "l", "m", "n", "o", "p", "q", "r", "s", "t", "u", "v", "w", "x", "y", "z", "braceleft", "bar", "braceright",
+    "asciitilde",
+];
+
+// How an embedded font program is stored: FontFile2, FontFile3 as bare CFF, or FontFile3 as OpenType, which
+// holds either TrueType outlines or a CFF table.
+#[derive(Clone, Copy, PartialEq)]
+enum Format { TrueType, Cff, OpenType }
+
+struct Program {
+    data: Vec<u8>,
+    format: Format,
+    glyphs: BTreeSet<u16>,
+    fonts: Vec<(ObjectId, BTreeSet<u32>)>,
+    descriptors: BTreeSet<ObjectId>,
+    subsettable: bool,
+}
+
+struct Embedded {
+    file: ObjectId,
+    descriptor: ObjectId,
+    cid_font: Option<ObjectId>,
+    format: Format,
+}
+
+// Cuts every embedded TrueType, CFF and OpenType program down to the glyphs in use. Glyph ids are kept, so
+// content streams and CID mappings stay valid. A font left in the document without being drawn keeps the
+// programs it shares from being subset, so unused resources should be stripped and pruned first. Returns the
+// number of programs subset.
+pub fn subset_fonts(doc: &mut Document) -> usize {
+    let usage = font_usage(doc);
+    let unknown_usage = direct_font_programs(doc);
+
+    // Fonts in the interactive form's resources may be used to fill fields with any text later.
+    let form_fonts: HashSet<ObjectId> = doc.catalog().ok()
+        .and_then(|c| c.get(b"AcroForm").ok()).and_then(|a| compress::resolve_dict(doc, a))
+        .and_then(|a| a.get(b"DR").ok()).and_then(|dr| compress::resolve_dict(doc, dr))
+        .and_then(|dr| dr.get(b"Font").ok()).and_then(|f| compress::resolve_dict(doc, f))
+        .map(|fonts| fonts.iter().filter_map(|(_, f)| f.as_reference().ok()).collect())
+        .unwrap_or_default();
+
+    let mut programs: BTreeMap<ObjectId, Program> = BTreeMap::new();
+    for (&font_id, object) in &doc.objects {
+        let Ok(font) = object.as_dict() else { continue };
+        if !font.has_type(b"Font") { continue; }
+        let Some(embedded) = embedded_program(doc, font) else { continue };
+        let program = match programs.entry(embedded.file) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let Some(data) = doc.get_object(embedded.file).and_then(Object::as_stream).ok().and_then(|s| s.get_plain_content().ok()) else { continue };
+                entry.insert(Program {
+                    data, format: embedded.format, glyphs: BTreeSet::new(), fonts: Vec::new(), descriptors: BTreeSet::new(), subsettable: true,
+                })
+            }
+        };
+        program.descriptors.insert(embedded.descriptor);
+        let codes = usage.get(&font_id).filter(|_| !form_fonts.contains(&font_id));
+        match codes.and_then(|codes| used_glyphs(doc, font, &embedded, &program.data, codes).map(|g| (codes, g))) {
+            Some((codes, glyphs)) => {
+                program.glyphs.extend(glyphs);
+                program.fonts.push((font_id, codes.clone()));
+            }
+            None => program.subsettable = false,
+        }
+    }
+
+    let mut count = 0;
+    for (file_id, program) in programs {
+        if !program.subsettable || program.fonts.is_empty() || unknown_usage.contains(&file_id) { continue; }
+        let subset = match program.format {
+            Format::TrueType => subset_truetype(&program.data, &program.glyphs),
+            Format::Cff => subset_cff(&program.data, &program.glyphs),
+            Format::OpenType => subset_opentype(&program.data, &program.glyphs),
+        };
+        let Some(subset) = subset.filter(|s| s.len() < program.data.len()) else { continue };
+        let Ok(stream) = doc.get_object_mut(file_id).and_then(Object::as_stream_mut) else { continue };
+        stream.dict.remove(b"Filter");
+        stream.dict.remove(b"DecodeParms");
+        if program.format == Format::TrueType { stream.dict.set("Length1", subset.len() as i64); }
+        stream.set_content(subset);
+
+        let tag = subset_tag(&program.glyphs);
+        for (font_id, codes) in &program.fonts { rewrite_font(doc, *font_id, codes, &tag); }
+        for descriptor in &program.descriptors {
+            let Ok(descriptor) = doc.get_dictionary_mut(*descriptor) else { continue };
+            // A CIDSet listing every glyph would no longer match the program.
+            descriptor.remove(b"CIDSet");
+            if let Ok(name) = descriptor.get(b"FontName").and_then(Object::as_name) {
+                let name = tagged(name, &tag);
+                descriptor.set("FontName", Object::Name(name));
+            }
+        }
+        count += 1;
+    }
+    count
+}
+
+// Objects reachable from font dictionaries written directly inside other objects. Their glyph usage is not
+// gathered, so font programs among them must be kept whole.
+fn direct_font_programs(doc: &Document) -> HashSet<ObjectId> {
+    fn find<'a>(object: &'a Object, fonts: &mut Vec<&'a Dictionary>) {
+        let dict = match object {
+            Object::Array(items) => return items.iter().for_each(|item| find(item, fonts)),
+            Object::Dictionary(dict) => dict,
+            _ => return,
+        };
+        if dict.has_type(b"Font") { fonts.push(dict); }
+        for (_, value) in dict.iter() { find(value, fonts); }
+    }
+    let mut fonts = Vec::new();
+    for object in doc.objects.values() {
+        match object {
+            Object::Dictionary(dict) => dict.iter().for_each(|(_, value)| find(value, &mut fonts)),
+            Object::Stream(stream) => stream.dict.iter().for_each(|(_, value)| find(value, &mut fonts)),
+            Object::Array(items) => items.iter().for_each(|item| find(item, &mut fonts)),
+            _ => {}
+        }
+    }
+    let mut pending = Vec::new();
+    for font in fonts { pdf::collect_references(&Object::Dictionary(font.clone()), &mut pending); }
+    let mut reachable = HashSet::new();
+    while let Some(id) = pending.pop() {
+        if !reachable.insert(id) { continue; }
+        if let Ok(object) = doc.get_object(id) { pdf::collect_references(object, &mut pending); }
+    }
+    reachable
+}
+
+// Character codes shown with each font on pages, forms, patterns, annotation appearances and Type 3 glyphs.
+// Fonts selected but never used to show text are present with no codes.
+pub fn font_usage(doc: &Document) -> BTreeMap<ObjectId, BTreeSet<u32>> {
+    let mut usage = BTreeMap::new();
+    let mut composite = HashMap::new();
+    for page_id in doc.page_iter() {
+        compress::visit_page(doc, page_id, true, &mut |op, state| record_text(doc, op, state, &mut usage, &mut composite));
+    }
+    let mut visited = BTreeSet::new();
+    loop {
+        let pending: Vec<ObjectId> = usage.keys().filter(|id| !visited.contains(*id)).copied().collect();
+        if pending.is_empty() { break; }
+        for font_id in pending {
+            visited.insert(font_id);
+            let Ok(font) = doc.get_dictionary(font_id) else { continue };
+            let Some(procs) = font.get(b"CharProcs").ok().and_then(|p| compress::resolve_dict(doc, p)) else { continue };
+            let resources = font.get(b"Resources").ok().and_then(|r| compress::resolve_dict(doc, r));
+            for (_, proc) in procs.iter() {
+                let Ok(content) = doc.dereference(proc).and_then(|(_, p)| p.as_stream()).and_then(|s| s.get_plain_content()) else { continue };
+                let state = GraphicsState { ctm: IDENTITY, resources, font: None };
+                compress::visit_content(doc, &content, state, 0, &mut |op, state| record_text(doc, op, state, &mut usage, &mut composite));
+            }
+        }
+    }
+    usage
+}
+
+fn record_text(doc: &Document, op: &Operation, state: &GraphicsState, usage: &mut BTreeMap<ObjectId, BTreeSet<u32>>, composite: &mut HashMap<ObjectId, bool>) {
+    let strings: Vec<&[u8]> = match op.operator.as_str() {
+        "Tf" => {
+            let name = op.operands.first().and_then(|o| o.as_name().ok());
+            if let Some(font) = name.and_then(|name| compress::resource_id(doc, state.resources, b"Font", name)) {
+                usage.entry(font).or_default();
+            }
+            return;
+        }
+        "Tj" | "'" => op.operands.first().and_then(|o| o.as_str().ok()).into_iter().collect(),
+        "\"" => op.operands.get(2).and_then(|o| o.as_str().ok()).into_iter().collect(),
+        "TJ" => match op.operands.first() {
+            Some(Object::Array(items)) => items.iter().filter_map(|o| o.as_str().ok()).collect(),
+            _ => return,
+        },
+        _ => return,
+    };
+    let Some(font) = state.font else { return };
+    let two_byte = *composite.entry(font).or_insert_with(|| {
+        doc.get_dictionary(font).and_then(|f| f.get(b"Subtype")).and_then(Object::as_name).ok() == Some(b"Type0")
+    });
+    let codes = usage.entry(font).or_default();
+    for text in strings {
+        if two_byte {
+            codes.extend(text.chunks_exact(2).map(|c| u32::from(u16::from_be_bytes([c[0], c[1]]))));
+        } else {
+            codes.extend(text.iter().map(|&c| u32::from(c)));
+        }
+    }
+}
+
+fn embedded_program(doc: &Document, font: &Dictionary) -> Option<Embedded> {
+    let subtype = font.get(b"Subtype").and_then(Object::as_name).ok()?;
+    let (cid_font, dict) = match subtype {
+        b"Type0" => {
+            let encoding = font.get(b"Encoding").and_then(Object::as_name).ok()?;
+            if encoding != b"Identity-H" && encoding != b"Identity-V" { return None; }
+            let descendants = doc.dereference(font.get(b"DescendantFonts").ok()?).ok()?.1.as_array().ok()?;
+            let id = descendants.first()?.as_reference().ok()?;
+            (Some(id), doc.get_dictionary(id).ok()?)
+        }
+        b"TrueType" | b"Type1" | b"MMType1" => (None, font),
+        _ => return None,
+    };
+    let descriptor = dict.get(b"FontDescriptor").and_then(Object::as_reference).ok()?;
+    let descriptor_dict = doc.get_dictionary(descriptor).ok()?;
+    if let Ok(file) = descriptor_dict.get(b"FontFile2").and_then(Object::as_reference) {
+        let truetype = matches!(dict.get(b"Subtype").and_then(Object::as_name).ok()?, b"TrueType" | b"CIDFontType2");
+        return truetype.then_some(Embedded { file, descriptor, cid_font, format: Format::TrueType });
+    }
+    let file = descriptor_dict.get(b"FontFile3").and_then(Object::as_reference).ok()?;
+    let stream = doc.get_object(file).and_then(Object::as_stream).ok()?;
+    let format = match (dict.get(b"Subtype").and_then(Object::as_name).ok()?, stream.dict.get(b"Subtype").and_then(Object::as_name).ok()?) {
+        (b"CIDFontType0", b"CIDFontType0C") | (b"Type1" | b"MMType1", b"Type1C") => Format::Cff,
+        (_, b"OpenType") => Format::OpenType,
+        _ => return None,
+    };
+    Some(Embedded { file, descriptor, cid_font, format })
+}
+
+// Glyph ids the codes shown with a font select in its embedded program, or None if that cannot be worked out reliably.
+fn used_glyphs(doc: &Document, font: &Dictionary, embedded: &Embedded, program: &[u8], codes: &BTreeSet<u32>) -> Option<BTreeSet<u16>> {
+    let cff = match embedded.format {
+        Format::TrueType => None,
+        Format::Cff => Some(program),
+        Format::OpenType => ttf_parser::RawFace::parse(program, 0).ok()?.table(ttf_parser::Tag::from_bytes(b"CFF ")),
+    };
+    if let Some(cff) = cff {
+        let table = ttf_parser::cff::Table::parse(cff)?;
+        if embedded.cid_font.is_none() { return cff_glyphs(doc, font, &table, codes); }
+        // CID-keyed programs map glyphs to CIDs through their charset, others use CIDs as glyph ids.
+        let by_cid: HashMap<u16, u16> = (0..table.number_of_glyphs())
+            .filter_map(|gid| table.glyph_cid(GlyphId(gid)).map(|cid| (cid, gid)))
+            .collect();
+        return Some(codes.iter().filter_map(|&cid| {
+            if by_cid.is_empty() { u16::try_from(cid).ok() } else { by_cid.get(&(cid as u16)).copied() }
+        }).collect());
+    }
+    if let Some(cid_font) = embedded.cid_font {
+        let cid_font = doc.get_dictionary(cid_font).ok()?;
+        return match cid_font.get(b"CIDToGIDMap").ok().map(|m| doc.dereference(m)) {
+            Some(Ok((_, Object::Stream(map)))) => {
+                let map = map.get_plain_content().ok()?;
+                Some(codes.iter().filter_map(|&cid| {
+                    let pair = map.get(cid as usize * 2..cid as usize * 2 + 2)?;
+                    Some(u16::from_be_bytes([pair[0], pair[1]]))
+                }).collect())
+            }
+            _ => Some(codes.iter().map(|&cid| cid as u16).collect()),
+        };
+    }
+    truetype_glyphs(doc, font, program, codes)
+}
+
+// Simple TrueType fonts pick glyphs through whichever cmap subtable the viewer prefers, so every candidate is kept.
+fn truetype_glyphs(doc: &Document, font: &Dictionary, program: &[u8], codes: &BTreeSet<u32>) -> Option<BTreeSet<u16>> {
+    let face = ttf_parser::Face::parse(program, 0).ok()?;
+    let encoding = font.get(b"Encoding").ok().and_then(|e| doc.dereference(e).ok()).map(|(_, e)| e);
+    let base_encoding = match encoding {
+        Some(Object::Name(name)) => Some(name.as_slice()),
+        Some(Object::Dictionary(dict)) => dict.get(b"BaseEncoding").and_then(Object::as_name).ok(),
+        _ => None,
+    };
+    let differences = encoding.and_then(|e| e.as_dict().ok()).map(|dict| differences(doc, dict)).unwrap_or_default();
+    let subtables: Vec<_> = face.tables().cmap.map(|cmap| cmap.subtables.into_iter().collect()).unwrap_or_default();
+
+    let mut glyphs = BTreeSet::new();
+    for &code in codes {
+        let mut unicode = None;
+        if let Some(name) = differences.get(&code) {
+            let by_name = face.glyph_index_by_name(&String::from_utf8_lossy(name));
+            glyphs.extend(by_name.map(|g| g.0));
+            unicode = glyph_name_unicode(name);
+            if unicode.is_none() && by_name.is_none() { return None; }
+        } else if code < 0x80 {
+            unicode = Some(code);
+        } else if base_encoding == Some(b"WinAnsiEncoding") {
+            unicode = Some(if code < 0xA0 { u32::from(WIN_ANSI_HIGH[code as usize - 0x80]) } else { code });
+        } else if subtables.iter().any(|s| s.is_unicode()) {
+            return None;
+        }
+        if subtables.is_empty() { glyphs.insert(code as u16); }
+        for subtable in &subtables {
+            let candidates: &[u32] = match (subtable.platform_id, subtable.encoding_id) {
+                (ttf_parser::PlatformId::Windows, 0) => &[code, 0xF000 + code, 0xF100 + code, 0xF200 + code],
+                (ttf_parser::PlatformId::Macintosh, 0) => &[code],
+                _ if subtable.is_unicode() => match unicode { Some(ref u) => std::slice::from_ref(u), None => &[] },
+                _ => &[],
+            };
+            glyphs.extend(candidates.iter().filter_map(|&c| subtable.glyph_index(c)).map(|g| g.0));
+        }
+    }
+    Some(glyphs)
+}
+
+// Simple CFF fonts pick glyphs by name: from the encoding's differences, from a standard encoding it names, or
+// else from the program's own encoding.
+fn cff_glyphs(doc: &Document, font: &Dictionary, table: &ttf_parser::cff::Table, codes: &BTreeSet<u32>) -> Option<BTreeSet<u16>> {
+    let encoding = font.get(b"Encoding").ok().and_then(|e| doc.dereference(e).ok()).map(|(_, e)| e);
+    let base_encoding = match encoding {
+        Some(Object::Name(name)) => Some(name.as_slice()),
+        Some(Object::Dictionary(dict)) => dict.get(b"BaseEncoding").and_then(Object::as_name).ok(),
+        _ => None,
+    };
+    let differences = encoding.and_then(|e| e.as_dict().ok()).map(|dict| differences(doc, dict)).unwrap_or_default();
+    let mut glyphs = BTreeSet::new();
+    for &code in codes {
+        let glyph = if let Some(name) = differences.get(&code) {
+            table.glyph_index_by_name(std::str::from_utf8(name).ok()?)
+        } else if let Some(base_encoding) = base_encoding {
+            // Codes outside printable ASCII would need the whole encoding's names.
+            let mut name = *ASCII_GLYPH_NAMES.get((code as usize).checked_sub(0x20)?)?;
+            if base_encoding == b"StandardEncoding" && code == 0x27 { name = "quoteright"; }
+            if base_encoding == b"StandardEncoding" && code == 0x60 { name = "quoteleft"; }
+            table.glyph_index_by_name(name)
+        } else {
+            table.glyph_index(u8::try_from(code).ok()?)
+        };
+        // A code without a glyph shows .notdef, which is always kept.
+        glyphs.extend(glyph.map(|g| g.0));
+    }
+    Some(glyphs)
+}
+
+fn differences(doc: &Document, encoding: &Dictionary) -> BTreeMap<u32, Vec<u8>> {
+    let mut names = BTreeMap::new();
+    let Some(Object::Array(items)) = encoding.get(b"Differences").ok().and_then(|d| doc.dereference(d).ok()).map(|(_, d)| d) else { return names };
+    let mut code = 0;
+    for item in items {
+        match item {
+            Object::Integer(n) => code = *n as u32,
+            Object::Name(name) => {
+                names.insert(code, name.clone());
+                code += 1;
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+// Unicode value of glyph names that spell it out ("uni00E9", "u1F600") or are a single ASCII character.
+fn glyph_name_unicode(name: &[u8]) -> Option<u32> {
+    let name = std::str::from_utf8(name).ok()?;
+    if name.len() == 1 && name.as_bytes()[0].is_ascii_alphanumeric() { return Some(name.as_bytes()[0] as u32); }
+    let hex = name.strip_prefix("uni").filter(|h| h.len() == 4).or_else(|| name.strip_prefix('u').filter(|h| (4..=6).contains(&h.len())))?;
+    u32::from_str_radix(hex, 16).ok()
+}
+
+// Trims widths and encoding differences to the codes still in use and tags the font name as a subset.
+fn rewrite_font(doc: &mut Document, font_id: ObjectId, codes: &BTreeSet<u32>, tag: &str) {
+    let Ok(font) = doc.get_dictionary(font_id) else { return };
+    let resolve = |object: Option<&Object>| object.and_then(|o| doc.dereference(o).ok()).map(|(_, o)| o.clone());
+    if font.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Type0") {
+        let Some(cid_font_id) = resolve(font.get(b"DescendantFonts").ok())
+            .and_then(|d| d.as_array().ok().and_then(|d| d.first()).and_then(|f| f.as_reference().ok())) else { return };
+        let Ok(cid_font) = doc.get_dictionary(cid_font_id) else { return };
+        let widths = match resolve(cid_font.get(b"W").ok()) {
+            Some(Object::Array(w)) => Some(cid_widths(doc, &w, codes)),
+            _ => None,
+        };
+        if let Ok(cid_font) = doc.get_dictionary_mut(cid_font_id) {
+            if let Some(widths) = widths { cid_font.set("W", widths); }
+            tag_base_font(cid_font, tag);
+        }
+    } else {
+        let (Some(&first), Some(&last)) = (codes.first(), codes.last()) else { return };
+        let old_first = font.get(b"FirstChar").and_then(Object::as_i64).unwrap_or(0) as u32;
+        let widths = match resolve(font.get(b"Widths").ok()) {
+            Some(Object::Array(w)) => Some((first..=last).map(|code| {
+                let width = code.checked_sub(old_first).and_then(|i| w.get(i as usize)).filter(|_| codes.contains(&code));
+                width.and_then(|w| doc.dereference(w).ok()).map(|(_, w)| w.clone()).unwrap_or(Object::Integer(0))
+            }).collect::<Vec<Object>>()),
+            _ => None,
+        };
+        let encoding = match resolve(font.get(b"Encoding").ok()) {
+            Some(Object::Dictionary(mut encoding)) => {
+                let names = differences(doc, &encoding);
+                let mut trimmed = Vec::new();
+                let mut next = None;
+                for (&code, name) in names.iter().filter(|(code, _)| codes.contains(code)) {
+                    if next != Some(code) { trimmed.push(Object::Integer(code as i64)); }
+                    trimmed.push(Object::Name(name.clone()));
+                    next = Some(code + 1);
+                }
+                encoding.set("Differences", trimmed);
+                Some(encoding)
+            }
+            _ => None,
+        };
+        let Ok(font) = doc.get_dictionary_mut(font_id) else { return };
+        if let Some(widths) = widths {
+            font.set("FirstChar", first as i64);
+            font.set("LastChar", last as i64);
+            font.set("Widths", widths);
+        }
+        if let Some(encoding) = encoding { font.set("Encoding", encoding); }
+    }
+    if let Ok(font) = doc.get_dictionary_mut(font_id) { tag_base_font(font, tag); }
+}
+
+// Rebuilds a CID font's W array with only the used CIDs, as runs of consecutive widths.
+fn cid_widths(doc: &Document, w: &[Object], codes: &BTreeSet<u32>) -> Vec<Object> {
+    let number = |o: &Object| doc.dereference(o).ok().and_then(|(_, o)| o.as_i64().ok());
+    let mut widths = BTreeMap::new();
+    let mut i = 0;
+    while i + 1 < w.len() {
+        let Some(first) = number(&w[i]) else { break };
+        match doc.dereference(&w[i + 1]).map(|(_, o)| o) {
+            Ok(Object::Array(list)) => {
+                let end = first + list.len() as i64;
+                for &cid in codes.range(first.max(0) as u32..end.max(0) as u32) {
+                    widths.insert(cid, list[(cid as i64 - first) as usize].clone());
+                }
+                i += 2;
+            }
+            _ => {
+                let (Some(last), Some(width)) = (number(&w[i + 1]), w.get(i + 2)) else { break };
+                if last < first { break; }
+                for &cid in codes.range(first.max(0) as u32..=last.max(0) as u32) { widths.insert(cid, width.clone()); }
+                i += 3;
+            }
+        }
+    }
+    let mut result = Vec::new();
+    let mut next = None;
+    for (cid, width) in widths {
+        if next != Some(cid) {
+            result.push(Object::Integer(cid as i64));
+            result.push(Object::Array(Vec::new()));
+        }
+        if let Some(Object::Array(run)) = result.last_mut() { run.push(width); }
+        next = Some(cid + 1);
+    }
+    result
+}
+
+fn tag_base_font(font: &mut Dictionary, tag: &str) {
+    if let Ok(name) = font.get(b"BaseFont").and_then(Object::as_name) {
+        let name = tagged(name, tag);
+        font.set("BaseFont", Object::Name(name));
+    }
+}
+
+fn subset_tag(glyphs: &BTreeSet<u16>) -> String {
+    let mut hash = glyphs.iter().fold(0x811c9dc5u32, |h, &g| (h ^ u32::from(g)).wrapping_mul(0x01000193));
+    (0..6).map(|_| {
+        let letter = (b'A' + (hash % 26) as u8) as char;
+        hash /= 26;
+        letter
+    }).collect()
+}
+
+fn tagged(name: &[u8], tag: &str) -> Vec<u8> {
+    if name.len() > 7 && name[6] == b'+' && name[..6].iter().all(u8::is_ascii_uppercase) { return name.to_vec(); }
+    let mut result = format!("{}+", tag).into_bytes();
+    result.extend_from_slice(name);
+    result
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+// Subsets the CFF table of an OpenType program, keeping its other tables, or its TrueType outlines.
+fn subset_opentype(data: &[u8], keep: &BTreeSet<u16>) -> Option<Vec<u8>> {
+    let mut tables = read_sfnt(data)?;
+    let Some(cff) = tables.get(b"CFF ") else { return subset_truetype(data, keep) };
+    let cff = subset_cff(cff, keep)?;
+    tables.insert(*b"CFF ", cff);
+    write_sfnt(&data[0..4], tables)
+}
+
+// Empties the outlines of unused glyphs and drops tables PDF viewers never read.
+fn subset_truetype(data: &[u8], keep: &BTreeSet<u16>) -> Option<Vec<u8>> {
+    let mut tables = read_sfnt(data)?;
+    tables.retain(|tag, _| TRUETYPE_TABLES.contains(&tag));
+    let head = tables.get(b"head")?;
+    let long_offsets = read_u16(head, 50)? == 1;
+    let glyph_count = read_u16(tables.get(b"maxp")?, 4)?;
+    let (loca, glyf) = (tables.get(b"loca")?, tables.get(b"glyf")?);
+    let glyph = |gid: u16| -> Option<&[u8]> {
+        let gid = gid as usize;
+        let (start, end) = if long_offsets {
+            (read_u32(loca, gid * 4)? as usize, read_u32(loca, gid * 4 + 4)? as usize)
+        } else {
+            (read_u16(loca, gid * 2)? as usize * 2, read_u16(loca, gid * 2 + 2)? as usize * 2)
+        };
+        glyf.get(start..end.max(start))
+    };
+
+    // Composite glyphs are drawn from other glyphs, which must stay too.
+    let mut kept: BTreeSet<u16> = keep.iter().copied().filter(|&g| g < glyph_count).chain([0]).collect();
+    let mut pending: Vec<u16> = kept.iter().copied().collect();
+    while let Some(gid) = pending.pop() {
+        let outline = glyph(gid)?;
+        if outline.len() < 10 || read_u16(outline, 0)? as i16 >= 0 { continue; }
+        let mut pos = 10;
+        loop {
+            let flags = read_u16(outline, pos)?;
+            let component = read_u16(outline, pos + 2)?;
+            if component < glyph_count && kept.insert(component) { pending.push(component); }
+            pos += 4 + if flags & 0x0001 != 0 { 4 } else { 2 };
+            pos += if flags & 0x0008 != 0 { 2 } else if flags & 0x0040 != 0 { 4 } else if flags & 0x0080 != 0 { 8 } else { 0 };
+            if flags & 0x0020 == 0 { break; }
+        }
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity((glyph_count as usize + 1) * 4);
+    for gid in 0..glyph_count {
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        if kept.contains(&gid) {
+            new_glyf.extend_from_slice(glyph(gid)?);
+            new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+        }
+    }
+    new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+    let mut new_head = head.clone();
+    new_head.get_mut(50..52)?.copy_from_slice(&1u16.to_be_bytes());
+    tables.insert(*b"glyf", new_glyf);
+    tables.insert(*b"loca", new_loca);
+    tables.insert(*b"head", new_head);
+    write_sfnt(&data[0..4], tables)
+}
+
+// The tables of a TrueType or OpenType program by tag. Collections are not supported.
+fn read_sfnt(data: &[u8]) -> Option<BTreeMap<[u8; 4], Vec<u8>>> {
+    if data.get(0..4)? == b"ttcf" { return None; }
+    let mut tables = BTreeMap::new();
+    for i in 0..read_u16(data, 4)? as usize {
+        let record = 12 + i * 16;
+        let tag: [u8; 4] = data.get(record..record + 4)?.try_into().ok()?;
+        let (offset, length) = (read_u32(data, record + 8)? as usize, read_u32(data, record + 12)? as usize);
+        tables.insert(tag, data.get(offset..offset + length)?.to_vec());
+    }
+    Some(tables)
+}
+
+// Lays the tables out again with a fresh directory and checksums.
+fn write_sfnt(version: &[u8], mut tables: BTreeMap<[u8; 4], Vec<u8>>) -> Option<Vec<u8>> {
+    if let Some(head) = tables.get_mut(b"head") { head.get_mut(8..12)?.fill(0); }
+    let count = tables.len() as u16;
+    let selector = 15 - count.leading_zeros() as u16;
+    let search_range = (1u16 << selector) * 16;
+    let mut font = version.to_vec();
+    for value in [count, search_range, selector, count * 16 - search_range] { font.extend_from_slice(&value.to_be_bytes()); }
+    let mut offset = 12 + tables.len() * 16;
+    let mut head_offset = None;
+    for (tag, table) in &tables {
+        if tag == b"head" { head_offset = Some(offset); }
+        font.extend_from_slice(tag);
+        for value in [checksum(table), offset as u32, table.len() as u32] { font.extend_from_slice(&value.to_be_bytes()); }
+        offset += table.len().next_multiple_of(4);
+    }
+    for table in tables.values() {
+        font.extend_from_slice(table);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+    if let Some(head_offset) = head_offset {
+        let adjustment = 0xB1B0AFBAu32.wrapping_sub(checksum(&font));
+        font.get_mut(head_offset + 8..head_offset + 12)?.copy_from_slice(&adjustment.to_be_bytes());
+    }
+    Some(font)
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+struct CffIndex<'a> {
+    items: Vec<&'a [u8]>,
+    end: usize,
+}
+
+type CffDict = Vec<(u16, Vec<Vec<u8>>)>;
+
+// Top DICT operators whose operands are offsets into the font program.
+const CFF_CHARSET: u16 = 15;
+const CFF_ENCODING: u16 = 16;
+const CFF_CHARSTRINGS: u16 = 17;
+const CFF_PRIVATE: u16 = 18;
+const CFF_SUBRS: u16 = 19;
+const CFF_FDARRAY: u16 = 1236;
+const CFF_FDSELECT: u16 = 1237;
+
+// Replaces the charstrings of unused glyphs with empty ones and lays the program out again with fresh offsets.
+fn subset_cff(data: &[u8], keep: &BTreeSet<u16>) -> Option<Vec<u8>> {
+    let names_start = *data.get(2)? as usize;
+    let names_end = read_index(data, names_start)?.end;
+    let top_index = read_index(data, names_end)?;
+    let strings_end = read_index(data, top_index.end)?.end;
+    let globals_end = read_index(data, strings_end)?.end;
+    let [top] = top_index.items.as_slice() else { return None };
+    let mut top = read_dict(top)?;
+
+    let char_strings = read_index(data, dict_offset(&top, CFF_CHARSTRINGS, 0)?)?;
+    let glyph_count = char_strings.items.len();
+    let char_strings: Vec<&[u8]> = char_strings.items.iter().enumerate()
+        .map(|(gid, cs)| if gid == 0 || keep.contains(&(gid as u16)) { *cs } else { EMPTY_CHARSTRING })
+        .collect();
+    let blob = |op: u16, predefined: usize, length: &dyn Fn(usize) -> Option<usize>| -> Option<Option<&[u8]>> {
+        match dict_offset(&top, op, 0) {
+            Some(offset) if offset > predefined => Some(Some(data.get(offset..offset + length(offset)?)?)),
+            _ => Some(None),
+        }
+    };
+    let charset = blob(CFF_CHARSET, 2, &|offset| charset_length(data, offset, glyph_count))?;
+    let encoding = blob(CFF_ENCODING, 1, &|offset| encoding_length(data, offset))?;
+    let fd_select = blob(CFF_FDSELECT, 0, &|offset| fd_select_length(data, offset, glyph_count))?;
+    let top_private = match (dict_offset(&top, CFF_PRIVATE, 0), dict_offset(&top, CFF_PRIVATE, 1)) {
+        (Some(size), Some(offset)) => Some(private_blob(data, size, offset)?),
+        _ => None,
+    };
+    let mut font_dicts = Vec::new();
+    let mut fd_privates = Vec::new();
+    if let Some(offset) = dict_offset(&top, CFF_FDARRAY, 0) {
+        for item in read_index(data, offset)?.items {
+            let dict = read_dict(item)?;
+            fd_privates.push(private_blob(data, dict_offset(&dict, CFF_PRIVATE, 0)?, dict_offset(&dict, CFF_PRIVATE, 1)?)?);
+            font_dicts.push(dict);
+        }
+    }
+
+    // Offsets are written as fixed five-byte integers, so the Top DICT keeps its size once they are filled in.
+    for op in [CFF_CHARSET, CFF_ENCODING, CFF_CHARSTRINGS, CFF_PRIVATE, CFF_FDARRAY, CFF_FDSELECT] {
+        let Some(entry) = top.iter_mut().find(|(o, _)| *o == op) else { continue };
+        let predefined = match op { CFF_CHARSET => 2, CFF_ENCODING => 1, _ => -1 };
+        if op != CFF_PRIVATE && entry.1.len() == 1 && dict_int(&entry.1[0]).is_some_and(|v| v <= predefined) { continue; }
+        entry.1 = entry.1.iter().map(|_| fixed_int(0)).collect();
+    }
+    let base = names_end + write_index(&[write_dict(&top).as_slice()]).len() + (globals_end - top_index.end);
+    let mut tail = Vec::new();
+    let place = |tail: &mut Vec<u8>, bytes: &[u8]| -> i32 {
+        let offset = base + tail.len();
+        tail.extend_from_slice(bytes);
+        offset as i32
+    };
+    if let Some(charset) = charset { set_dict(&mut top, CFF_CHARSET, &[place(&mut tail, charset)]); }
+    if let Some(encoding) = encoding { set_dict(&mut top, CFF_ENCODING, &[place(&mut tail, encoding)]); }
+    if let Some(fd_select) = fd_select { set_dict(&mut top, CFF_FDSELECT, &[place(&mut tail, fd_select)]); }
+    let offset = place(&mut tail, &write_index(&char_strings));
+    set_dict(&mut top, CFF_CHARSTRINGS, &[offset]);
+    if let Some((private, size)) = top_private {
+        let offset = place(&mut tail, &private);
+        set_dict(&mut top, CFF_PRIVATE, &[size as i32, offset]);
+    }
+    for (dict, (private, size)) in font_dicts.iter_mut().zip(fd_privates) {
+        let offset = place(&mut tail, &private);
+        set_dict(dict, CFF_PRIVATE, &[size as i32, offset]);
+    }
+    if !font_dicts.is_empty() {
+        let encoded: Vec<Vec<u8>> = font_dicts.iter().map(write_dict).collect();
+        let items: Vec<&[u8]> = encoded.iter().map(Vec::as_slice).collect();
+        let offset = place(&mut tail, &write_index(&items));
+        set_dict(&mut top, CFF_FDARRAY, &[offset]);
+    }
+
+    let mut font = data[..names_end].to_vec();
+    font.extend_from_slice(&write_index(&[write_dict(&top).as_slice()]));
+    font.extend_from_slice(&data[top_index.end..globals_end]);
+    font.extend_from_slice(&tail);
+    Some(font)
+}
+
+// A Private DICT followed directly by its local subroutines, along with the DICT's own size.
+fn private_blob(data: &[u8], size: usize, offset: usize) -> Option<(Vec<u8>, usize)> {
+    let mut dict = read_dict(data.get(offset..offset + size)?)?;
+    let Some(subrs_offset) = dict_offset(&dict, CFF_SUBRS, 0).map(|o| offset + o) else {
+        let bytes = write_dict(&dict);
+        let size = bytes.len();
+        return Some((bytes, size));
+    };
+    let subrs = data.get(subrs_offset..read_index(data, subrs_offset)?.end)?;
+    set_dict(&mut dict, CFF_SUBRS, &[0]);
+    let size = write_dict(&dict).len();
+    set_dict(&mut dict, CFF_SUBRS, &[size as i32]);
+    let mut bytes = write_dict(&dict);
+    bytes.extend_from_slice(subrs);
+    Some((bytes, size))
+}
+
+fn read_index(data: &[u8], pos: usize) -> Option<CffIndex<'_>> {
+    let count = read_u16(data, pos)? as usize;
+    if count == 0 { return Some(CffIndex { items: Vec::new(), end: pos + 2 }); }
+    let offset_size = *data.get(pos + 2)? as usize;
+    if !(1..=4).contains(&offset_size) { return None; }
+    let offset = |i: usize| -> Option<usize> {
+        let start = pos + 3 + i * offset_size;
+        Some(data.get(start..start + offset_size)?.iter().fold(0, |v, &b| v << 8 | b as usize))
+    };
+    let data_start = pos + 3 + (count + 1) * offset_size - 1;
+    let mut items = Vec::with_capacity(count);
+    for i in 0..count { items.push(data.get(data_start + offset(i)?..data_start + offset(i + 1)?)?); }
+    Some(CffIndex { items, end: data_start + offset(count)? })
+}
+
+fn write_index(items: &[&[u8]]) -> Vec<u8> {
+    if items.is_empty() { return vec![0, 0]; }
+    let total: usize = items.iter().map(|i| i.len()).sum::<usize>() + 1;
+    let offset_size = match total { 0..=0xFF => 1, 0x100..=0xFFFF => 2, 0x10000..=0xFF_FFFF => 3, _ => 4 };
+    let mut bytes = (items.len() as u16).to_be_bytes().to_vec();
+    bytes.push(offset_size as u8);
+    let mut offset = 1usize;
+    for item in items.iter().map(|i| i.len()).chain([0]) {
+        bytes.extend_from_slice(&offset.to_be_bytes()[size_of::<usize>() - offset_size..]);
+        offset += item;
+    }
+    for item in items { bytes.extend_from_slice(item); }
+    bytes
+}
+
+fn read_dict(data: &[u8]) -> Option<CffDict> {
+    let mut dict = Vec::new();
+    let mut operands = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let b0 = data[pos];
+        let length = match b0 {
+            0..=21 => {
+                let op = if b0 == 12 { 1200 + *data.get(pos + 1)? as u16 } else { b0 as u16 };
+                dict.push((op, std::mem::take(&mut operands)));
+                pos += if b0 == 12 { 2 } else { 1 };
+                continue;
+            }
+            28 => 3,
+            29 => 5,
+            30 => 1 + data[pos + 1..].iter().position(|b| b >> 4 == 0xF || b & 0xF == 0xF)? + 1,
+            32..=246 => 1,
+            247..=254 => 2,
+            _ => return None,
+        };
+        operands.push(data.get(pos..pos + length)?.to_vec());
+        pos += length;
+    }
+    Some(dict)
+}
+
+fn write_dict(dict: &CffDict) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (op, operands) in dict {
+        for operand in operands { bytes.extend_from_slice(operand); }
+        if *op >= 1200 { bytes.extend_from_slice(&[12, (op - 1200) as u8]); } else { bytes.push(*op as u8); }
+    }
+    bytes
+}
+
+fn dict_int(operand: &[u8]) -> Option<i32> {
+    let b0 = *operand.first()? as i32;
+    let b1 = operand.get(1).map(|&b| b as i32);
+    match b0 {
+        28 => Some(i16::from_be_bytes(operand.get(1..3)?.try_into().ok()?) as i32),
+        29 => Some(i32::from_be_bytes(operand.get(1..5)?.try_into().ok()?)),
+        32..=246 => Some(b0 - 139),
+        247..=250 => Some((b0 - 247) * 256 + b1? + 108),
+        251..=254 => Some(-(b0 - 251) * 256 - b1? - 108),
+        _ => None,
+    }
+}
+
+fn dict_offset(dict: &CffDict, op: u16, index: usize) -> Option<usize> {
+    let (_, operands) = dict.iter().find(|(o, _)| *o == op)?;
+    usize::try_from(dict_int(operands.get(index)?)?).ok()
+}
+
+fn set_dict(dict: &mut CffDict, op: u16, values: &[i32]) {
+    let operands = values.iter().map(|&v| fixed_int(v)).collect();
+    match dict.iter_mut().find(|(o, _)| *o == op) {
+        Some(entry) => entry.1 = operands,
+        None => dict.push((op, operands)),
+    }
+}
+
+fn fixed_int(value: i32) -> Vec<u8> {
+    let mut bytes = vec![29];
+    bytes.extend_from_slice(&value.to_be_bytes());
+    bytes
+}
+
+fn charset_length(data: &[u8], offset: usize, glyph_count: usize) -> Option<usize> {
+    match *data.get(offset)? {
+        0 => Some(1 + 2 * glyph_count.saturating_sub(1)),
+        format @ (1 | 2) => {
+            let (mut pos, mut covered) = (offset + 1, 1);
+            while covered < glyph_count {
+                let left = if format == 1 { *data.get(pos + 2)? as usize } else { read_u16(data, pos + 2)? as usize };
+                covered += left + 1;
+                pos += if format == 1 { 3 } else { 4 };
+            }
+            Some(pos - offset)
+        }
+        _ => None,
+    }
+}
+
+fn encoding_length(data: &[u8], offset: usize) -> Option<usize> {
+    let format = *data.get(offset)?;
+    let count = *data.get(offset + 1)? as usize;
+    let length = match format & 0x7F {
+        0 => 2 + count,
+        1 => 2 + 2 * count,
+        _ => return None,
+    };
+    if format & 0x80 == 0 { return Some(length); }
+    Some(length + 1 + 3 * *data.get(offset + length)? as usize)
+}
+
+fn fd_select_length(data: &[u8], offset: usize, glyph_count: usize) -> Option<usize> {
+    match *data.get(offset)? {
+        0 => Some(1 + glyph_count),
+        3 => Some(3 + 3 * read_u16(data, offset + 1)? as usize + 2),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Stream};
+    use lopdf::content::Content;
+    use ttf_parser::OutlineBuilder;
+
+    struct Counter(usize);
+
+    impl OutlineBuilder for Counter {
+        fn move_to(&mut self, _: f32, _: f32) { self.0 += 1; }
+        fn line_to(&mut self, _: f32, _: f32) { self.0 += 1; }
+        fn quad_to(&mut self, _: f32, _: f32, _: f32, _: f32) { self.0 += 1; }
+        fn curve_to(&mut self, _: f32, _: f32, _: f32, _: f32, _: f32, _: f32) { self.0 += 1; }
+        fn close(&mut self) {}
+    }
+
+    fn table(length: usize, fields: &[(usize, &[u8])]) -> Vec<u8> {
+        let mut table = vec![0; length];
+        for (offset, bytes) in fields { table[*offset..offset + bytes.len()].copy_from_slice(bytes); }
+        table
+    }
+
+    fn sfnt_tables(glyph_count: u16) -> BTreeMap<[u8; 4], Vec<u8>> {
+        let mut tables = BTreeMap::new();
+        tables.insert(*b"head", table(54, &[(0, &[0, 1, 0, 0]), (12, &0x5F0F3CF5u32.to_be_bytes()), (18, &1000u16.to_be_bytes()), (50, &[0, 1])]));
+        tables.insert(*b"hhea", table(36, &[(0, &[0, 1, 0, 0]), (34, &glyph_count.to_be_bytes())]));
+        tables.insert(*b"maxp", table(6, &[(0, &[0, 0, 0x50, 0]), (4, &glyph_count.to_be_bytes())]));
+        tables.insert(*b"hmtx", (0..glyph_count).flat_map(|_| [2, 0x58, 0, 0]).collect());
+        tables
+    }
+
+    // Glyphs 0 to 2 are triangles and glyph 3 is a composite of glyph 1.
+    fn truetype_font() -> Vec<u8> {
+        let triangle: Vec<u8> = [&1i16.to_be_bytes()[..], &[0, 0, 0, 0, 0, 100, 0, 100], &[0, 2, 0, 0], &[1, 1, 1], &[0, 0, 0, 100, 0, 0], &[0, 0, 0, 0, 0, 100]].concat();
+        let composite: Vec<u8> = [&(-1i16).to_be_bytes()[..], &[0, 0, 0, 0, 0, 100, 0, 100], &[0, 2, 0, 1, 0, 0]].concat();
+        let (mut glyf, mut loca) = (Vec::new(), Vec::new());
+        for glyph in [&triangle, &triangle, &triangle, &composite] {
+            loca.extend_from_slice(&(glyf.len() as u32).to_be_bytes());
+            glyf.extend_from_slice(glyph);
+            glyf.resize(glyf.len().next_multiple_of(4), 0);
+        }
+        loca.extend_from_slice(&(glyf.len() as u32).to_be_bytes());
+        let mut tables = sfnt_tables(4);
+        tables.insert(*b"glyf", glyf);
+        tables.insert(*b"loca", loca);
+        write_sfnt(&[0, 1, 0, 0], tables).unwrap()
+    }
+
+    // A CFF program with .notdef and squares named A, B and C, in the standard encoding.
+    fn cff_font() -> Vec<u8> {
+        let square: &[u8] = &[139, 139, 21, 239, 139, 5, 139, 239, 5, 14];
+        let char_strings = write_index(&[EMPTY_CHARSTRING, square, square, square]);
+        let charset: Vec<u8> = [0u8].into_iter().chain([34u16, 35, 36].iter().flat_map(|sid| sid.to_be_bytes())).collect();
+        let private = write_dict(&vec![(20, vec![vec![139]])]);
+        let mut top: CffDict = vec![(CFF_CHARSET, vec![fixed_int(0)]), (CFF_CHARSTRINGS, vec![fixed_int(0)]), (CFF_PRIVATE, vec![fixed_int(0), fixed_int(0)])];
+        let mut head = vec![1, 0, 4, 4];
+        head.extend(write_index(&[b"Test"]));
+        let base = head.len() + write_index(&[write_dict(&top).as_slice()]).len() + 4;
+        set_dict(&mut top, CFF_CHARSET, &[base as i32]);
+        set_dict(&mut top, CFF_CHARSTRINGS, &[(base + charset.len()) as i32]);
+        set_dict(&mut top, CFF_PRIVATE, &[private.len() as i32, (base + charset.len() + char_strings.len()) as i32]);
+        head.extend(write_index(&[write_dict(&top).as_slice()]));
+        [head, vec![0, 0, 0, 0], charset, char_strings, private].concat()
+    }
+
+    fn drawn(face: &ttf_parser::Face, glyph: u16) -> bool {
+        face.outline_glyph(GlyphId(glyph), &mut Counter(0)).is_some()
+    }
+
+    fn cff_drawn(table: &ttf_parser::cff::Table, glyph: u16) -> bool {
+        let mut counter = Counter(0);
+        table.outline(GlyphId(glyph), &mut counter).is_ok() && counter.0 > 0
+    }
+
+    #[test]
+    fn truetype_subset_keeps_used_glyphs_and_components() {
+        let font = truetype_font();
+        let subset = subset_truetype(&font, &BTreeSet::from([3])).unwrap();
+        assert!(subset.len() < font.len());
+        assert_eq!(checksum(&subset), 0xB1B0AFBA);
+        let face = ttf_parser::Face::parse(&subset, 0).unwrap();
+        assert_eq!(face.number_of_glyphs(), 4);
+        assert!(drawn(&face, 0) && drawn(&face, 1) && drawn(&face, 3));
+        assert!(!drawn(&face, 2));
+    }
+
+    #[test]
+    fn cff_subset_empties_unused_charstrings() {
+        let font = cff_font();
+        assert!(cff_drawn(&ttf_parser::cff::Table::parse(&font).unwrap(), 1));
+        let subset = subset_cff(&font, &BTreeSet::from([2])).unwrap();
+        let table = ttf_parser::cff::Table::parse(&subset).unwrap();
+        assert_eq!(table.number_of_glyphs(), 4);
+        assert!(cff_drawn(&table, 2));
+        assert!(!cff_drawn(&table, 1) && !cff_drawn(&table, 3));
+        assert_eq!(table.glyph_index_by_name("C"), Some(GlyphId(3)));
+    }
+
+    // A page showing `text` with a simple font whose program is in `file`, and optionally a second font written
+    // directly in the page's resources that shares the program.
+    fn font_document(file: Stream, font: Dictionary, text: &str, shared: bool) -> (Document, ObjectId) {
+        let mut doc = Document::with_version("1.5");
+        let file_id = doc.add_object(file);
+        let descriptor = doc.add_object(dictionary! { "Type" => "FontDescriptor", "FontName" => "Test", "FontFile3" => file_id });
+        let mut font = font;
+        font.set("FontDescriptor", descriptor);
+        let font_id = doc.add_object(font.clone());
+        let content = Content { operations: vec![
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec!["F1".into(), 12.into()]),
+            Operation::new("Tj", vec![Object::string_literal(text)]),
+            Operation::new("ET", vec![]),
+        ] };
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+        let mut fonts = dictionary! { "F1" => font_id };
+        if shared { fonts.set("F2", font); }
+        let pages_id = doc.new_object_id();
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page", "Parent" => pages_id, "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Contents" => content_id, "Resources" => dictionary! { "Font" => fonts },
+        });
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] }));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        (doc, file_id)
+    }
+
+    fn type1c(encoding: Option<&str>) -> (Stream, Dictionary) {
+        let mut font = dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Test" };
+        if let Some(encoding) = encoding { font.set("Encoding", Object::Name(encoding.as_bytes().to_vec())); }
+        (Stream::new(dictionary! { "Subtype" => "Type1C" }, cff_font()), font)
+    }
+
+    fn subset_program(doc: &Document, file_id: ObjectId) -> Vec<u8> {
+        doc.get_object(file_id).unwrap().as_stream().unwrap().get_plain_content().unwrap()
+    }
+
+    #[test]
+    fn subsets_simple_cff_fonts_through_the_builtin_encoding() {
+        let (file, font) = type1c(None);
+        let (mut doc, file_id) = font_document(file, font, "A", false);
+        assert_eq!(subset_fonts(&mut doc), 1);
+        let program = subset_program(&doc, file_id);
+        let table = ttf_parser::cff::Table::parse(&program).unwrap();
+        assert!(cff_drawn(&table, 1));
+        assert!(!cff_drawn(&table, 2) && !cff_drawn(&table, 3));
+        let font = doc.get_dictionary(doc.get_pages()[&1]).unwrap().get_deref(b"Resources", &doc).unwrap().as_dict().unwrap()
+            .get_deref(b"Font", &doc).unwrap().as_dict().unwrap().get_deref(b"F1", &doc).unwrap().as_dict().unwrap();
+        let name = font.get(b"BaseFont").unwrap().as_name().unwrap();
+        assert!(name.ends_with(b"+Test") && name[..6].iter().all(u8::is_ascii_uppercase));
+    }
+
+    #[test]
+    fn subsets_simple_cff_fonts_through_a_named_encoding() {
+        let (file, font) = type1c(Some("WinAnsiEncoding"));
+        let (mut doc, file_id) = font_document(file, font, "BC", false);
+        assert_eq!(subset_fonts(&mut doc), 1);
+        let program = subset_program(&doc, file_id);
+        let table = ttf_parser::cff::Table::parse(&program).unwrap();
+        assert!(!cff_drawn(&table, 1));
+        assert!(cff_drawn(&table, 2) && cff_drawn(&table, 3));
+    }
+
+    #[test]
+    fn subsets_the_cff_table_of_opentype_programs() {
+        let mut tables = sfnt_tables(4);
+        tables.insert(*b"CFF ", cff_font());
+        let program = write_sfnt(b"OTTO", tables).unwrap();
+        let file = Stream::new(dictionary! { "Subtype" => "OpenType" }, program);
+        let font = dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Test" };
+        let (mut doc, file_id) = font_document(file, font, "C", false);
+        assert_eq!(subset_fonts(&mut doc), 1);
+        let program = subset_program(&doc, file_id);
+        let face = ttf_parser::Face::parse(&program, 0).unwrap();
+        assert!(drawn(&face, 3));
+        assert!(!drawn(&face, 1) && !drawn(&face, 2));
+    }
+
+    #[test]
+    fn keeps_programs_shared_with_direct_font_dictionaries() {
+        let (file, font) = type1c(None);
+        let (mut doc, file_id) = font_document(file, font, "A", true);
+        assert_eq!(subset_fonts(&mut doc), 0);
+        assert_eq!(subset_program(&doc, file_id), cff_font());
+    }
+}
@@ -0,0 +1,532 @@
+use lopdf::{dictionary, Dictionary, Document, EncryptionState, Object, ObjectId, Stream, StringFormat};
+use lopdf::encryption;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use crate::pdf;
+
+// Objects in the order a linearized file stores them (ISO 32000-1, annex F). The first page section also holds
+// every object it shares with later pages.
+struct Layout {
+    catalog: ObjectId,
+    first_page: Vec<ObjectId>,
+    pages: Vec<Vec<ObjectId>>,
+    shared: Vec<ObjectId>,
+    other: Vec<ObjectId>,
+    // Objects each later page uses from the first page and shared sections.
+    page_shared: Vec<Vec<ObjectId>>,
+}
+
+pub struct LinearizationReport {
+    pub linearized: bool,
+    pub pages: i64,
+    pub first_page_end: i64,
+    pub problems: Vec<String>,
+    // False when the pages could not be read, such as in an encrypted file, to compare their count.
+    pub pages_checked: bool,
+}
+
+impl LinearizationReport {
+    pub fn summary(&self) -> String {
+        if !self.linearized { return "Not linearized: viewers must download the whole file before showing a page.".to_string(); }
+        let mut text = if self.problems.is_empty() {
+            format!("Linearized for fast web view: {} pages, the first page is readable after {}.", self.pages, pdf::format_size(self.first_page_end))
+        } else {
+            format!("Marked as linearized, but the layout is broken:\n  {}", self.problems.join("\n  "))
+        };
+        if !self.pages_checked { text.push_str("\nThe page count was not checked, since the pages could not be read."); }
+        text
+    }
+}
+
+// Writes the document with the first page's objects and the hint tables up front, so a viewer can show page one
+// after fetching only the start of the file. Unreferenced objects are dropped. When encryption is given, objects
+// are encrypted under their new numbers.
+pub fn write_linearized(doc: &Document, target: &mut dyn Write, encryption: Option<&EncryptionState>) -> Result<(), Box<dyn std::error::Error>> {
+    let layout = layout(doc)?;
+    let main: Vec<ObjectId> = layout.pages.iter().flatten().chain(&layout.shared).chain(&layout.other).copied().collect();
+    let first: Vec<ObjectId> = std::iter::once(layout.catalog).chain(layout.first_page.iter().copied()).collect();
+    // The main section takes the low numbers, then the encryption dictionary; the linearization dictionary,
+    // catalog, first page objects and hint stream take the high ones.
+    let mut numbers: HashMap<ObjectId, u32> = main.iter().enumerate().map(|(i, &id)| (id, i as u32 + 1)).collect();
+    let encrypt_number = encryption.map(|_| main.len() as u32 + 1);
+    let first_number = main.len() as u32 + 1 + encrypt_number.map_or(0, |_| 1);
+    numbers.extend(first.iter().enumerate().map(|(i, &id)| (id, first_number + 1 + i as u32)));
+    let hint_number = first_number + 1 + first.len() as u32;
+    let size = hint_number + 1;
+
+    let body = |id: ObjectId| -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let number = numbers[&id];
+        let mut object = renumbered(doc.get_object(id)?, &numbers);
+        if let Some(state) = encryption { encryption::encrypt_object(state, (number, 0), &mut object)?; }
+        Ok(indirect_object(number, &object))
+    };
+    let mut main_bodies = main.iter().map(|&id| body(id)).collect::<Result<Vec<_>, _>>()?;
+    if let (Some(state), Some(number)) = (encryption, encrypt_number) { main_bodies.push(indirect_object(number, &Object::Dictionary(state.encode()?))); }
+    let first_bodies = first.iter().map(|&id| body(id)).collect::<Result<Vec<_>, _>>()?;
+
+    let mut trailer = Dictionary::new();
+    trailer.set("Size", size as i64);
+    for key in [&b"Root"[..], b"Info", b"ID"] {
+        if let Ok(value) = doc.trailer.get(key) { trailer.set(key, renumbered(value, &numbers)); }
+    }
+    if let Some(number) = encrypt_number { trailer.set("Encrypt", Object::Reference((number, 0))); }
+
+    // Fixed-width numbers keep the first part of the file the same size however the offsets come out.
+    let header = [format!("%PDF-{}\n", doc.version).into_bytes(), b"%\xE2\xE3\xCF\xD3\n".to_vec()].concat();
+    let lin_dict = |l: usize, h: (usize, usize), e: usize, t: usize| format!(
+        "{} 0 obj\n<</Linearized 1/L {:010}/H [{:010} {:010}]/O {}/E {:010}/N {}/T {:010}>>\nendobj\n",
+        first_number, l, h.0, h.1, numbers[&layout.first_page[0]], e, layout.pages.len() + 1, t,
+    ).into_bytes();
+    let first_xref = |offsets: &[usize], prev: usize| {
+        let mut out = format!("xref\n{} {}\n", first_number, offsets.len()).into_bytes();
+        for offset in offsets { out.extend(format!("{:010} 00000 n\r\n", offset).bytes()); }
+        out.extend(b"trailer\n");
+        write_dictionary(&mut out, &trailer);
+        out.truncate(out.len() - 2);
+        out.extend(format!("/Prev {:010}>>\nstartxref\n0\n%%EOF\n", prev).bytes());
+        out
+    };
+    let first_count = first.len() + 2;
+    let part4_start = header.len() + lin_dict(0, (0, 0), 0, 0).len() + first_xref(&vec![0; first_count], 0).len();
+    let hint_offset = part4_start + first_bodies[0].len();
+
+    // Hint tables measure offsets as if the hint stream were absent.
+    let mut offsets: HashMap<ObjectId, usize> = HashMap::new();
+    let mut lengths: HashMap<ObjectId, usize> = HashMap::new();
+    let mut pos = hint_offset;
+    for (&id, bytes) in layout.first_page.iter().chain(&main).zip(first_bodies[1..].iter().chain(&main_bodies)) {
+        offsets.insert(id, pos);
+        lengths.insert(id, bytes.len());
+        pos += bytes.len();
+    }
+    let hint_data = hint_tables(&layout, &numbers, &offsets, &lengths);
+    let mut hint = Stream::new(Dictionary::new(), hint_data.0);
+    hint.compress()?;
+    hint.dict.set("S", hint_data.1 as i64);
+    let mut hint = Object::Stream(hint);
+    if let Some(state) = encryption { encryption::encrypt_object(state, (hint_number, 0), &mut hint)?; }
+    let hint = indirect_object(hint_number, &hint);
+
+    let first_page_end = hint_offset + hint.len() + layout.first_page.iter().map(|id| lengths[id]).sum::<usize>();
+    let main_xref = first_page_end + main_bodies.iter().map(Vec::len).sum::<usize>();
+    let mut xref = format!("xref\n0 {}\n", main_bodies.len() + 1).into_bytes();
+    let first_entry = main_xref + xref.len();
+    xref.extend(b"0000000000 65535 f\r\n");
+    let mut offset = first_page_end;
+    for bytes in &main_bodies {
+        xref.extend(format!("{:010} 00000 n\r\n", offset).bytes());
+        offset += bytes.len();
+    }
+    let first_xref_offset = header.len() + lin_dict(0, (0, 0), 0, 0).len();
+    xref.extend(format!("trailer\n<</Size {}>>\nstartxref\n{}\n%%EOF\n", size, first_xref_offset).bytes());
+    let length = main_xref + xref.len();
+
+    // The linearization dictionary comes first, then the catalog, the hint stream and the first page objects.
+    let mut first_offsets = vec![header.len(), part4_start];
+    let mut offset = hint_offset + hint.len();
+    for bytes in &first_bodies[1..] {
+        first_offsets.push(offset);
+        offset += bytes.len();
+    }
+    first_offsets.push(hint_offset);
+    target.write_all(&header)?;
+    target.write_all(&lin_dict(length, (hint_offset, hint.len()), first_page_end, first_entry - 1))?;
+    target.write_all(&first_xref(&first_offsets, main_xref))?;
+    target.write_all(&first_bodies[0])?;
+    target.write_all(&hint)?;
+    for bytes in first_bodies[1..].iter().chain(&main_bodies) { target.write_all(bytes)?; }
+    target.write_all(&xref)?;
+    Ok(())
+}
+
+fn layout(doc: &Document) -> Result<Layout, Box<dyn std::error::Error>> {
+    let catalog = doc.trailer.get(b"Root")?.as_reference()?;
+    let page_ids: Vec<ObjectId> = doc.page_iter().collect();
+    if page_ids.is_empty() { return Err("The document has no pages.".into()); }
+    let used: Vec<Vec<ObjectId>> = page_ids.iter().map(|&id| page_objects(doc, id)).collect();
+    let mut users: HashMap<ObjectId, usize> = HashMap::new();
+    for objects in &used[1..] {
+        for &id in objects { *users.entry(id).or_default() += 1; }
+    }
+    let mut placed: HashSet<ObjectId> = HashSet::from([catalog]);
+    let first_page: Vec<ObjectId> = used[0].iter().copied().filter(|&id| placed.insert(id)).collect();
+    let pages: Vec<Vec<ObjectId>> = used[1..].iter().map(|objects| {
+        objects.iter().copied().filter(|id| users[id] == 1 && placed.insert(*id)).collect()
+    }).collect();
+    let shared: Vec<ObjectId> = used[1..].iter().flatten().copied().filter(|&id| placed.insert(id)).collect();
+    let in_tables: HashSet<ObjectId> = first_page.iter().chain(&shared).copied().collect();
+    let page_shared = used[1..].iter().map(|objects| objects.iter().copied().filter(|id| in_tables.contains(id)).collect()).collect();
+    // Everything else the catalog and document info lead to, such as the page tree. The old encryption
+    // dictionary, if any, is replaced rather than carried over.
+    let mut pending: Vec<ObjectId> = doc.trailer.get(b"Info").and_then(Object::as_reference).into_iter().collect();
+    pdf::collect_references(doc.get_object(catalog)?, &mut pending);
+    let mut other = Vec::new();
+    while let Some(id) = pending.pop() {
+        let Ok(object) = doc.get_object(id) else { continue };
+        if !placed.insert(id) { continue; }
+        pdf::collect_references(object, &mut pending);
+        other.push(id);
+    }
+    Ok(Layout { catalog, first_page, pages, shared, other, page_shared })
+}
+
+// The page object followed by everything it draws with, without following links to other pages.
+fn page_objects(doc: &Document, page_id: ObjectId) -> Vec<ObjectId> {
+    let mut objects = vec![page_id];
+    let mut seen = HashSet::from([page_id]);
+    let mut pending = Vec::new();
+    if let Ok(page) = doc.get_object(page_id) { pdf::collect_references(page, &mut pending); }
+    pending.reverse();
+    while let Some(id) = pending.pop() {
+        if !seen.insert(id) { continue; }
+        let Ok(object) = doc.get_object(id) else { continue };
+        if let Object::Dictionary(dict) = object && (dict.has_type(b"Page") || dict.has_type(b"Pages")) { continue; }
+        objects.push(id);
+        let mut refs = Vec::new();
+        pdf::collect_references(object, &mut refs);
+        pending.extend(refs.into_iter().rev());
+    }
+    objects
+}
+
+// Page offset hint table followed by the shared object hint table, and the offset of the latter.
+fn hint_tables(layout: &Layout, numbers: &HashMap<ObjectId, u32>, offsets: &HashMap<ObjectId, usize>, lengths: &HashMap<ObjectId, usize>) -> (Vec<u8>, usize) {
+    let first_page_length: usize = layout.first_page.iter().map(|id| lengths[id]).sum();
+    let page_counts: Vec<u64> = std::iter::once(layout.first_page.len()).chain(layout.pages.iter().map(Vec::len)).map(|n| n as u64).collect();
+    let page_lengths: Vec<u64> = std::iter::once(first_page_length)
+        .chain(layout.pages.iter().map(|objects| objects.iter().map(|id| lengths[id]).sum()))
+        .map(|n| n as u64).collect();
+    let groups: Vec<ObjectId> = layout.first_page.iter().chain(&layout.shared).copied().collect();
+    let group_index: HashMap<ObjectId, u64> = groups.iter().enumerate().map(|(i, &id)| (id, i as u64)).collect();
+    // The first page never lists shared objects; its own section holds them.
+    let shared_refs: Vec<Vec<u64>> = std::iter::once(Vec::new())
+        .chain(layout.page_shared.iter().map(|objects| objects.iter().map(|id| group_index[id]).collect()))
+        .collect();
+
+    let mut bits = BitWriter::default();
+    let (min_count, count_bits) = range(&page_counts);
+    let (min_length, length_bits) = range(&page_lengths);
+    let ref_bits = nbits(shared_refs.iter().map(|r| r.len() as u64).max().unwrap_or(0));
+    let id_bits = nbits(shared_refs.iter().flatten().copied().max().unwrap_or(0));
+    bits.write(min_count, 32);
+    bits.write(offsets[&layout.first_page[0]] as u64, 32);
+    bits.write(count_bits as u64, 16);
+    bits.write(min_length, 32);
+    bits.write(length_bits as u64, 16);
+    // Content stream positions are reported as the whole page, as Acrobat does.
+    bits.write(0, 32);
+    bits.write(0, 16);
+    bits.write(min_length, 32);
+    bits.write(length_bits as u64, 16);
+    bits.write(ref_bits as u64, 16);
+    bits.write(id_bits as u64, 16);
+    bits.write(0, 16);
+    bits.write(4, 16);
+    for &count in &page_counts { bits.write(count - min_count, count_bits); }
+    bits.flush();
+    for &length in &page_lengths { bits.write(length - min_length, length_bits); }
+    bits.flush();
+    for refs in &shared_refs { bits.write(refs.len() as u64, ref_bits); }
+    bits.flush();
+    for &id in shared_refs.iter().flatten() { bits.write(id, id_bits); }
+    bits.flush();
+    // Fractional positions and content stream offsets take no bits.
+    for &length in &page_lengths { bits.write(length - min_length, length_bits); }
+    bits.flush();
+    let shared_offset = bits.bytes.len();
+
+    // One object per group.
+    let group_lengths: Vec<u64> = groups.iter().map(|id| lengths[id] as u64).collect();
+    let (min_group, group_bits) = range(&group_lengths);
+    let first_shared = layout.shared.first();
+    bits.write(first_shared.map_or(0, |id| numbers[id] as u64), 32);
+    bits.write(first_shared.map_or(0, |id| offsets[id] as u64), 32);
+    bits.write(layout.first_page.len() as u64, 32);
+    bits.write(groups.len() as u64, 32);
+    bits.write(0, 16);
+    bits.write(min_group, 32);
+    bits.write(group_bits as u64, 16);
+    for &length in &group_lengths { bits.write(length - min_group, group_bits); }
+    bits.flush();
+    for _ in &groups { bits.write(0, 1); }
+    bits.flush();
+    (bits.bytes, shared_offset)
+}
+
+fn range(values: &[u64]) -> (u64, u32) {
+    let min = values.iter().copied().min().unwrap_or(0);
+    (min, nbits(values.iter().copied().max().unwrap_or(0) - min))
+}
+
+fn nbits(value: u64) -> u32 {
+    64 - value.leading_zeros()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u8,
+    used: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            self.current = (self.current << 1) | ((value >> i) & 1) as u8;
+            self.used += 1;
+            if self.used == 8 { self.bytes.push(self.current); self.current = 0; self.used = 0; }
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.used > 0 { self.write(0, 8 - self.used); }
+    }
+}
+
+// Writes the document with every object that may be compressed packed into object streams, indexed by a
+// cross-reference stream. lopdf leaves all but the first object stream it writes out of the index, so the layout is
+// done here; a stream holds at most as many objects as a 16-bit index reaches.
+pub fn write_object_streams(doc: &Document, target: &mut dyn Write) -> Result<(), Box<dyn std::error::Error>> {
+    let encrypt = doc.trailer.get(b"Encrypt").and_then(Object::as_reference).ok();
+    let mut direct = Vec::new();
+    let mut packed = Vec::new();
+    for (&id, object) in &doc.objects {
+        // Object and cross-reference streams from the source are rebuilt, and linearization no longer holds.
+        let kind = match object {
+            Object::Dictionary(dict) => dict.get(b"Type").and_then(Object::as_name).ok(),
+            Object::Stream(stream) => stream.dict.get(b"Type").and_then(Object::as_name).ok(),
+            _ => None,
+        };
+        if matches!(kind, Some(b"ObjStm" | b"XRef")) || object.as_dict().is_ok_and(|d| d.has(b"Linearized")) { continue; }
+        if id.1 != 0 || Some(id) == encrypt || object.as_stream().is_ok() { direct.push((id, object)); } else { packed.push((id.0, object)); }
+    }
+
+    let mut out = format!("%PDF-{}
+", doc.version.as_str().max("1.5")).into_bytes();
+    out.extend(b"%\xE2\xE3\xCF\xD3\n");
+    // Type, then offset or object stream number, then generation or index, per object number.
+    let mut entries: HashMap<u32, (u8, u32, u16)> = HashMap::new();
+    for (id, object) in direct {
+        entries.insert(id.0, (1, out.len() as u32, id.1));
+        out.extend(format!("{} {} obj\n", id.0, id.1).bytes());
+        write_object(&mut out, object);
+        out.extend(b"\nendobj\n");
+    }
+    let mut number = doc.max_id;
+    for chunk in packed.chunks(usize::from(u16::MAX)) {
+        number += 1;
+        let mut offsets = Vec::new();
+        let mut body = Vec::new();
+        for (index, &(id, object)) in chunk.iter().enumerate() {
+            entries.insert(id, (2, number, index as u16));
+            offsets.extend(format!("{} {} ", id, body.len()).bytes());
+            write_object(&mut body, object);
+            body.push(b'\n');
+        }
+        let dict = dictionary! { "Type" => "ObjStm", "N" => chunk.len() as i64, "First" => offsets.len() as i64 };
+        let mut stream = Stream::new(dict, [offsets, body].concat());
+        stream.compress()?;
+        entries.insert(number, (1, out.len() as u32, 0));
+        out.extend(indirect_object(number, &Object::Stream(stream)));
+    }
+
+    number += 1;
+    let xref_offset = out.len() as u32;
+    entries.insert(number, (1, xref_offset, 0));
+    let mut table = Vec::new();
+    for n in 0..=number {
+        let (kind, field, index) = entries.get(&n).copied().unwrap_or((0, 0, if n == 0 { 65535 } else { 0 }));
+        table.push(kind);
+        table.extend(field.to_be_bytes());
+        table.extend(index.to_be_bytes());
+    }
+    let mut dict = doc.trailer.clone();
+    dict.remove(b"Prev");
+    dict.set("Type", "XRef");
+    dict.set("Size", number as i64 + 1);
+    dict.set("W", vec![1.into(), 4.into(), 2.into()]);
+    let mut xref = Stream::new(dict, table);
+    xref.compress()?;
+    out.extend(indirect_object(number, &Object::Stream(xref)));
+    out.extend(format!("startxref\n{}\n%%EOF\n", xref_offset).bytes());
+    target.write_all(&out)?;
+    Ok(())
+}
+
+fn renumbered(object: &Object, numbers: &HashMap<ObjectId, u32>) -> Object {
+    match object {
+        Object::Reference(id) => numbers.get(id).map_or(Object::Null, |&n| Object::Reference((n, 0))),
+        Object::Array(items) => Object::Array(items.iter().map(|o| renumbered(o, numbers)).collect()),
+        Object::Dictionary(dict) => Object::Dictionary(renumbered_dictionary(dict, numbers)),
+        Object::Stream(stream) => Object::Stream(Stream::new(renumbered_dictionary(&stream.dict, numbers), stream.content.clone())),
+        other => other.clone(),
+    }
+}
+
+fn renumbered_dictionary(dict: &Dictionary, numbers: &HashMap<ObjectId, u32>) -> Dictionary {
+    dict.iter().map(|(key, value)| (key.clone(), renumbered(value, numbers))).collect()
+}
+
+fn indirect_object(number: u32, object: &Object) -> Vec<u8> {
+    let mut out = format!("{} 0 obj\n", number).into_bytes();
+    write_object(&mut out, object);
+    out.extend(b"\nendobj\n");
+    out
+}
+
+// lopdf keeps its object writer private, so this mirrors its output.
+fn write_object(out: &mut Vec<u8>, object: &Object) {
+    match object {
+        Object::Null => out.extend(b"null"),
+        Object::Boolean(value) => out.extend(if *value { &b"true"[..] } else { b"false" }),
+        Object::Integer(value) => out.extend(value.to_string().bytes()),
+        Object::Real(value) => out.extend(value.to_string().bytes()),
+        Object::Name(name) => write_name(out, name),
+        Object::String(text, StringFormat::Literal) => {
+            out.push(b'(');
+            for &byte in text {
+                match byte {
+                    b'(' | b')' | b'\\' => out.extend([b'\\', byte]),
+                    b'\r' => out.extend(b"\\r"),
+                    _ => out.push(byte),
+                }
+            }
+            out.push(b')');
+        }
+        Object::String(text, StringFormat::Hexadecimal) => {
+            out.push(b'<');
+            for byte in text { out.extend(format!("{:02X}", byte).bytes()); }
+            out.push(b'>');
+        }
+        Object::Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 { out.push(b' '); }
+                write_object(out, item);
+            }
+            out.push(b']');
+        }
+        Object::Dictionary(dict) => write_dictionary(out, dict),
+        Object::Stream(stream) => {
+            write_dictionary(out, &stream.dict);
+            out.extend(b"stream\n");
+            out.extend(&stream.content);
+            out.extend(b"\nendstream");
+        }
+        Object::Reference(id) => out.extend(format!("{} {} R", id.0, id.1).bytes()),
+    }
+}
+
+fn write_name(out: &mut Vec<u8>, name: &[u8]) {
+    out.push(b'/');
+    for &byte in name {
+        if b" \t\n\r\x0C()<>[]{}/%#".contains(&byte) || !(33..=126).contains(&byte) {
+            out.extend(format!("#{:02X}", byte).bytes());
+        } else {
+            out.push(byte);
+        }
+    }
+}
+
+fn write_dictionary(out: &mut Vec<u8>, dict: &Dictionary) {
+    out.extend(b"<<");
+    for (key, value) in dict.iter() {
+        write_name(out, key);
+        out.push(b' ');
+        write_object(out, value);
+    }
+    out.extend(b">>");
+}
+
+// A linearized file starts with its linearization dictionary, whose length entry still matches the file; any later
+// edit appended as an incremental update breaks that. The layout is checked from the raw bytes, so encrypted files
+// can be checked without their password; the page count is only compared when the pages can be read.
+pub fn check_linearized(data: &[u8]) -> Result<LinearizationReport, Box<dyn std::error::Error>> {
+    let mut report = LinearizationReport { linearized: false, pages: 0, first_page_end: 0, problems: Vec::new(), pages_checked: false };
+    let Some(params) = linearization_params(&data[..data.len().min(1024)]) else { return Ok(report) };
+    report.linearized = true;
+    let int = |key: &[u8]| params.get(key).and_then(|values| values.first()).copied().unwrap_or(-1);
+    report.pages = int(b"N");
+    report.first_page_end = int(b"E");
+    if int(b"L") != data.len() as i64 { report.problems.push(format!("File length is {} bytes, not the recorded {}; it was changed after linearizing.", data.len(), int(b"L"))); }
+    if report.first_page_end <= 0 || report.first_page_end > data.len() as i64 { report.problems.push("The end of the first page lies outside the file.".to_string()); }
+    let hint = params.get(&b"H"[..]).and_then(|h| h.first()).and_then(|&offset| data.get(offset as usize..));
+    let hint_ok = hint.is_some_and(|rest| {
+        let dict_end = rest.windows(6).position(|w| w == b"stream").unwrap_or(0);
+        first_object_id(rest).is_some() && rest[..dict_end].windows(2).any(|w| w == b"/S")
+    });
+    if !hint_ok { report.problems.push("The hint stream is missing.".to_string()); }
+    let t = int(b"T");
+    if t < 0 || !data.get(t as usize + 1..).is_some_and(|rest| rest.starts_with(b"0000000000 65535 f")) {
+        report.problems.push("The main cross-reference table is not where recorded.".to_string());
+    }
+    let Ok(doc) = Document::load_mem(data) else { return Ok(report) };
+    let page_ids: Vec<ObjectId> = doc.page_iter().collect();
+    if !page_ids.is_empty() {
+        report.pages_checked = true;
+        if report.pages != page_ids.len() as i64 { report.problems.push(format!("Records {} pages, but the document has {}.", report.pages, page_ids.len())); }
+        if page_ids[0].0 as i64 != int(b"O") { report.problems.push("The first page object is not the one recorded.".to_string()); }
+    }
+    Ok(report)
+}
+
+// Keys and numbers of the linearization dictionary, if the first object in the data is one.
+fn linearization_params(data: &[u8]) -> Option<HashMap<Vec<u8>, Vec<i64>>> {
+    first_object_id(data)?;
+    let start = data.windows(3).position(|w| w == b"obj")? + 3;
+    let end = start + data[start..].windows(6).position(|w| w == b"endobj")?;
+    let mut params = HashMap::new();
+    for entry in data[start..end].split(|&b| b == b'/').skip(1) {
+        let key_len = entry.iter().position(|b| !b.is_ascii_alphanumeric()).unwrap_or(entry.len());
+        let values = entry[key_len..].split(|b| !b.is_ascii_digit()).filter(|w| !w.is_empty())
+            .filter_map(|w| std::str::from_utf8(w).ok()?.parse().ok()).collect();
+        params.insert(entry[..key_len].to_vec(), values);
+    }
+    params.contains_key(&b"Linearized"[..]).then_some(params)
+}
+
+// Object number and generation of the first "n g obj" in the data.
+fn first_object_id(data: &[u8]) -> Option<ObjectId> {
+    let end = data.windows(3).position(|w| w == b"obj")?;
+    let mut words = data[..end].split(|b| b.is_ascii_whitespace()).filter(|w| !w.is_empty()).rev();
+    let generation = std::str::from_utf8(words.next()?).ok()?.parse().ok()?;
+    let number = std::str::from_utf8(words.next()?).ok()?.parse().ok()?;
+    Some((number, generation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::tests::{sample_document, temp_path, write_sample};
+
+    #[test]
+    fn linearized_output_passes_the_check() {
+        let mut data = Vec::new();
+        write_linearized(&sample_document(5), &mut data, None).unwrap();
+        let report = check_linearized(&data).unwrap();
+        assert!(report.linearized);
+        assert_eq!(report.problems, Vec::<String>::new());
+        assert!(report.pages_checked);
+        assert_eq!(report.pages, 5);
+    }
+
+    #[test]
+    fn appended_updates_break_linearization() {
+        let mut data = Vec::new();
+        write_linearized(&sample_document(2), &mut data, None).unwrap();
+        data.extend(b"\n% appended\n");
+        assert!(!check_linearized(&data).unwrap().problems.is_empty());
+    }
+
+    #[test]
+    fn checks_encrypted_files_without_the_password() {
+        let input = write_sample("linearize-encrypt-in.pdf", 3);
+        let output = temp_path("linearize-encrypt-out.pdf");
+        pdf::encrypt_pdf(&input, output.clone(), "secret".to_string(), &pdf::OutputOptions { linearize: true }).unwrap();
+        let report = check_linearized(&std::fs::read(&output).unwrap()).unwrap();
+        assert!(report.linearized);
+        assert_eq!(report.pages, 3);
+        assert!(!report.pages_checked);
+        assert!(report.summary().contains("not checked"));
+    }
+}
@@ -1,10 +1,27 @@
+mod bounds;
+mod cli;
+mod compress;
+mod fonts;
+mod linearize;
+mod orient;
+mod pages;
 mod pdf;
+mod stamp;
 mod ui;
 
 use gtk::prelude::*;
 use gtk::{Notebook, Window, WindowType};
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(e) = cli::run(&args) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     if gtk::init().is_err() {
         println!("Failed to initialize GTK.");
         return;
@@ -21,6 +38,9 @@ fn main() {
     ui::create_split_tab(&notebook, &window);
     ui::create_compress_tab(&notebook, &window);
     ui::create_rotate_tab(&notebook, &window);
+    ui::create_crop_tab(&notebook, &window);
+    ui::create_layout_tab(&notebook, &window);
+    ui::create_stamp_tab(&notebook, &window);
     ui::create_tools_tab(&notebook, &window);
     ui::create_organize_tab(&notebook, &window);
     ui::create_security_tab(&notebook, &window);
@@ -0,0 +1,170 @@
+use lopdf::{Document, Object, ObjectId};
+use image::GrayImage;
+use image::imageops::{self, FilterType};
+use crate::compress;
+use crate::pdf;
+
+// Share of the page a single image must cover for the page to count as a scan.
+const SCAN_COVERAGE: f32 = 0.7;
+// Scans are analysed at this size; text lines stay several pixels tall.
+const ANALYSIS_SIZE: u32 = 1200;
+// Minimum number of text lines needed before trusting a detected direction.
+const MIN_LINES: usize = 3;
+
+// The Rotate value that shows a scanned page's text upright, or None when the page is not a scan or its text
+// direction is unclear.
+pub fn upright_rotation(doc: &Document, page_id: ObjectId) -> Option<i64> {
+    let [x0, y0, x1, y1] = pdf::page_box(doc, page_id)?;
+    let page_area = (x1 - x0) * (y1 - y0);
+    let mut scan = None;
+    let mut largest = 0.0;
+    compress::visit_page(doc, page_id, false, &mut |op, state| {
+        if op.operator != "Do" { return; }
+        let Some(name) = op.operands.first().and_then(|o| o.as_name().ok()) else { return };
+        let Some((_, stream)) = compress::xobject(doc, state.resources, name) else { return };
+        if stream.dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Image") { return; }
+        let ctm = state.ctm;
+        let area = (ctm[0] * ctm[3] - ctm[1] * ctm[2]).abs();
+        if area > largest { largest = area; scan = Some((stream, ctm)); }
+    });
+    if largest < page_area * SCAN_COVERAGE { return None; }
+    let (stream, ctm) = scan?;
+    let img = compress::decode_image(doc, stream)?.resize(ANALYSIS_SIZE, ANALYSIS_SIZE, FilterType::Triangle).to_luma8();
+    let text = text_rotation(&img)?;
+    // Clockwise turn of the image's top edge on the page, from where the image's up direction points.
+    let (up_x, up_y) = (ctm[2], ctm[3]);
+    let placed = if up_y.abs() >= up_x.abs() { if up_y > 0.0 { 0 } else { 180 } } else if up_x > 0.0 { 90 } else { 270 };
+    Some((-(text + placed)).rem_euclid(360))
+}
+
+// Clockwise angle the text in the image is turned by. Lines of text make the ink profile across them far more
+// uneven than the profile along them; ascenders, which outnumber descenders in Latin text, then tell up from down.
+fn text_rotation(img: &GrayImage) -> Option<i64> {
+    let mean = img.pixels().map(|p| p.0[0] as u64).sum::<u64>() / (img.width() as u64 * img.height() as u64).max(1);
+    let threshold = (mean as f32 * 0.6) as u8;
+    let rows = ink_profile(img, threshold, true);
+    let columns = ink_profile(img, threshold, false);
+    let horizontal = unevenness(&rows) >= unevenness(&columns);
+    let (lines, score) = if horizontal { line_direction(&rows, img.width()) } else {
+        // Turned a quarter clockwise, text at 90 degrees ends up upside down and text at 270 upright.
+        let turned = imageops::rotate90(img);
+        line_direction(&ink_profile(&turned, threshold, true), turned.width())
+    };
+    if lines < MIN_LINES || (score.unsigned_abs() as usize) < (lines / 4).max(2) { return None; }
+    Some(match (horizontal, score > 0) {
+        (true, true) => 0,
+        (true, false) => 180,
+        (false, true) => 270,
+        (false, false) => 90,
+    })
+}
+
+// Dark pixels per row, or per column.
+fn ink_profile(img: &GrayImage, threshold: u8, by_row: bool) -> Vec<u32> {
+    let mut profile = vec![0; if by_row { img.height() } else { img.width() } as usize];
+    for (x, y, pixel) in img.enumerate_pixels() {
+        if pixel.0[0] < threshold { profile[if by_row { y } else { x } as usize] += 1; }
+    }
+    profile
+}
+
+// Variance relative to the squared mean.
+fn unevenness(profile: &[u32]) -> f64 {
+    let n = profile.len().max(1) as f64;
+    let mean = profile.iter().map(|&v| v as f64).sum::<f64>() / n;
+    if mean == 0.0 { return 0.0; }
+    profile.iter().map(|&v| (v as f64 - mean).powi(2)).sum::<f64>() / n / (mean * mean)
+}
+
+// Number of text lines across the row profile, and how many more of them reach further above their dense
+// middle band than below it.
+fn line_direction(rows: &[u32], width: u32) -> (usize, i64) {
+    let floor = (width / 200).max(2);
+    let (mut lines, mut score) = (0, 0);
+    let mut y = 0;
+    while y < rows.len() {
+        if rows[y] <= floor { y += 1; continue; }
+        let start = y;
+        while y < rows.len() && rows[y] > floor { y += 1; }
+        let band = &rows[start..y];
+        if band.len() < 5 { continue; }
+        let peak = *band.iter().max().unwrap_or(&0);
+        let first = band.iter().position(|&v| v * 2 >= peak).unwrap_or(0);
+        let last = band.iter().rposition(|&v| v * 2 >= peak).unwrap_or(0);
+        lines += 1;
+        score += (first as i64 - (band.len() - 1 - last) as i64).signum();
+    }
+    (lines, score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Stream};
+    use image::Luma;
+
+    // Lines of upright text: a dense band of lowercase letters, ascenders above it and fewer descenders below.
+    fn text_lines() -> GrayImage {
+        GrayImage::from_fn(600, 420, |x, y| {
+            let (line, row) = (y / 35, y % 35);
+            // Words move along each line, so the gaps between them do not line up down the page.
+            let in_word = (x + line * 37) % 50 < 40 && (20..580).contains(&x) && (1..11).contains(&line);
+            let ink = in_word && match row {
+                0..=5 => x % 9 < 2,
+                6..=15 => x % 3 != 0,
+                16..=18 => x % 31 < 2,
+                _ => false,
+            };
+            Luma([if ink { 0 } else { 255 }])
+        })
+    }
+
+    #[test]
+    fn finds_the_direction_of_text_lines() {
+        let img = text_lines();
+        assert_eq!(text_rotation(&img), Some(0));
+        assert_eq!(text_rotation(&imageops::rotate90(&img)), Some(90));
+        assert_eq!(text_rotation(&imageops::rotate180(&img)), Some(180));
+        assert_eq!(text_rotation(&imageops::rotate270(&img)), Some(270));
+    }
+
+    #[test]
+    fn gives_no_direction_without_enough_lines() {
+        assert_eq!(text_rotation(&GrayImage::from_pixel(300, 300, Luma([255]))), None);
+        let lines = text_lines();
+        let one_line = GrayImage::from_fn(600, 420, |x, y| if y < 35 { lines[(x, y)] } else { Luma([255]) });
+        assert_eq!(text_rotation(&one_line), None);
+    }
+
+    // A page showing the image across its whole MediaBox.
+    fn scanned_page(img: &GrayImage) -> (Document, ObjectId) {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let (width, height) = (img.width() as i64, img.height() as i64);
+        let image_id = doc.add_object(Stream::new(dictionary! {
+            "Type" => "XObject", "Subtype" => "Image", "Width" => width, "Height" => height,
+            "ColorSpace" => "DeviceGray", "BitsPerComponent" => 8,
+        }, img.as_raw().clone()));
+        let content_id = doc.add_object(Stream::new(dictionary! {}, format!("q {} 0 0 {} 0 0 cm /Scan Do Q", width, height).into_bytes()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page", "Parent" => pages_id, "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+            "Contents" => content_id, "Resources" => dictionary! { "XObject" => dictionary! { "Scan" => image_id } },
+        });
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => vec![page_id.into()], "Count" => 1 }));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        (doc, page_id)
+    }
+
+    #[test]
+    fn turns_scanned_pages_so_their_text_reads_upright() {
+        let img = text_lines();
+        let (doc, page_id) = scanned_page(&img);
+        assert_eq!(upright_rotation(&doc, page_id), Some(0));
+        // Text scanned a quarter turn clockwise needs the page turned a quarter back.
+        let (doc, page_id) = scanned_page(&imageops::rotate90(&img));
+        assert_eq!(upright_rotation(&doc, page_id), Some(270));
+        let (doc, page_id) = scanned_page(&imageops::rotate180(&img));
+        assert_eq!(upright_rotation(&doc, page_id), Some(180));
+    }
+}
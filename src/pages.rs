@@ -0,0 +1,539 @@
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use lopdf::content::{Content, Operation};
+use std::collections::HashMap;
+use crate::compress::{self, Matrix};
+use crate::pdf::{self, FitMode};
+
+// Page boxes, which all live in the page's user space.
+const PAGE_BOXES: [&[u8]; 5] = [b"MediaBox", b"CropBox", b"BleedBox", b"TrimBox", b"ArtBox"];
+// Annotation entries holding flat lists of x, y coordinates.
+const ANNOTATION_POINTS: [&[u8]; 4] = [b"QuadPoints", b"Vertices", b"L", b"CL"];
+// Annotation flag that keeps an annotation upright when the page is rotated.
+const NO_ROTATE: i64 = 16;
+
+// A rectangle as [left, bottom, right, top].
+pub fn rectangle(doc: &Document, object: &Object) -> Option<[f32; 4]> {
+    let values: Vec<f32> = doc.dereference(object).ok()?.1.as_array().ok()?.iter().filter_map(|v| v.as_float().ok()).collect();
+    let [a, b, c, d] = values[..] else { return None };
+    Some([a.min(c), b.min(d), a.max(c), b.max(d)])
+}
+
+pub fn transform(m: &Matrix, x: f32, y: f32) -> (f32, f32) {
+    (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
+
+pub fn invert(m: &Matrix) -> Matrix {
+    let det = m[0] * m[3] - m[1] * m[2];
+    let (a, b, c, d) = (m[3] / det, -m[1] / det, -m[2] / det, m[0] / det);
+    [a, b, c, d, -(m[4] * a + m[5] * c), -(m[4] * b + m[5] * d)]
+}
+
+pub fn transform_rect(m: &Matrix, rect: [f32; 4]) -> [f32; 4] {
+    let (ax, ay) = transform(m, rect[0], rect[1]);
+    let (bx, by) = transform(m, rect[2], rect[3]);
+    [ax.min(bx), ay.min(by), ax.max(bx), ay.max(by)]
+}
+
+fn rect_object(rect: [f32; 4]) -> Object {
+    Object::Array(rect.iter().map(|&v| Object::Real(v)).collect())
+}
+
+// The rectangle less margins given as [left, bottom, right, top] of the page as displayed with its rotation.
+pub fn inset(rect: [f32; 4], margins: [f32; 4], rotation: i64) -> [f32; 4] {
+    // Turning the page a quarter clockwise shows its left edge at the top, its bottom edge on the left and so on.
+    let quarters = (rotation.rem_euclid(360) / 90) as usize;
+    let m: [f32; 4] = std::array::from_fn(|i| margins[(i + 4 - quarters) % 4]);
+    [rect[0] + m[0], rect[1] + m[1], rect[2] - m[2], rect[3] - m[3]]
+}
+
+// Maps user space onto the page as displayed with its rotation, measured in points from the top left corner of
+// the rectangle.
+pub fn display_matrix(rect: [f32; 4], rotation: i64) -> Matrix {
+    let [x0, y0, x1, y1] = rect;
+    match rotation.rem_euclid(360) {
+        90 => [0.0, 1.0, 1.0, 0.0, -y0, -x0],
+        180 => [-1.0, 0.0, 0.0, 1.0, x1, -y0],
+        270 => [0.0, -1.0, -1.0, 0.0, y1, x1],
+        _ => [1.0, 0.0, 0.0, -1.0, -x0, y1],
+    }
+}
+
+// Maps user space onto the page as displayed with its rotation, with the lower left corner of the rectangle at
+// the origin.
+pub fn upright_matrix(rect: [f32; 4], rotation: i64) -> Matrix {
+    let [x0, y0, x1, y1] = rect;
+    match rotation.rem_euclid(360) {
+        90 => [0.0, -1.0, 1.0, 0.0, -y0, x1],
+        180 => [-1.0, 0.0, 0.0, -1.0, x1, y1],
+        270 => [0.0, 1.0, -1.0, 0.0, y1, -x0],
+        _ => [1.0, 0.0, 0.0, 1.0, -x0, -y0],
+    }
+}
+
+// Width and height of the page's visible area as displayed.
+pub fn displayed_size(doc: &Document, page_id: ObjectId) -> Option<(f32, f32)> {
+    let [x0, y0, x1, y1] = pdf::page_box(doc, page_id)?;
+    let rotation = pdf::page_rotation(doc, page_id);
+    Some(if rotation.rem_euclid(180) == 90 { (y1 - y0, x1 - x0) } else { (x1 - x0, y1 - y0) })
+}
+
+// Sets the page's CropBox, and with `media_box` its MediaBox too, cutting the other boxes down to fit.
+pub fn crop_page(doc: &mut Document, page_id: ObjectId, rect: [f32; 4], media_box: bool) -> Result<(), Box<dyn std::error::Error>> {
+    pdf::inherit_page_attributes(doc, page_id)?;
+    let page = doc.get_dictionary(page_id)?;
+    let boxes: Vec<(&[u8], Option<[f32; 4]>)> = PAGE_BOXES[2..].iter().filter_map(|&key| {
+        let [x0, y0, x1, y1] = rectangle(doc, page.get(key).ok()?)?;
+        let clipped = [x0.max(rect[0]), y0.max(rect[1]), x1.min(rect[2]), y1.min(rect[3])];
+        Some((key, (clipped[0] < clipped[2] && clipped[1] < clipped[3]).then_some(clipped)))
+    }).collect();
+    let page = doc.get_dictionary_mut(page_id)?;
+    page.set("CropBox", rect_object(rect));
+    if !media_box { return Ok(()); }
+    page.set("MediaBox", rect_object(rect));
+    for (key, clipped) in boxes {
+        match clipped {
+            Some(clipped) => page.set(key, rect_object(clipped)),
+            None => { page.remove(key); }
+        }
+    }
+    Ok(())
+}
+
+// Cuts the page into two pages showing the given parts of it: the page keeps the first part and a copy sharing its
+// content gets the second, which is returned. Annotations go to the part holding the middle of their Rect.
+pub fn split_page(doc: &mut Document, page_id: ObjectId, first: [f32; 4], second: [f32; 4]) -> Result<ObjectId, Box<dyn std::error::Error>> {
+    pdf::inherit_page_attributes(doc, page_id)?;
+    let (mut kept, mut moved) = (Vec::new(), Vec::new());
+    for id in annotation_ids(doc, page_id) {
+        let rect = doc.get_dictionary(id).ok().and_then(|a| rectangle(doc, a.get(b"Rect").ok()?));
+        let in_second = rect.is_some_and(|[x0, y0, x1, y1]| {
+            let (x, y) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+            x >= second[0] && x <= second[2] && y >= second[1] && y <= second[3]
+        });
+        if in_second { moved.push(id) } else { kept.push(id) }
+    }
+    let mut copy = doc.get_dictionary(page_id)?.clone();
+    // Thumbnails, article beads and the structure tree entry belong to the original page only.
+    for key in [&b"Thumb"[..], b"B", b"StructParents"] { copy.remove(key); }
+    copy.set("Annots", moved.iter().map(|&id| Object::Reference(id)).collect::<Vec<_>>());
+    let copy_id = doc.add_object(copy);
+    for &id in &moved {
+        if let Ok(annotation) = doc.get_dictionary_mut(id) && annotation.has(b"P") { annotation.set("P", copy_id); }
+    }
+    let page = doc.get_dictionary_mut(page_id)?;
+    if page.has(b"Annots") { page.set("Annots", kept.into_iter().map(Object::Reference).collect::<Vec<_>>()); }
+    for (id, rect) in [(page_id, first), (copy_id, second)] {
+        crop_page(doc, id, rect, true)?;
+        let page = doc.get_dictionary_mut(id)?;
+        if page.get(b"Annots").and_then(Object::as_array).is_ok_and(|a| a.is_empty()) { page.remove(b"Annots"); }
+    }
+    Ok(copy_id)
+}
+
+// Adds a copy of the page with copies of its annotations, so both pages can sit in the page tree without sharing
+// annotations whose P entry can only name one of them. Links between the annotations, such as popups, follow
+// the copies.
+pub fn copy_page(doc: &mut Document, page_id: ObjectId) -> Result<ObjectId, Box<dyn std::error::Error>> {
+    let mut copy = doc.get_dictionary(page_id)?.clone();
+    // Thumbnails, article beads and the structure tree entry belong to the original page only.
+    for key in [&b"Thumb"[..], b"B", b"StructParents"] { copy.remove(key); }
+    let copy_id = doc.new_object_id();
+    let annots = annotation_ids(doc, page_id);
+    let copies: HashMap<ObjectId, ObjectId> = annots.iter().map(|&id| (id, doc.new_object_id())).collect();
+    for &id in &annots {
+        let Ok(mut annotation) = doc.get_dictionary(id).cloned() else { continue };
+        for key in [&b"Popup"[..], b"Parent", b"IRT"] {
+            if let Some(&linked) = annotation.get(key).and_then(Object::as_reference).ok().and_then(|l| copies.get(&l)) { annotation.set(key, linked); }
+        }
+        annotation.set("P", copy_id);
+        annotation.remove(b"StructParent");
+        doc.objects.insert(copies[&id], Object::Dictionary(annotation));
+    }
+    let listed = copy.get(b"Annots").ok().and_then(|a| doc.dereference(a).ok()).and_then(|(_, a)| a.as_array().ok().cloned());
+    if let Some(listed) = listed {
+        let listed: Vec<Object> = listed.into_iter().map(|annot| match annot {
+            Object::Reference(id) => copies.get(&id).map_or(Object::Reference(id), |&c| Object::Reference(c)),
+            Object::Dictionary(mut annotation) => {
+                annotation.set("P", copy_id);
+                Object::Dictionary(annotation)
+            }
+            other => other,
+        }).collect();
+        copy.set("Annots", listed);
+    }
+    doc.objects.insert(copy_id, Object::Dictionary(copy));
+    Ok(copy_id)
+}
+
+// Draws the page's content turned the way its Rotate entry shows it and sets Rotate to 0, so the page looks the
+// same to tools that ignore Rotate. Returns false if the page was not rotated.
+pub fn bake_rotation(doc: &mut Document, page_id: ObjectId) -> Result<bool, Box<dyn std::error::Error>> {
+    pdf::inherit_page_attributes(doc, page_id)?;
+    let page = doc.get_dictionary(page_id)?;
+    let rotation = page.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0).rem_euclid(360);
+    if rotation % 90 != 0 { return Err(format!("Page rotation of {} degrees is not a multiple of 90.", rotation).into()); }
+    if rotation == 0 {
+        doc.get_dictionary_mut(page_id)?.set("Rotate", 0);
+        return Ok(false);
+    }
+    let media_box = page.get(b"MediaBox").ok().and_then(|b| rectangle(doc, b)).ok_or("The page has no MediaBox.")?;
+    let matrix = upright_matrix(media_box, rotation);
+    let turn = [matrix[0], matrix[1], matrix[2], matrix[3], 0.0, 0.0];
+    let boxes: Vec<(&[u8], [f32; 4])> = PAGE_BOXES.iter().filter_map(|&key| Some((key, transform_rect(&matrix, rectangle(doc, page.get(key).ok()?)?)))).collect();
+    let annotations = annotation_ids(doc, page_id);
+
+    let prefix = Content { operations: vec![Operation::new("q", vec![]), Operation::new("cm", matrix.iter().map(|&v| v.into()).collect())] };
+    let prefix = doc.add_object(Stream::new(Dictionary::new(), [prefix.encode()?, b"\n".to_vec()].concat()));
+    let suffix = doc.add_object(Stream::new(Dictionary::new(), b"\nQ".to_vec()));
+    let contents: Vec<Object> = std::iter::once(prefix).chain(doc.get_page_contents(page_id)).chain(std::iter::once(suffix)).map(Object::Reference).collect();
+    for id in annotations { transform_annotation(doc, id, &matrix, Some(&turn)); }
+    let page = doc.get_dictionary_mut(page_id)?;
+    page.set("Contents", contents);
+    for (key, rect) in boxes { page.set(key, rect_object(rect)); }
+    page.set("Rotate", 0);
+    Ok(true)
+}
+
+// A Form XObject drawing the page's content, clipped to its visible area.
+pub fn page_form(doc: &Document, page_id: ObjectId) -> Result<Stream, Box<dyn std::error::Error>> {
+    let rect = pdf::page_box(doc, page_id).ok_or("The page has no MediaBox.")?;
+    let content = compress::page_content(doc, page_id).ok_or("The page content cannot be read.")?;
+    let page = doc.get_dictionary(page_id)?;
+    let mut dict = dictionary! { "Type" => "XObject", "Subtype" => "Form", "BBox" => rect_object(rect) };
+    dict.set("Resources", compress::page_resources(doc, page_id).cloned().unwrap_or_default());
+    // Transparency groups keep blending the content the same way inside the form.
+    if let Ok(group) = page.get(b"Group") { dict.set("Group", group.clone()); }
+    let mut form = Stream::new(dict, content);
+    form.compress()?;
+    Ok(form)
+}
+
+// Scales the page's content onto paper of the given size, turned to match the page's shape, and returns false if
+// the page already was that size. Rotation is baked in first so the paper matches the page as displayed.
+pub fn fit_page(doc: &mut Document, page_id: ObjectId, paper: (f32, f32), mode: FitMode) -> Result<bool, Box<dyn std::error::Error>> {
+    let baked = bake_rotation(doc, page_id)?;
+    let [x0, y0, x1, y1] = pdf::page_box(doc, page_id).ok_or("The page has no MediaBox.")?;
+    let (width, height) = (x1 - x0, y1 - y0);
+    let (paper_width, paper_height) = if (width > height) == (paper.0 > paper.1) { paper } else { (paper.1, paper.0) };
+    let scale = match mode {
+        FitMode::Fit => (paper_width / width).min(paper_height / height),
+        FitMode::Fill => (paper_width / width).max(paper_height / height),
+        FitMode::Center => 1.0,
+    };
+    let matrix = [scale, 0.0, 0.0, scale, (paper_width - width * scale) / 2.0 - x0 * scale, (paper_height - height * scale) / 2.0 - y0 * scale];
+    let paper_rect = [0.0, 0.0, paper_width, paper_height];
+    if matrix == compress::IDENTITY && rectangle(doc, doc.get_dictionary(page_id)?.get(b"MediaBox")?) == Some(paper_rect) { return Ok(baked); }
+
+    let mut form = page_form(doc, page_id)?;
+    form.dict.set("Matrix", matrix.iter().map(|&v| Object::Real(v)).collect::<Vec<_>>());
+    let form_id = doc.add_object(form);
+    let content = doc.add_object(Stream::new(Dictionary::new(), b"/Page Do".to_vec()));
+    let page = doc.get_dictionary(page_id)?;
+    let boxes: Vec<(&[u8], Option<[f32; 4]>)> = PAGE_BOXES[2..].iter().filter_map(|&key| {
+        let [bx0, by0, bx1, by1] = transform_rect(&matrix, rectangle(doc, page.get(key).ok()?)?);
+        let clipped = [bx0.max(0.0), by0.max(0.0), bx1.min(paper_width), by1.min(paper_height)];
+        Some((key, (clipped[0] < clipped[2] && clipped[1] < clipped[3]).then_some(clipped)))
+    }).collect();
+    for id in annotation_ids(doc, page_id) { transform_annotation(doc, id, &matrix, None); }
+    let page = doc.get_dictionary_mut(page_id)?;
+    page.set("Contents", content);
+    page.set("Resources", dictionary! { "XObject" => dictionary! { "Page" => form_id } });
+    page.set("MediaBox", rect_object(paper_rect));
+    page.remove(b"CropBox");
+    for (key, rect) in boxes {
+        match rect {
+            Some(rect) => page.set(key, rect_object(rect)),
+            None => { page.remove(key); }
+        }
+    }
+    Ok(true)
+}
+
+// A page drawn on a sheet: `matrix` maps the page as displayed, with its lower left corner at the origin, onto the
+// sheet, and `clip` limits it to a rectangle of the sheet.
+pub struct Placement {
+    pub page: ObjectId,
+    pub matrix: Matrix,
+    pub clip: Option<[f32; 4]>,
+}
+
+// The matrix that scales a displayed page of the given size to fit inside the cell, centered.
+pub fn fit_into(size: (f32, f32), cell: [f32; 4]) -> Matrix {
+    let (width, height) = (cell[2] - cell[0], cell[3] - cell[1]);
+    let scale = (width / size.0).min(height / size.1);
+    [scale, 0.0, 0.0, scale, cell[0] + (width - size.0 * scale) / 2.0, cell[1] + (height - size.1 * scale) / 2.0]
+}
+
+// The page as a Form XObject showing it as displayed, with its lower left corner at the origin, and its displayed
+// size. Forms are kept in `forms` so each page is converted once.
+pub fn upright_form(doc: &mut Document, forms: &mut HashMap<ObjectId, (ObjectId, (f32, f32))>, page_id: ObjectId) -> Result<(ObjectId, (f32, f32)), Box<dyn std::error::Error>> {
+    if let Some(&form) = forms.get(&page_id) { return Ok(form); }
+    let rotation = pdf::page_rotation(doc, page_id);
+    let mut form = page_form(doc, page_id)?;
+    let rect = pdf::page_box(doc, page_id).ok_or("The page has no MediaBox.")?;
+    form.dict.set("Matrix", upright_matrix(rect, rotation).iter().map(|&v| Object::Real(v)).collect::<Vec<_>>());
+    let form = (doc.add_object(form), displayed_size(doc, page_id).ok_or("The page has no MediaBox.")?);
+    forms.insert(page_id, form);
+    Ok(form)
+}
+
+// Builds a new page of the given size drawing the placed pages, optionally outlined, and returns its id. Each
+// source page becomes one Form XObject, kept in `forms` for reuse across sheets. The sheet is not added to the page
+// tree, and the placed pages' annotations are not carried over.
+pub fn sheet(doc: &mut Document, forms: &mut HashMap<ObjectId, (ObjectId, (f32, f32))>, size: (f32, f32), placements: &[Placement], borders: bool) -> Result<ObjectId, Box<dyn std::error::Error>> {
+    let mut operations = Vec::new();
+    let mut xobjects = Dictionary::new();
+    for placement in placements {
+        let (form_id, (width, height)) = upright_form(doc, forms, placement.page)?;
+        let name = format!("P{}", form_id.0);
+        xobjects.set(name.clone(), form_id);
+        let m = placement.matrix;
+        operations.push(Operation::new("q", vec![]));
+        if let Some([x0, y0, x1, y1]) = placement.clip {
+            operations.push(Operation::new("re", vec![x0.into(), y0.into(), (x1 - x0).into(), (y1 - y0).into()]));
+            operations.push(Operation::new("W", vec![]));
+            operations.push(Operation::new("n", vec![]));
+        }
+        operations.push(Operation::new("cm", m.iter().map(|&v| v.into()).collect()));
+        operations.push(Operation::new("Do", vec![Object::Name(name.into_bytes())]));
+        if borders {
+            // Half a point wide on the sheet, whatever the page is scaled by.
+            let scale = (m[0] * m[3] - m[1] * m[2]).abs().sqrt();
+            operations.push(Operation::new("w", vec![(0.5 / scale).into()]));
+            operations.push(Operation::new("re", vec![0.into(), 0.into(), width.into(), height.into()]));
+            operations.push(Operation::new("S", vec![]));
+        }
+        operations.push(Operation::new("Q", vec![]));
+    }
+    let mut content = Stream::new(Dictionary::new(), Content { operations }.encode()?);
+    content.compress()?;
+    let content_id = doc.add_object(content);
+    Ok(doc.add_object(dictionary! {
+        "Type" => "Page",
+        "MediaBox" => rect_object([0.0, 0.0, size.0, size.1]),
+        "Contents" => content_id,
+        "Resources" => dictionary! { "XObject" => xobjects },
+    }))
+}
+
+// Draws `operations` over the page's content, or under it, in default user space. The page's own content is
+// wrapped in q/Q so its graphics state does not carry over. `resources` maps categories to the named resources the
+// operations use; names already taken on the page by something else are renamed in the operations.
+pub fn add_content(doc: &mut Document, page_id: ObjectId, mut operations: Vec<Operation>, resources: &Dictionary, under: bool) -> Result<(), Box<dyn std::error::Error>> {
+    pdf::inherit_page_attributes(doc, page_id)?;
+    // The page gets its own copy, since resource dictionaries are often shared between pages.
+    let mut page_resources = compress::page_resources(doc, page_id).cloned().unwrap_or_default();
+    for (category, entries) in resources.iter() {
+        let Ok(entries) = entries.as_dict() else { continue };
+        let mut existing = page_resources.get(category).ok().and_then(|c| compress::resolve_dict(doc, c)).cloned().unwrap_or_default();
+        for (name, value) in entries.iter() {
+            let mut unique = name.clone();
+            let mut n = 1;
+            while existing.get(&unique).is_ok_and(|v| v != value) {
+                unique = [name.as_slice(), format!("_{}", n).as_bytes()].concat();
+                n += 1;
+            }
+            if unique != *name {
+                for op in &mut operations {
+                    for operand in &mut op.operands {
+                        if operand.as_name().ok() == Some(name.as_slice()) { *operand = Object::Name(unique.clone()); }
+                    }
+                }
+            }
+            existing.set(unique, value.clone());
+        }
+        page_resources.set(category.clone(), existing);
+    }
+    let added = [b"q\n".to_vec(), Content { operations }.encode()?, b"\nQ\n".to_vec()].concat();
+    let added = doc.add_object(Stream::new(Dictionary::new(), added));
+    let open = doc.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()));
+    let close = doc.add_object(Stream::new(Dictionary::new(), b"\nQ\n".to_vec()));
+    let existing = std::iter::once(open).chain(doc.get_page_contents(page_id)).chain(std::iter::once(close));
+    let contents: Vec<Object> = if under { std::iter::once(added).chain(existing).collect::<Vec<_>>() } else { existing.chain(std::iter::once(added)).collect() }
+        .into_iter().map(Object::Reference).collect();
+    let page = doc.get_dictionary_mut(page_id)?;
+    page.set("Contents", contents);
+    page.set("Resources", page_resources);
+    Ok(())
+}
+
+fn annotation_ids(doc: &Document, page_id: ObjectId) -> Vec<ObjectId> {
+    doc.get_dictionary(page_id).ok().and_then(|p| p.get(b"Annots").ok()).and_then(|a| doc.dereference(a).ok()?.1.as_array().ok())
+        .map(|a| a.iter().filter_map(|o| o.as_reference().ok()).collect()).unwrap_or_default()
+}
+
+// Moves an annotation along with page content drawn through `matrix`. Appearances are fitted into Rect anyway and
+// only need the `turn`, if the content was turned.
+fn transform_annotation(doc: &mut Document, id: ObjectId, matrix: &Matrix, turn: Option<&Matrix>) {
+    let Ok(mut annotation) = doc.get_dictionary(id).cloned() else { return };
+    let no_rotate = annotation.get(b"F").and_then(Object::as_i64).unwrap_or(0) & NO_ROTATE != 0;
+    if let Some(rect) = annotation.get(b"Rect").ok().and_then(|r| rectangle(doc, r)) {
+        let rect = if no_rotate {
+            // Upright annotations keep their size, pinned at their upper left corner.
+            let (x, y) = transform(matrix, rect[0], rect[3]);
+            [x, y - (rect[3] - rect[1]), x + (rect[2] - rect[0]), y]
+        } else {
+            transform_rect(matrix, rect)
+        };
+        annotation.set("Rect", rect_object(rect));
+    }
+    for key in ANNOTATION_POINTS {
+        if let Ok(Object::Array(points)) = annotation.get(key) { annotation.set(key, turn_points(points, matrix)); }
+    }
+    if let Ok(Object::Array(ink)) = annotation.get(b"InkList") {
+        let ink: Vec<Object> = ink.iter().map(|path| path.as_array().map_or_else(|_| path.clone(), |points| turn_points(points, matrix))).collect();
+        annotation.set("InkList", ink);
+    }
+    // Appearances are fitted into Rect as drawn, so turning them keeps them matching the turned page.
+    if !no_rotate && let Some(turn) = turn && let Some(appearances) = annotation.get(b"AP").ok().and_then(|ap| compress::resolve_dict(doc, ap)).cloned() {
+        let mut turned = Dictionary::new();
+        for (key, value) in appearances.iter() {
+            let value = match doc.dereference(value) {
+                Ok((_, Object::Stream(stream))) => turned_appearance(doc, stream.clone(), turn),
+                Ok((_, Object::Dictionary(states))) => {
+                    let states: Vec<(Vec<u8>, Object)> = states.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+                    let mut turned_states = Dictionary::new();
+                    for (state, value) in states {
+                        let value = match doc.dereference(&value) { Ok((_, Object::Stream(stream))) => turned_appearance(doc, stream.clone(), turn), _ => value };
+                        turned_states.set(state, value);
+                    }
+                    Object::Dictionary(turned_states)
+                }
+                _ => value.clone(),
+            };
+            turned.set(key.clone(), value);
+        }
+        annotation.set("AP", turned);
+    }
+    doc.objects.insert(id, Object::Dictionary(annotation));
+}
+
+fn turn_points(points: &[Object], matrix: &Matrix) -> Object {
+    let values: Vec<f32> = points.iter().filter_map(|v| v.as_float().ok()).collect();
+    if values.len() != points.len() || !values.len().is_multiple_of(2) { return Object::Array(points.to_vec()); }
+    Object::Array(values.chunks(2).flat_map(|p| { let (x, y) = transform(matrix, p[0], p[1]); [Object::Real(x), Object::Real(y)] }).collect())
+}
+
+// A copy of the appearance stream with the turn added to its Matrix; the original may be shared.
+fn turned_appearance(doc: &mut Document, mut stream: Stream, turn: &Matrix) -> Object {
+    let matrix = stream.dict.get(b"Matrix").and_then(Object::as_array).ok().and_then(|m| compress::to_matrix(m)).unwrap_or(compress::IDENTITY);
+    stream.dict.set("Matrix", compress::multiply(&matrix, turn).iter().map(|&v| Object::Real(v)).collect::<Vec<_>>());
+    Object::Reference(doc.add_object(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::tests::sample_document;
+
+    fn close(a: [f32; 4], b: [f32; 4]) -> bool {
+        a.iter().zip(b).all(|(x, y)| (x - y).abs() < 0.01)
+    }
+
+    // Where a point of user space shows up on the page as displayed, from the top left corner.
+    fn displayed(doc: &Document, page_id: ObjectId, (x, y): (f32, f32)) -> (f32, f32) {
+        transform(&display_matrix(pdf::page_box(doc, page_id).unwrap(), pdf::page_rotation(doc, page_id)), x, y)
+    }
+
+    fn displayed_rect(doc: &Document, page_id: ObjectId, rect: [f32; 4]) -> [f32; 4] {
+        let (ax, ay) = displayed(doc, page_id, (rect[0], rect[1]));
+        let (bx, by) = displayed(doc, page_id, (rect[2], rect[3]));
+        [ax.min(bx), ay.min(by), ax.max(bx), ay.max(by)]
+    }
+
+    fn page_rect(doc: &Document, page_id: ObjectId, key: &[u8]) -> [f32; 4] {
+        rectangle(doc, doc.get_dictionary(page_id).unwrap().get(key).unwrap()).unwrap()
+    }
+
+    // A 600 by 800 page with a CropBox and one annotation.
+    fn annotated_page(rotation: i64) -> (Document, ObjectId, ObjectId) {
+        let mut doc = sample_document(1);
+        let page_id = doc.page_iter().next().unwrap();
+        let annotation = doc.add_object(dictionary! { "Type" => "Annot", "Subtype" => "Square", "Rect" => rect_object([100.0, 650.0, 200.0, 750.0]) });
+        let page = doc.get_dictionary_mut(page_id).unwrap();
+        page.set("MediaBox", rect_object([0.0, 0.0, 600.0, 800.0]));
+        page.set("CropBox", rect_object([10.0, 20.0, 590.0, 780.0]));
+        page.set("Annots", vec![annotation.into()]);
+        page.set("Rotate", rotation);
+        (doc, page_id, annotation)
+    }
+
+    #[test]
+    fn baking_rotation_keeps_the_page_looking_the_same() {
+        for rotation in [90, 180, 270] {
+            let (mut doc, page_id, annotation) = annotated_page(rotation);
+            let point = (150.0, 700.0);
+            let before_point = displayed(&doc, page_id, point);
+            let before_annotation = displayed_rect(&doc, page_id, page_rect(&doc, annotation, b"Rect"));
+            assert!(bake_rotation(&mut doc, page_id).unwrap());
+
+            assert_eq!(pdf::page_rotation(&doc, page_id), 0);
+            let media_box = if rotation == 180 { [0.0, 0.0, 600.0, 800.0] } else { [0.0, 0.0, 800.0, 600.0] };
+            assert!(close(page_rect(&doc, page_id, b"MediaBox"), media_box), "MediaBox at {}", rotation);
+            let [x0, y0, x1, y1] = page_rect(&doc, page_id, b"CropBox");
+            assert_eq!(((x1 - x0).round(), (y1 - y0).round()), if rotation == 180 { (580.0, 760.0) } else { (760.0, 580.0) });
+            // The content is drawn through the matrix the new first content stream sets up.
+            let prefix = doc.get_object(doc.get_page_contents(page_id)[0]).unwrap().as_stream().unwrap();
+            let cm = Content::decode(&prefix.content).unwrap().operations.into_iter().find(|op| op.operator == "cm").unwrap();
+            let matrix = compress::to_matrix(&cm.operands).unwrap();
+            let after_point = displayed(&doc, page_id, transform(&matrix, point.0, point.1));
+            assert!((after_point.0 - before_point.0).abs() < 0.01 && (after_point.1 - before_point.1).abs() < 0.01, "point at {}", rotation);
+            let after_annotation = displayed_rect(&doc, page_id, page_rect(&doc, annotation, b"Rect"));
+            assert!(close(after_annotation, before_annotation), "annotation at {}", rotation);
+        }
+    }
+
+    #[test]
+    fn baking_an_upright_page_changes_nothing() {
+        let (mut doc, page_id, annotation) = annotated_page(0);
+        assert!(!bake_rotation(&mut doc, page_id).unwrap());
+        assert_eq!(doc.get_page_contents(page_id).len(), 1);
+        assert!(close(page_rect(&doc, annotation, b"Rect"), [100.0, 650.0, 200.0, 750.0]));
+    }
+
+    #[test]
+    fn insets_margins_of_the_page_as_displayed() {
+        let rect = [0.0, 0.0, 600.0, 800.0];
+        let [left, bottom, right, top] = [10.0, 20.0, 30.0, 40.0];
+        for rotation in [0, 90, 180, 270, -90] {
+            let inner = inset(rect, [left, bottom, right, top], rotation);
+            // In displayed coordinates, measured down from the top left corner, each margin sits on its own side.
+            let display = display_matrix(rect, rotation);
+            let (width, height) = if rotation.rem_euclid(180) == 90 { (800.0, 600.0) } else { (600.0, 800.0) };
+            assert!(close(transform_rect(&display, inner), [left, top, width - right, height - bottom]), "rotation {}", rotation);
+        }
+    }
+
+    // A page whose boxes are all set, the ArtBox in its top right corner.
+    fn boxed_page() -> (Document, ObjectId) {
+        let mut doc = sample_document(1);
+        let page_id = doc.page_iter().next().unwrap();
+        let page = doc.get_dictionary_mut(page_id).unwrap();
+        page.set("MediaBox", rect_object([0.0, 0.0, 600.0, 800.0]));
+        page.set("BleedBox", rect_object([0.0, 0.0, 600.0, 800.0]));
+        page.set("TrimBox", rect_object([100.0, 20.0, 590.0, 790.0]));
+        page.set("ArtBox", rect_object([500.0, 700.0, 590.0, 790.0]));
+        (doc, page_id)
+    }
+
+    #[test]
+    fn cropping_the_media_box_clips_the_other_boxes() {
+        let (mut doc, page_id) = boxed_page();
+        crop_page(&mut doc, page_id, [50.0, 50.0, 400.0, 600.0], true).unwrap();
+        for key in [&b"MediaBox"[..], b"CropBox", b"BleedBox"] {
+            assert!(close(page_rect(&doc, page_id, key), [50.0, 50.0, 400.0, 600.0]));
+        }
+        assert!(close(page_rect(&doc, page_id, b"TrimBox"), [100.0, 50.0, 400.0, 600.0]));
+        // Nothing of the ArtBox is left inside the new MediaBox.
+        assert!(!doc.get_dictionary(page_id).unwrap().has(b"ArtBox"));
+    }
+
+    #[test]
+    fn cropping_only_the_crop_box_leaves_the_other_boxes() {
+        let (mut doc, page_id) = boxed_page();
+        crop_page(&mut doc, page_id, [50.0, 50.0, 400.0, 600.0], false).unwrap();
+        assert!(close(page_rect(&doc, page_id, b"CropBox"), [50.0, 50.0, 400.0, 600.0]));
+        assert!(close(page_rect(&doc, page_id, b"MediaBox"), [0.0, 0.0, 600.0, 800.0]));
+        assert!(close(page_rect(&doc, page_id, b"ArtBox"), [500.0, 700.0, 590.0, 790.0]));
+    }
+}
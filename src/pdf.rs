@@ -1,11 +1,31 @@
 use lopdf::{Document, Object, dictionary, Stream};
 use lopdf::content::{Content, Operation};
 use lopdf::encryption::{EncryptionState, EncryptionVersion, Permissions};
-use std::collections::BTreeMap;
-use std::path::PathBuf;
-use image::GenericImageView;
+use lopdf::xref::XrefType;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use image::{GenericImageView, RgbImage};
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+use zip::CompressionMethod;
+use crate::bounds;
+use crate::compress;
+use crate::fonts;
+use crate::linearize;
+use crate::orient;
+use crate::pages;
+use crate::stamp;
 
-pub fn merge_pdfs(files: &[PathBuf], output: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OutputOptions {
+    pub linearize: bool,
+}
+
+pub fn merge_pdfs(files: &[PathBuf], output: PathBuf, output_options: &OutputOptions) -> Result<(), Box<dyn std::error::Error>> {
     let mut max_id = 1;
     let mut p_collect = Vec::new();
     let mut o_collect = BTreeMap::new();
@@ -36,51 +56,903 @@ pub fn merge_pdfs(files: &[PathBuf], output: PathBuf) -> Result<(), Box<dyn std:
         }
         out_doc.trailer.set("Root", Object::Reference(catalog_id));
         out_doc.max_id = max_id;
-        out_doc.save(output)?;
+        write_document(&mut out_doc, &output, output_options)?;
     }
     Ok(())
 }
 
-pub fn split_pdf(input: &PathBuf, output_dir: &PathBuf) -> Result<usize, Box<dyn std::error::Error>> {
+pub enum SplitOutput { Folder, Zip }
+
+pub fn split_pdf(input: &PathBuf, output: &PathBuf, target: SplitOutput, output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
     let doc = Document::load(input)?;
-    let pages = doc.get_pages();
-    for (i, _) in pages.iter().enumerate() {
-        let mut out_doc = doc.clone();
-        let out_pages = out_doc.get_pages();
-        let target_page_id = out_pages.get(&(i as u32 + 1)).ok_or("Page not found")?;
-        let pages_id = out_doc.new_object_id();
-        let pages_dict = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![Object::Reference(*target_page_id)] };
-        out_doc.objects.insert(pages_id, Object::Dictionary(pages_dict));
-        let catalog_id = out_doc.trailer.get(b"Root")?.as_reference()?;
-        if let Ok(Object::Dictionary(catalog)) = out_doc.get_object_mut(catalog_id) {
-            catalog.set("Pages", Object::Reference(pages_id));
+    let pages: Vec<(u32, lopdf::ObjectId)> = doc.get_pages().into_iter().collect();
+    match target {
+        // Each worker builds and writes one small page document at a time, so memory stays bounded by the pool size.
+        SplitOutput::Folder => pages.par_iter().try_for_each(|&(n, page_id)| -> Result<(), String> {
+            let bytes = single_page_bytes(&doc, page_id, output_options)?;
+            std::fs::write(output.join(format!("page_{}.pdf", n)), bytes).map_err(|e| e.to_string())
+        })?,
+        SplitOutput::Zip => {
+            // A failure part way through would leave a truncated archive behind, so it is removed instead.
+            if let Err(e) = write_page_zip(&doc, &pages, output, output_options) {
+                let _ = std::fs::remove_file(output);
+                return Err(e);
+            }
         }
-        out_doc.save(output_dir.join(format!("page_{}.pdf", i + 1)))?;
     }
     Ok(pages.len())
 }
 
-pub fn compress_pdf(input: &PathBuf, output: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-    let mut doc = Document::load(input)?;
-    doc.trailer.remove(b"Info"); doc.trailer.remove(b"Metadata"); doc.trailer.remove(b"PieceInfo"); doc.trailer.remove(b"XMP");
-    doc.decompress(); doc.compress(); doc.prune_objects(); doc.trailer.remove(b"Prev");
-    doc.save(output)?;
+fn write_page_zip(doc: &Document, pages: &[(u32, lopdf::ObjectId)], output: &Path, output_options: &OutputOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(output)?));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    // Pages are serialized in parallel a batch at a time and streamed into the archive in page order.
+    for chunk in pages.chunks(rayon::current_num_threads() * 4) {
+        let parts: Vec<Vec<u8>> = chunk.par_iter().map(|&(_, page_id)| single_page_bytes(doc, page_id, output_options)).collect::<Result<_, _>>()?;
+        for (&(n, _), bytes) in chunk.iter().zip(parts) {
+            zip.start_file(format!("page_{}.pdf", n), options)?;
+            zip.write_all(&bytes)?;
+        }
+    }
+    zip.finish()?.flush()?;
     Ok(())
 }
 
-pub fn rotate_pdf(input: &PathBuf, output: PathBuf, degrees: i32) -> Result<(), Box<dyn std::error::Error>> {
+// Runs `op` on every input in parallel, writing each result into the output folder under the input's name. A name
+// already taken by another output, or by one of the inputs themselves, gets a number in front, so no input is
+// overwritten while other workers may still be reading it.
+pub fn batch_process<F>(inputs: &[PathBuf], output_dir: &Path, op: F) -> Vec<Result<PathBuf, String>>
+where
+    F: Fn(&PathBuf, PathBuf) -> Result<(), Box<dyn std::error::Error>> + Sync,
+{
+    let absolute = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let folder = absolute(output_dir);
+    let mut taken: HashSet<PathBuf> = inputs.iter().map(|input| absolute(input)).collect();
+    let mut outputs: Vec<PathBuf> = Vec::new();
+    for (i, input) in inputs.iter().enumerate() {
+        let name = input.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| format!("file_{}.pdf", i + 1));
+        let mut file_name = name.clone();
+        let mut n = i + 1;
+        while !taken.insert(folder.join(&file_name)) {
+            file_name = format!("{}_{}", n, name);
+            n += 1;
+        }
+        outputs.push(output_dir.join(file_name));
+    }
+    inputs.par_iter().zip(outputs).map(|(input, output)| {
+        op(input, output.clone()).map(|_| output).map_err(|e| format!("{}: {}", input.display(), e))
+    }).collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct CompressOptions {
+    pub optimize_images: bool,
+    pub image_dpi: u32,
+    pub image_dpi_threshold: u32,
+    pub jpeg_quality: u8,
+    pub subset_fonts: bool,
+    pub metadata: MetadataMode,
+    pub max_deflate: bool,
+    pub object_streams: bool,
+}
+
+impl Default for CompressOptions {
+    fn default() -> Self {
+        CompressOptions {
+            optimize_images: false, image_dpi: 150, image_dpi_threshold: 225, jpeg_quality: 75,
+            subset_fonts: false, metadata: MetadataMode::StripInfo, max_deflate: false, object_streams: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MetadataMode { Keep, StripInfo, StripAll }
+
+impl MetadataMode {
+    pub const ALL: [MetadataMode; 3] = [MetadataMode::Keep, MetadataMode::StripInfo, MetadataMode::StripAll];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MetadataMode::Keep => "keep",
+            MetadataMode::StripInfo => "info",
+            MetadataMode::StripAll => "all",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<MetadataMode> {
+        MetadataMode::ALL.into_iter().find(|m| m.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompressPreset { Screen, Ebook, Print, Lossless, Compatibility }
+
+impl CompressPreset {
+    pub const ALL: [CompressPreset; 5] = [
+        CompressPreset::Screen, CompressPreset::Ebook, CompressPreset::Print, CompressPreset::Lossless, CompressPreset::Compatibility,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CompressPreset::Screen => "screen",
+            CompressPreset::Ebook => "ebook",
+            CompressPreset::Print => "print",
+            CompressPreset::Lossless => "lossless",
+            CompressPreset::Compatibility => "compatibility",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<CompressPreset> {
+        CompressPreset::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    pub fn options(self) -> CompressOptions {
+        let images = |dpi: u32, quality: u8, metadata: MetadataMode| CompressOptions {
+            optimize_images: true, image_dpi: dpi, image_dpi_threshold: dpi * 3 / 2, jpeg_quality: quality,
+            subset_fonts: true, metadata, max_deflate: false, object_streams: true,
+        };
+        match self {
+            CompressPreset::Screen => images(72, 40, MetadataMode::StripAll),
+            CompressPreset::Ebook => images(150, 60, MetadataMode::StripAll),
+            CompressPreset::Print => images(300, 85, MetadataMode::Keep),
+            CompressPreset::Lossless => CompressOptions {
+                optimize_images: false, subset_fonts: true, metadata: MetadataMode::Keep, max_deflate: true, object_streams: true, ..Default::default()
+            },
+            // Classic cross-reference table and no object streams, for readers older than PDF 1.5.
+            CompressPreset::Compatibility => CompressOptions {
+                optimize_images: false, metadata: MetadataMode::Keep, object_streams: false, ..Default::default()
+            },
+        }
+    }
+}
+
+pub struct CompressReport {
+    pub original_size: u64,
+    pub new_size: u64,
+    pub before: compress::SizeBreakdown,
+    pub after: compress::SizeBreakdown,
+    // The result came out larger, so the input was copied to the output instead.
+    pub kept_original: bool,
+}
+
+impl CompressReport {
+    pub fn summary(&self) -> String {
+        let saved = self.original_size as i64 - self.new_size as i64;
+        let percent = if self.original_size > 0 { saved.abs() as f64 * 100.0 / self.original_size as f64 } else { 0.0 };
+        let mut text = format!(
+            "Original size: {}\nNew size: {} ({:.1}% {})",
+            format_size(self.original_size as i64), format_size(self.new_size as i64), percent, if saved < 0 { "larger" } else { "smaller" },
+        );
+        if self.kept_original {
+            text.push_str("\n\nCompressing made the file larger, so the original was kept.");
+            return text;
+        }
+        if saved < 0 { text.push_str("\n\nWarning: the compressed file is larger than the original."); }
+        text.push_str("\n\nSavings by category:");
+        for ((name, before), (_, after)) in self.before.categories().into_iter().zip(self.after.categories()) {
+            text.push_str(&format!("\n  {}: {}", name, format_size(before as i64 - after as i64)));
+        }
+        text
+    }
+}
+
+pub fn format_size(bytes: i64) -> String {
+    let abs = bytes.unsigned_abs() as f64;
+    let sign = if bytes < 0 { "-" } else { "" };
+    if abs >= 1024.0 * 1024.0 { format!("{}{:.1} MB", sign, abs / (1024.0 * 1024.0)) }
+    else if abs >= 1024.0 { format!("{}{:.1} KB", sign, abs / 1024.0) }
+    else { format!("{}{} B", sign, abs) }
+}
+
+pub fn compress_pdf(input: &PathBuf, output: PathBuf, options: &CompressOptions, output_options: &OutputOptions) -> Result<CompressReport, Box<dyn std::error::Error>> {
+    let original_size = std::fs::metadata(input)?.len();
     let mut doc = Document::load(input)?;
-    for (_, page_id) in doc.get_pages() {
-        if let Ok(Object::Dictionary(page)) = doc.get_object_mut(page_id) {
-            let current = page.get(b"Rotate").ok().and_then(|obj| obj.as_i64().ok()).unwrap_or(0);
-            page.set("Rotate", (current + degrees as i64) % 360);
+    let before = compress::size_breakdown(&doc);
+    compress::strip_metadata(&mut doc, options.metadata);
+    compress::strip_unused_resources(&mut doc);
+    doc.prune_objects();
+    if options.subset_fonts { fonts::subset_fonts(&mut doc); }
+    if options.optimize_images { compress::optimize_images(&mut doc, options); }
+    compress::recompress_streams(&mut doc, options.max_deflate);
+    doc.prune_objects(); doc.trailer.remove(b"Prev");
+    let mut after = compress::size_breakdown(&doc);
+    // The result goes beside the output first, so an input that is also the output stays intact until it is replaced.
+    let partial = partial_path(&output);
+    if let Err(e) = save_document(&mut doc, &partial, options.object_streams, output_options) {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e);
+    }
+    let mut new_size = std::fs::metadata(&partial)?.len();
+    // Stripped metadata and linearization were asked for, so a larger file is still written for them.
+    let kept_original = new_size > original_size && options.metadata == MetadataMode::Keep && !output_options.linearize;
+    if kept_original {
+        std::fs::remove_file(&partial)?;
+        if !same_file(input, &output) { std::fs::copy(input, &output)?; }
+        new_size = original_size;
+        after = before.clone();
+    } else {
+        std::fs::rename(&partial, &output)?;
+    }
+    Ok(CompressReport { original_size, new_size, before, after, kept_original })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PageOrientation { Portrait, Landscape }
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rotation {
+    // A multiple of 90 degrees; negative angles turn counter-clockwise.
+    By(i32),
+    // Turns pages whose displayed shape does not match the target. With `detect_text`, scanned pages are instead
+    // turned so their text reads upright.
+    AutoOrient { target: PageOrientation, detect_text: bool },
+}
+
+impl Rotation {
+    pub fn from_name(name: &str) -> Option<Rotation> {
+        match name {
+            "portrait" => Some(Rotation::AutoOrient { target: PageOrientation::Portrait, detect_text: false }),
+            "landscape" => Some(Rotation::AutoOrient { target: PageOrientation::Landscape, detect_text: false }),
+            _ => name.parse().ok().map(Rotation::By),
         }
     }
-    doc.save(output)?;
-    Ok(())
 }
 
-pub fn image_to_pdf(input: &PathBuf, output: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+// Rotates the given pages (all pages when empty) and returns how many of them were turned. With `bake`, their
+// rotation is then drawn into the content and Rotate reset to 0.
+pub fn rotate_pdf(input: &PathBuf, output: PathBuf, rotation: Rotation, pages: &[u32], bake: bool, output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    if let Rotation::By(degrees) = rotation && degrees % 90 != 0 { return Err(format!("Rotation must be a multiple of 90 degrees, got {}.", degrees).into()); }
+    let mut doc = Document::load(input)?;
+    let mut rotated = 0;
+    for (_, page_id) in selected_pages(&doc, pages)? {
+        let current = page_rotation(&doc, page_id);
+        let turn = match rotation {
+            Rotation::By(degrees) => degrees as i64,
+            Rotation::AutoOrient { target, detect_text } => {
+                let upright = if detect_text { orient::upright_rotation(&doc, page_id) } else { None };
+                upright.map_or_else(|| orientation_turn(&doc, page_id, current, target), |r| r - current)
+            }
+        };
+        let turned = turn.rem_euclid(360) != 0;
+        if turned { doc.get_dictionary_mut(page_id)?.set("Rotate", (current + turn).rem_euclid(360)); }
+        let baked = bake && pages::bake_rotation(&mut doc, page_id)?;
+        if turned || baked { rotated += 1; }
+    }
+    write_document(&mut doc, &output, output_options)?;
+    Ok(rotated)
+}
+
+// Page numbers and ids of the given pages, or of all pages when empty. A page listed twice is only returned once.
+fn selected_pages(doc: &Document, pages: &[u32]) -> Result<Vec<(u32, lopdf::ObjectId)>, Box<dyn std::error::Error>> {
+    let page_map = doc.get_pages();
+    if pages.is_empty() { return Ok(page_map.into_iter().collect()); }
+    let mut seen = HashSet::new();
+    let mut selected = Vec::new();
+    for &n in pages {
+        let page_id = *page_map.get(&n).ok_or_else(|| format!("Page {} does not exist.", n))?;
+        if seen.insert(page_id) { selected.push((n, page_id)); }
+    }
+    Ok(selected)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Crop {
+    // Points trimmed off the left, bottom, right and top of each page as displayed.
+    Margins([f32; 4]),
+    // Trims each page to its painted content plus `padding` points. With `pixels`, white borders inside images are
+    // trimmed too.
+    Auto { padding: f32, pixels: bool },
+}
+
+// Crops the visible area of the given pages (all pages when empty) and returns how many were cropped. With
+// `media_box`, the MediaBox is cut down as well so the trimmed area is gone for good. Blank pages are left alone
+// by auto-trim.
+pub fn crop_pdf(input: &PathBuf, output: PathBuf, crop: Crop, pages: &[u32], media_box: bool, output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    if let Crop::Margins(margins) = crop && margins.iter().any(|&m| m < 0.0) { return Err("Crop margins cannot be negative.".into()); }
+    let mut doc = Document::load(input)?;
+    let mut cropped = 0;
+    for (number, page_id) in selected_pages(&doc, pages)? {
+        let visible = page_box(&doc, page_id).ok_or_else(|| format!("Page {} has no MediaBox.", number))?;
+        let rect = match crop {
+            Crop::Margins(margins) => pages::inset(visible, margins, page_rotation(&doc, page_id)),
+            Crop::Auto { padding, pixels } => {
+                let Some([x0, y0, x1, y1]) = bounds::content_bounds(&doc, page_id, pixels) else { continue };
+                let rect = [(x0 - padding).max(visible[0]), (y0 - padding).max(visible[1]), (x1 + padding).min(visible[2]), (y1 + padding).min(visible[3])];
+                // Content painted only outside the visible area.
+                if rect[0] >= rect[2] || rect[1] >= rect[3] { continue; }
+                rect
+            }
+        };
+        if rect[2] - rect[0] < 1.0 || rect[3] - rect[1] < 1.0 { return Err(format!("The margins leave nothing of page {}.", number).into()); }
+        pages::crop_page(&mut doc, page_id, rect, media_box)?;
+        cropped += 1;
+    }
+    write_document(&mut doc, &output, output_options)?;
+    Ok(cropped)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitDirection { Vertical, Horizontal }
+
+impl SplitDirection {
+    pub const ALL: [SplitDirection; 2] = [SplitDirection::Vertical, SplitDirection::Horizontal];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SplitDirection::Vertical => "vertical",
+            SplitDirection::Horizontal => "horizontal",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SplitDirection> {
+        SplitDirection::ALL.into_iter().find(|d| d.name() == name)
+    }
+}
+
+// Cuts each of the given pages (all pages when empty) in half as displayed and returns the new page count. Vertical
+// cuts give the left then the right half, or the other way round for right-to-left books; horizontal cuts give the
+// top then the bottom half.
+pub fn split_spreads(input: &PathBuf, output: PathBuf, direction: SplitDirection, right_to_left: bool, pages: &[u32], output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut doc = Document::load(input)?;
+    let selected: Vec<lopdf::ObjectId> = selected_pages(&doc, pages)?.into_iter().map(|(_, id)| id).collect();
+    let mut page_ids = Vec::new();
+    for (number, page_id) in doc.get_pages() {
+        page_ids.push(page_id);
+        if !selected.contains(&page_id) { continue; }
+        let visible = page_box(&doc, page_id).ok_or_else(|| format!("Page {} has no MediaBox.", number))?;
+        let rotation = page_rotation(&doc, page_id);
+        let (width, height) = pages::displayed_size(&doc, page_id).ok_or_else(|| format!("Page {} has no MediaBox.", number))?;
+        let (first, second) = match direction {
+            SplitDirection::Vertical => ([0.0, 0.0, width / 2.0, 0.0], [width / 2.0, 0.0, 0.0, 0.0]),
+            SplitDirection::Horizontal => ([0.0, height / 2.0, 0.0, 0.0], [0.0, 0.0, 0.0, height / 2.0]),
+        };
+        let (first, second) = if right_to_left && direction == SplitDirection::Vertical { (second, first) } else { (first, second) };
+        let copy = pages::split_page(&mut doc, page_id, pages::inset(visible, first, rotation), pages::inset(visible, second, rotation))?;
+        page_ids.push(copy);
+    }
+    let count = page_ids.len();
+    update_pages_tree(&mut doc, page_ids)?;
+    write_document(&mut doc, &output, output_options)?;
+    Ok(count)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PaperSize { A3, A4, A5, Letter, Legal, Custom(f32, f32) }
+
+impl PaperSize {
+    pub const ALL: [PaperSize; 5] = [PaperSize::A3, PaperSize::A4, PaperSize::A5, PaperSize::Letter, PaperSize::Legal];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PaperSize::A3 => "a3",
+            PaperSize::A4 => "a4",
+            PaperSize::A5 => "a5",
+            PaperSize::Letter => "letter",
+            PaperSize::Legal => "legal",
+            PaperSize::Custom(..) => "custom",
+        }
+    }
+
+    // A standard size by name, or a custom one as width x height in points, or in millimetres with "mm" appended.
+    pub fn from_name(name: &str) -> Option<PaperSize> {
+        let name = name.to_ascii_lowercase();
+        if let Some(size) = PaperSize::ALL.into_iter().find(|p| p.name() == name) { return Some(size); }
+        let (dimensions, unit) = match name.strip_suffix("mm") { Some(d) => (d, 72.0 / 25.4), None => (name.as_str(), 1.0) };
+        let (width, height) = dimensions.split_once('x')?;
+        let (width, height) = (width.trim().parse::<f32>().ok()? * unit, height.trim().parse::<f32>().ok()? * unit);
+        (width >= 1.0 && height >= 1.0).then_some(PaperSize::Custom(width, height))
+    }
+
+    // Portrait width and height in points.
+    pub fn points(self) -> (f32, f32) {
+        match self {
+            PaperSize::A3 => (842.0, 1191.0),
+            PaperSize::A4 => (595.0, 842.0),
+            PaperSize::A5 => (420.0, 595.0),
+            PaperSize::Letter => (612.0, 792.0),
+            PaperSize::Legal => (612.0, 1008.0),
+            PaperSize::Custom(width, height) => (width, height),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FitMode { Fit, Fill, Center }
+
+impl FitMode {
+    pub const ALL: [FitMode; 3] = [FitMode::Fit, FitMode::Fill, FitMode::Center];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FitMode::Fit => "fit",
+            FitMode::Fill => "fill",
+            FitMode::Center => "center",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FitMode> {
+        FitMode::ALL.into_iter().find(|m| m.name() == name)
+    }
+}
+
+// Puts the given pages (all pages when empty) on the paper size, turned to each page's orientation, and returns how
+// many changed. Fit scales the content to fit whole, fill scales it to cover the paper, cutting off what overflows,
+// and center keeps its size.
+pub fn fit_to_paper(input: &PathBuf, output: PathBuf, paper: PaperSize, mode: FitMode, pages: &[u32], output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut doc = Document::load(input)?;
+    let mut resized = 0;
+    for (_, page_id) in selected_pages(&doc, pages)? {
+        if pages::fit_page(&mut doc, page_id, paper.points(), mode)? { resized += 1; }
+    }
+    write_document(&mut doc, &output, output_options)?;
+    Ok(resized)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NUpOptions {
+    pub columns: u32,
+    pub rows: u32,
+    // Fill each column top to bottom before moving right, instead of each row left to right.
+    pub column_major: bool,
+    pub margin: f32,
+    pub gutter: f32,
+    pub borders: bool,
+    // Sheet size, or the size of the first page when None.
+    pub paper: Option<PaperSize>,
+}
+
+impl Default for NUpOptions {
+    fn default() -> Self {
+        NUpOptions { columns: 2, rows: 2, column_major: false, margin: 18.0, gutter: 9.0, borders: false, paper: None }
+    }
+}
+
+impl NUpOptions {
+    // Columns and rows for a number of pages per sheet, as laid out on a sheet turned to suit the pages.
+    pub fn grid(pages_per_sheet: u32) -> Option<(u32, u32)> {
+        match pages_per_sheet {
+            1 => Some((1, 1)),
+            2 => Some((2, 1)),
+            4 => Some((2, 2)),
+            6 => Some((3, 2)),
+            8 => Some((4, 2)),
+            9 => Some((3, 3)),
+            16 => Some((4, 4)),
+            _ => None,
+        }
+    }
+}
+
+// Lays out the given pages (all pages when empty) in a grid on new sheets and returns the number of sheets. The
+// sheet is turned to whichever orientation fits the first page larger.
+pub fn n_up(input: &PathBuf, output: PathBuf, options: &NUpOptions, pages: &[u32], output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    let (columns, rows) = (options.columns.max(1), options.rows.max(1));
+    let mut doc = Document::load(input)?;
+    let page_ids: Vec<lopdf::ObjectId> = selected_pages(&doc, pages)?.into_iter().map(|(_, id)| id).collect();
+    let first = pages::displayed_size(&doc, *page_ids.first().ok_or("No pages selected.")?).ok_or("The page has no MediaBox.")?;
+    let cell_size = |(width, height): (f32, f32)| (
+        (width - 2.0 * options.margin - (columns - 1) as f32 * options.gutter) / columns as f32,
+        (height - 2.0 * options.margin - (rows - 1) as f32 * options.gutter) / rows as f32,
+    );
+    let scale = |sheet: (f32, f32)| { let (w, h) = cell_size(sheet); (w / first.0).min(h / first.1) };
+    let paper = options.paper.map_or(first, PaperSize::points);
+    let sheet_size = if scale((paper.1, paper.0)) > scale(paper) { (paper.1, paper.0) } else { paper };
+    let (cell_width, cell_height) = cell_size(sheet_size);
+    if cell_width <= 0.0 || cell_height <= 0.0 { return Err("The margins and gutters leave no room for the pages.".into()); }
+
+    let mut forms = HashMap::new();
+    let mut sheets = Vec::new();
+    for chunk in page_ids.chunks((columns * rows) as usize) {
+        let placements: Vec<pages::Placement> = chunk.iter().enumerate().map(|(i, &page)| {
+            let i = i as u32;
+            let (column, row) = if options.column_major { (i / rows, i % rows) } else { (i % columns, i / columns) };
+            let x = options.margin + column as f32 * (cell_width + options.gutter);
+            let top = sheet_size.1 - options.margin - row as f32 * (cell_height + options.gutter);
+            let size = pages::displayed_size(&doc, page).unwrap_or(first);
+            pages::Placement { page, matrix: pages::fit_into(size, [x, top - cell_height, x + cell_width, top]), clip: None }
+        }).collect();
+        sheets.push(pages::sheet(&mut doc, &mut forms, sheet_size, &placements, options.borders)?);
+    }
+    let count = sheets.len();
+    update_pages_tree(&mut doc, sheets)?;
+    drop_replaced_pages(&mut doc);
+    write_document(&mut doc, &output, output_options)?;
+    Ok(count)
+}
+
+// Drops the pages that were imposed onto new sheets, along with the outlines, named destinations and form fields
+// that point at them.
+fn drop_replaced_pages(doc: &mut Document) {
+    if let Ok(catalog) = doc.catalog_mut() { catalog.remove(b"Outlines"); catalog.remove(b"Names"); catalog.remove(b"AcroForm"); }
+    doc.prune_objects(); doc.renumber_objects();
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BookletOptions {
+    // Pages per signature, a multiple of 4; 0 folds all pages as one signature.
+    pub signature: u32,
+    // Points that pages on the innermost sheet of a signature are moved toward the spine, to make up for the sheets
+    // around it pushing it outwards when folded. Sheets further out are moved proportionally less.
+    pub creep: f32,
+    // Sheet size, or two of the first page side by side when None.
+    pub paper: Option<PaperSize>,
+}
+
+// Page indexes in printing order for saddle-stitched signatures, as left and right pairs on the front and then the
+// back of each sheet. Each signature is padded to a multiple of four with blanks (None).
+pub fn booklet_order(count: usize, signature: usize) -> Vec<Option<usize>> {
+    let padded = count.div_ceil(4) * 4;
+    let signature = if signature == 0 { padded } else { signature };
+    let mut order = Vec::new();
+    for start in (0..padded).step_by(signature.max(4)) {
+        let n = signature.min(padded - start);
+        let page = |i: usize| (start + i < count).then_some(start + i);
+        for k in (0..n / 2).step_by(2) {
+            order.extend([page(n - 1 - k), page(k), page(k + 1), page(n - 2 - k)]);
+        }
+    }
+    order
+}
+
+// Imposes the given pages (all pages when empty) as a booklet of landscape sheets, two pages per side, to print
+// double-sided flipping on the short edge and fold in half. Returns the number of sheets.
+pub fn booklet(input: &PathBuf, output: PathBuf, options: &BookletOptions, pages: &[u32], output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    if !options.signature.is_multiple_of(4) { return Err(format!("Signatures must hold a multiple of 4 pages, got {}.", options.signature).into()); }
+    let mut doc = Document::load(input)?;
+    let page_ids: Vec<lopdf::ObjectId> = selected_pages(&doc, pages)?.into_iter().map(|(_, id)| id).collect();
+    let first = pages::displayed_size(&doc, *page_ids.first().ok_or("No pages selected.")?).ok_or("The page has no MediaBox.")?;
+    let (width, height) = options.paper.map_or((first.0 * 2.0, first.1), PaperSize::points);
+    let sheet_size = (width.max(height), width.min(height));
+    let half = sheet_size.0 / 2.0;
+
+    let order = booklet_order(page_ids.len(), options.signature as usize);
+    let total_sheets = order.len() / 4;
+    let signature_sheets = if options.signature == 0 { total_sheets } else { options.signature as usize / 4 };
+    let mut forms = HashMap::new();
+    let mut sides = Vec::new();
+    for (i, pair) in order.chunks(2).enumerate() {
+        // Sheets are numbered from the outside of their signature in; the last signature may be thinner.
+        let (sheet, start) = (i / 2 % signature_sheets, i / 2 / signature_sheets * signature_sheets);
+        let sheets = signature_sheets.min(total_sheets - start);
+        let shift = if sheets > 1 { options.creep * sheet as f32 / (sheets - 1) as f32 } else { 0.0 };
+        let placements: Vec<pages::Placement> = pair.iter().enumerate().filter_map(|(side, index)| {
+            let page = page_ids[(*index)?];
+            let size = pages::displayed_size(&doc, page).unwrap_or(first);
+            let mut matrix = pages::fit_into(size, [side as f32 * half, 0.0, (side + 1) as f32 * half, sheet_size.1]);
+            matrix[4] += if side == 0 { shift } else { -shift };
+            Some(pages::Placement { page, matrix, clip: None })
+        }).collect();
+        sides.push(pages::sheet(&mut doc, &mut forms, sheet_size, &placements, false)?);
+    }
+    let count = sides.len() / 2;
+    update_pages_tree(&mut doc, sides)?;
+    drop_replaced_pages(&mut doc);
+    write_document(&mut doc, &output, output_options)?;
+    Ok(count)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PosterOptions {
+    // Size of the sheets the tiles are printed on.
+    pub paper: PaperSize,
+    // Enlargement of the pages, 1 for actual size.
+    pub scale: f32,
+    // How much of each tile is repeated on the next one, for taping them together.
+    pub overlap: f32,
+    // Unprinted border around each tile, which holds the cut marks and labels.
+    pub margin: f32,
+    pub cut_marks: bool,
+    pub labels: bool,
+}
+
+impl Default for PosterOptions {
+    fn default() -> Self {
+        PosterOptions { paper: PaperSize::A4, scale: 1.0, overlap: 18.0, margin: 18.0, cut_marks: true, labels: true }
+    }
+}
+
+// Cuts each of the given pages (all pages when empty) into overlapping tiles printed on separate sheets, row by row
+// from the top left, and returns the number of tiles. Sheets are turned to whichever orientation needs fewer.
+pub fn poster(input: &PathBuf, output: PathBuf, options: &PosterOptions, pages: &[u32], output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    if options.scale <= 0.0 { return Err("The scale must be positive.".into()); }
+    let mut doc = Document::load(input)?;
+    let (paper_width, paper_height) = options.paper.points();
+    let mut forms = HashMap::new();
+    let mut tiles = Vec::new();
+    for (number, page) in selected_pages(&doc, pages)? {
+        let (width, height) = pages::displayed_size(&doc, page).ok_or_else(|| format!("Page {} has no MediaBox.", number))?;
+        let (width, height) = (width * options.scale, height * options.scale);
+        // Columns and rows of tiles with a printable area of the given size.
+        let grid = |(w, h): (f32, f32)| -> Option<(u32, u32)> {
+            let (w, h) = (w - 2.0 * options.margin, h - 2.0 * options.margin);
+            if w <= options.overlap || h <= options.overlap { return None; }
+            let count = |length: f32, tile: f32| if length <= tile { 1 } else { ((length - options.overlap) / (tile - options.overlap)).ceil() as u32 };
+            Some((count(width, w), count(height, h)))
+        };
+        let portrait = grid((paper_width, paper_height));
+        let landscape = grid((paper_height, paper_width));
+        let ((columns, rows), sheet_size) = match (portrait, landscape) {
+            (Some(p), Some(l)) if l.0 * l.1 < p.0 * p.1 => (l, (paper_height, paper_width)),
+            (Some(p), _) => (p, (paper_width, paper_height)),
+            (None, Some(l)) => (l, (paper_height, paper_width)),
+            (None, None) => return Err("The margins and overlap leave no room on the sheets.".into()),
+        };
+        let (tile_width, tile_height) = (sheet_size.0 - 2.0 * options.margin, sheet_size.1 - 2.0 * options.margin);
+        let clip = [options.margin, options.margin, options.margin + tile_width, options.margin + tile_height];
+        for row in 0..rows {
+            for column in 0..columns {
+                let left = column as f32 * (tile_width - options.overlap);
+                let bottom = height - row as f32 * (tile_height - options.overlap) - tile_height;
+                let matrix = [options.scale, 0.0, 0.0, options.scale, options.margin - left, options.margin - bottom];
+                let tile = pages::sheet(&mut doc, &mut forms, sheet_size, &[pages::Placement { page, matrix, clip: Some(clip) }], false)?;
+                let label = format!("Page {}, row {} of {}, column {} of {}", number, row + 1, rows, column + 1, columns);
+                pages::add_content(&mut doc, tile, tile_marks(clip, options, &label), &dictionary! { "Font" => dictionary! { "Helv" => stamp::StandardFont::Helvetica.dictionary() } }, false)?;
+                tiles.push(tile);
+            }
+        }
+    }
+    let count = tiles.len();
+    update_pages_tree(&mut doc, tiles)?;
+    drop_replaced_pages(&mut doc);
+    write_document(&mut doc, &output, output_options)?;
+    Ok(count)
+}
+
+// Cut marks at the corners of the tile's printed area and its label underneath, both in the margin.
+fn tile_marks(clip: [f32; 4], options: &PosterOptions, label: &str) -> Vec<Operation> {
+    let mut operations = Vec::new();
+    let gap = 3.0;
+    let length = (options.margin - gap - 1.0).min(12.0);
+    if options.cut_marks && length > 0.0 {
+        operations.push(Operation::new("w", vec![0.25.into()]));
+        for (x, y, dx, dy) in [(clip[0], clip[1], -1.0, -1.0), (clip[2], clip[1], 1.0, -1.0), (clip[0], clip[3], -1.0, 1.0), (clip[2], clip[3], 1.0, 1.0)] {
+            operations.push(Operation::new("m", vec![(x + dx * gap).into(), y.into()]));
+            operations.push(Operation::new("l", vec![(x + dx * (gap + length)).into(), y.into()]));
+            operations.push(Operation::new("m", vec![x.into(), (y + dy * gap).into()]));
+            operations.push(Operation::new("l", vec![x.into(), (y + dy * (gap + length)).into()]));
+        }
+        operations.push(Operation::new("S", vec![]));
+    }
+    if options.labels {
+        operations.extend([
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec!["Helv".into(), 7.into()]),
+            Operation::new("Td", vec![(clip[0] + gap + length).into(), (clip[1] - 9.0).max(2.0).into()]),
+            Operation::new("Tj", vec![Object::string_literal(label)]),
+            Operation::new("ET", vec![]),
+        ]);
+    }
+    operations
+}
+
+// Draws the stamp's text, such as DRAFT, on each of the given pages (all pages when empty) and returns how many
+// were marked.
+pub fn watermark(input: &PathBuf, output: PathBuf, stamp: &stamp::TextStamp, pages: &[u32], output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    if stamp.text.trim().is_empty() { return Err("The watermark text is empty.".into()); }
+    if stamp.size <= 0.0 { return Err("The font size must be positive.".into()); }
+    let mut doc = Document::load(input)?;
+    let selected = selected_pages(&doc, pages)?;
+    for &(_, page_id) in &selected { stamp::stamp_text(&mut doc, page_id, stamp)?; }
+    write_document(&mut doc, &output, output_options)?;
+    Ok(selected.len())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NumberingOptions {
+    // Text drawn on each page, where {n} stands for the page's number and {total} for the last number given.
+    pub template: String,
+    pub start: u64,
+    // Numbers are padded with zeros to at least this many digits, as Bates numbers are.
+    pub digits: usize,
+    pub prefix: String,
+    pub suffix: String,
+    // Font, size, color and position of the numbers; its text is replaced by each page's label.
+    pub style: stamp::TextStamp,
+}
+
+impl Default for NumberingOptions {
+    fn default() -> Self {
+        NumberingOptions {
+            template: "{n}".to_string(), start: 1, digits: 0, prefix: String::new(), suffix: String::new(),
+            style: stamp::TextStamp { size: 10.0, position: stamp::Position::Bottom, ..Default::default() },
+        }
+    }
+}
+
+impl NumberingOptions {
+    pub fn label(&self, number: u64, last: u64) -> String {
+        let number = format!("{}{:0width$}{}", self.prefix, number, self.suffix, width = self.digits);
+        self.template.replace("{n}", &number).replace("{total}", &last.to_string())
+    }
+}
+
+// Numbers the given pages (all pages when empty) in order from the start number and returns how many were numbered.
+pub fn number_pages(input: &PathBuf, output: PathBuf, options: &NumberingOptions, pages: &[u32], output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    let doc = Document::load(input)?;
+    let count = selected_pages(&doc, pages)?.len() as u64;
+    number_document(doc, &output, options, pages, options.start + count.saturating_sub(1), output_options)
+}
+
+// Numbers every page of the files, continuing the count from one file to the next as Bates numbering does, and
+// writes them into the output folder in parallel. {total} is the last number of the whole batch. Nothing is written
+// unless every file can be read, since a file left out would shift the numbers of the ones after it.
+pub fn number_batch(inputs: &[PathBuf], output_dir: &Path, options: &NumberingOptions, output_options: &OutputOptions) -> Vec<Result<PathBuf, String>> {
+    // Pages are counted the way number_document finds them, so each file gets exactly the numbers it uses.
+    let counts: Vec<Result<u64, String>> = inputs.par_iter().map(|input| {
+        Document::load(input).map(|doc| doc.get_pages().len() as u64).map_err(|e| format!("{}: {}", input.display(), e))
+    }).collect();
+    if counts.iter().any(Result::is_err) {
+        return counts.into_iter().zip(inputs).map(|(count, input)| {
+            count.and_then(|_| Err(format!("{}: Not numbered, since another file in the batch could not be read.", input.display())))
+        }).collect();
+    }
+    let mut starts = HashMap::new();
+    let mut next = options.start;
+    for (input, count) in inputs.iter().zip(counts.into_iter().flatten()) {
+        // A file listed twice gets the same numbers both times.
+        if let Entry::Vacant(entry) = starts.entry(input.clone()) {
+            entry.insert(next);
+            next += count;
+        }
+    }
+    let last = next.saturating_sub(1).max(options.start);
+    batch_process(inputs, output_dir, |input, output| {
+        let options = NumberingOptions { start: starts[input], ..options.clone() };
+        number_document(Document::load(input)?, &output, &options, &[], last, output_options).map(|_| ())
+    })
+}
+
+fn number_document(mut doc: Document, output: &Path, options: &NumberingOptions, pages: &[u32], last: u64, output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    if options.style.size <= 0.0 { return Err("The font size must be positive.".into()); }
+    let selected = selected_pages(&doc, pages)?;
+    for (number, &(_, page_id)) in (options.start..).zip(&selected) {
+        let label = stamp::TextStamp { text: options.label(number, last), ..options.style.clone() };
+        stamp::stamp_text(&mut doc, page_id, &label)?;
+    }
+    write_document(&mut doc, output, output_options)?;
+    Ok(selected.len())
+}
+
+// Header and footer texts for one kind of page, each as left, center and right. Empty texts are left out.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeaderFooter {
+    pub header: [String; 3],
+    pub footer: [String; 3],
+}
+
+// Headers and footers, whose texts may hold {filename}, {title}, {date}, {page} and {pages}.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderFooterOptions {
+    // Texts for every page, or only the odd pages when `even` is set.
+    pub all: HeaderFooter,
+    pub first: Option<HeaderFooter>,
+    pub even: Option<HeaderFooter>,
+    // Font, size and color of the texts, and their distance from the page edges.
+    pub style: stamp::TextStamp,
+}
+
+impl Default for HeaderFooterOptions {
+    fn default() -> Self {
+        HeaderFooterOptions { all: HeaderFooter::default(), first: None, even: None, style: stamp::TextStamp { size: 9.0, ..Default::default() } }
+    }
+}
+
+// Adds headers and footers to the given pages (all pages when empty) and returns how many got them. The first
+// and even page texts go by the page's number in the document.
+pub fn headers_footers(input: &PathBuf, output: PathBuf, options: &HeaderFooterOptions, pages: &[u32], output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    if options.style.size <= 0.0 { return Err("The font size must be positive.".into()); }
+    let mut doc = Document::load(input)?;
+    let filename = input.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let title = doc.trailer.get(b"Info").ok().and_then(|info| compress::resolve_dict(&doc, info)).and_then(|info| info.get(b"Title").ok())
+        .and_then(|title| lopdf::decode_text_string(title).ok()).filter(|t| !t.trim().is_empty())
+        .unwrap_or_else(|| input.file_stem().map(|n| n.to_string_lossy().to_string()).unwrap_or_default());
+    let date = today();
+    let total = doc.get_pages().len().to_string();
+    let selected = selected_pages(&doc, pages)?;
+    for &(number, page_id) in &selected {
+        let texts = match (&options.first, &options.even) {
+            (Some(first), _) if number == 1 => first,
+            (_, Some(even)) if number % 2 == 0 => even,
+            _ => &options.all,
+        };
+        let positions = [stamp::Position::TopLeft, stamp::Position::Top, stamp::Position::TopRight, stamp::Position::BottomLeft, stamp::Position::Bottom, stamp::Position::BottomRight];
+        let stamps: Vec<stamp::TextStamp> = texts.header.iter().chain(&texts.footer).zip(positions).map(|(text, position)| {
+            let text = text.replace("{filename}", &filename).replace("{title}", &title).replace("{date}", &date)
+                .replace("{pages}", &total).replace("{page}", &number.to_string());
+            stamp::TextStamp { text, position, rotation: 0.0, ..options.style.clone() }
+        }).collect();
+        stamp::stamp_texts(&mut doc, page_id, &stamps, options.style.under)?;
+    }
+    write_document(&mut doc, &output, output_options)?;
+    Ok(selected.len())
+}
+
+// Today's date in UTC as YYYY-MM-DD.
+fn today() -> String {
+    civil_date(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs() / 86400) as i64)
+}
+
+// Days since 1970-01-01 as a YYYY-MM-DD date, counting in 400-year eras that start on March 1st.
+fn civil_date(days: i64) -> String {
+    let z = days + 719468;
+    let (era, day_of_era) = (z.div_euclid(146097), z.rem_euclid(146097));
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// A page as the crop preview draws it: its visible area, what is painted there and where its content lies.
+pub struct PagePreview {
+    pub page_count: u32,
+    pub rect: [f32; 4],
+    pub rotation: i64,
+    pub marks: Vec<bounds::Mark>,
+    pub images: HashMap<lopdf::ObjectId, RgbImage>,
+    pub content: Option<[f32; 4]>,
+}
+
+impl PagePreview {
+    // Width and height of the page as displayed.
+    pub fn size(&self) -> (f32, f32) {
+        let (width, height) = (self.rect[2] - self.rect[0], self.rect[3] - self.rect[1]);
+        if self.rotation.rem_euclid(180) == 90 { (height, width) } else { (width, height) }
+    }
+
+    pub fn display_matrix(&self) -> compress::Matrix {
+        pages::display_matrix(self.rect, self.rotation)
+    }
+}
+
+pub fn page_preview(input: &PathBuf, page: u32) -> Result<PagePreview, Box<dyn std::error::Error>> {
+    let doc = Document::load(input)?;
+    let page_map = doc.get_pages();
+    let page_id = *page_map.get(&page).ok_or_else(|| format!("Page {} does not exist.", page))?;
+    let marks = bounds::page_marks(&doc, page_id);
+    let mut images = HashMap::new();
+    for mark in &marks {
+        let bounds::Mark::Image { id: Some(id), .. } = mark else { continue };
+        if images.contains_key(id) { continue; }
+        let Some(img) = doc.get_object(*id).and_then(Object::as_stream).ok().and_then(|s| compress::decode_image(&doc, s)) else { continue };
+        images.insert(*id, img.thumbnail(600, 600).to_rgb8());
+    }
+    Ok(PagePreview {
+        page_count: page_map.len() as u32,
+        rect: page_box(&doc, page_id).ok_or_else(|| format!("Page {} has no MediaBox.", page))?,
+        rotation: page_rotation(&doc, page_id),
+        content: bounds::content_bounds(&doc, page_id, false),
+        marks,
+        images,
+    })
+}
+
+// Quarter turn that gives the page the target shape as displayed, or none if it already has it or is square.
+fn orientation_turn(doc: &Document, page_id: lopdf::ObjectId, rotation: i64, target: PageOrientation) -> i64 {
+    let Some([x0, y0, x1, y1]) = page_box(doc, page_id) else { return 0 };
+    let (mut width, mut height) = (x1 - x0, y1 - y0);
+    if rotation.rem_euclid(180) == 90 { std::mem::swap(&mut width, &mut height); }
+    let shape = if height > width { PageOrientation::Portrait } else if width > height { PageOrientation::Landscape } else { return 0 };
+    if shape == target { 0 } else { 90 }
+}
+
+// The visible area of a page, its CropBox or else its MediaBox, as [left, bottom, right, top].
+pub fn page_box(doc: &Document, page_id: lopdf::ObjectId) -> Option<[f32; 4]> {
+    let mut page = doc.get_dictionary(page_id).ok()?.clone();
+    page.extend(&inherited_page_attributes(doc, page_id).ok()?);
+    pages::rectangle(doc, page.get(b"CropBox").or_else(|_| page.get(b"MediaBox")).ok()?)
+}
+
+// The page's Rotate value, which may be inherited from the page tree.
+pub fn page_rotation(doc: &Document, page_id: lopdf::ObjectId) -> i64 {
+    let rotate = doc.get_dictionary(page_id).ok().and_then(|page| page.get(b"Rotate").ok().cloned())
+        .or_else(|| inherited_page_attributes(doc, page_id).ok()?.get(b"Rotate").ok().cloned());
+    rotate.and_then(|r| r.as_i64().ok()).unwrap_or(0)
+}
+
+pub fn image_to_pdf(input: &PathBuf, output: PathBuf, output_options: &OutputOptions) -> Result<(), Box<dyn std::error::Error>> {
     let img = image::open(input)?;
     let (width, height) = img.dimensions();
     let mut doc = Document::with_version("1.5");
@@ -95,39 +967,169 @@ pub fn image_to_pdf(input: &PathBuf, output: PathBuf) -> Result<(), Box<dyn std:
     content.operations.push(Operation::new("Do", vec![Object::Name(b"Im0".to_vec())]));
     content.operations.push(Operation::new("Q", vec![]));
     let stream = Stream::new(dictionary! {}, content.encode()?);
-    let rgb = img.to_rgb8();
-    let image_dict = dictionary! { "Type" => "XObject", "Subtype" => "Image", "Width" => width, "Height" => height, "ColorSpace" => "DeviceRGB", "BitsPerComponent" => 8 };
-    doc.objects.insert(image_id, Object::Stream(Stream::new(image_dict, rgb.into_raw())));
+    let image = image_xobject(&mut doc, &img)?;
+    doc.objects.insert(image_id, Object::Stream(image));
     doc.objects.insert(content_id, Object::Stream(stream));
     doc.objects.insert(page_id, Object::Dictionary(dictionary! { "Type" => "Page", "Parent" => pages_id, "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()], "Contents" => content_id, "Resources" => dictionary! { "XObject" => dictionary! { "Im0" => image_id } } }));
     doc.objects.insert(pages_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => vec![page_id.into()], "Count" => 1 }));
     doc.objects.insert(catalog_id, Object::Dictionary(dictionary! { "Type" => "Catalog", "Pages" => pages_id }));
     doc.trailer.set("Root", catalog_id);
-    doc.compress(); doc.save(output)?;
+    doc.compress(); write_document(&mut doc, &output, output_options)?;
     Ok(())
 }
 
-pub fn delete_pages(input: &PathBuf, output: PathBuf, to_delete: Vec<u32>) -> Result<(), Box<dyn std::error::Error>> {
+// An image XObject with the image's colors, and its alpha channel added to the document as a soft mask when the
+// image has transparent parts.
+fn image_xobject(doc: &mut Document, img: &image::DynamicImage) -> Result<Stream, Box<dyn std::error::Error>> {
+    let (width, height) = img.dimensions();
+    let mut image_dict = dictionary! { "Type" => "XObject", "Subtype" => "Image", "Width" => width, "Height" => height, "ColorSpace" => "DeviceRGB", "BitsPerComponent" => 8 };
+    if img.color().has_alpha() {
+        let alpha: Vec<u8> = img.to_rgba8().pixels().map(|p| p.0[3]).collect();
+        if alpha.iter().any(|&a| a < 255) {
+            let mask_dict = dictionary! { "Type" => "XObject", "Subtype" => "Image", "Width" => width, "Height" => height, "ColorSpace" => "DeviceGray", "BitsPerComponent" => 8 };
+            let mut mask = Stream::new(mask_dict, alpha);
+            mask.compress()?;
+            image_dict.set("SMask", doc.add_object(mask));
+        }
+    }
+    let mut image = Stream::new(image_dict, img.to_rgb8().into_raw());
+    image.compress()?;
+    Ok(image)
+}
+
+// Draws the image file, such as a logo, on each of the given pages (all pages when empty) and returns how many were
+// stamped. The image is stored once and shared by the pages.
+pub fn image_stamp(input: &PathBuf, output: PathBuf, image: &Path, stamp: &stamp::ImageStamp, pages: &[u32], output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    if stamp.width <= 0.0 { return Err("The image width must be positive.".into()); }
+    let img = image::open(image)?;
+    let (width, height) = img.dimensions();
+    let mut doc = Document::load(input)?;
+    let selected = selected_pages(&doc, pages)?;
+    let xobject = image_xobject(&mut doc, &img)?;
+    let image_id = doc.add_object(xobject);
+    for &(_, page_id) in &selected { stamp::stamp_image(&mut doc, page_id, image_id, height as f32 / width as f32, stamp)?; }
+    write_document(&mut doc, &output, output_options)?;
+    Ok(selected.len())
+}
+
+pub fn delete_pages(input: &PathBuf, output: PathBuf, to_delete: Vec<u32>, output_options: &OutputOptions) -> Result<(), Box<dyn std::error::Error>> {
     let mut doc = Document::load(input)?;
     let pages = doc.get_pages();
     let kept_ids: Vec<lopdf::ObjectId> = pages.iter().filter(|(n, _)| !to_delete.contains(n)).map(|(_, id)| *id).collect();
     if kept_ids.is_empty() { return Err("Cannot delete all pages.".into()); }
     update_pages_tree(&mut doc, kept_ids)?;
-    doc.save(output)?;
+    compress::strip_unused_resources(&mut doc);
+    doc.prune_objects();
+    write_document(&mut doc, &output, output_options)?;
     Ok(())
 }
 
-pub fn reorder_pages(input: &PathBuf, output: PathBuf, order: Vec<u32>) -> Result<(), Box<dyn std::error::Error>> {
+pub fn reorder_pages(input: &PathBuf, output: PathBuf, order: Vec<u32>, output_options: &OutputOptions) -> Result<(), Box<dyn std::error::Error>> {
     let mut doc = Document::load(input)?;
     let pages = doc.get_pages();
     let new_ids: Vec<lopdf::ObjectId> = order.iter().filter_map(|n| pages.get(n)).copied().collect();
     if new_ids.is_empty() { return Err("Invalid page order.".into()); }
     update_pages_tree(&mut doc, new_ids)?;
-    doc.save(output)?;
+    write_document(&mut doc, &output, output_options)?;
     Ok(())
 }
 
-pub fn insert_pages(base: &PathBuf, to_insert: &PathBuf, output: PathBuf, after_page: u32) -> Result<(), Box<dyn std::error::Error>> {
+pub fn extract_pages(input: &PathBuf, output: PathBuf, pages: Vec<u32>, output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut doc = Document::load(input)?;
+    let page_map = doc.get_pages();
+    let mut new_ids: Vec<lopdf::ObjectId> = Vec::new();
+    for n in &pages {
+        let id = *page_map.get(n).ok_or_else(|| format!("Page {} does not exist.", n))?;
+        // A page object can only appear once in the tree, so repeated selections get a copy.
+        if new_ids.contains(&id) {
+            new_ids.push(pages::copy_page(&mut doc, id)?);
+        } else {
+            new_ids.push(id);
+        }
+    }
+    if new_ids.is_empty() { return Err("No pages selected.".into()); }
+    update_pages_tree(&mut doc, new_ids)?;
+    // Outlines and named destinations point at pages that may have been dropped.
+    if let Ok(catalog) = doc.catalog_mut() { catalog.remove(b"Outlines"); catalog.remove(b"Names"); }
+    compress::strip_unused_resources(&mut doc);
+    doc.prune_objects(); doc.renumber_objects();
+    write_document(&mut doc, &output, output_options)?;
+    Ok(pages.len())
+}
+
+pub fn parse_page_ranges(text: &str) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+    let mut pages = Vec::new();
+    for part in text.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let invalid = || format!("Invalid page range: {}", part);
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start: u32 = start.trim().parse().map_err(|_| invalid())?;
+                let end: u32 = end.trim().parse().map_err(|_| invalid())?;
+                if start == 0 || end == 0 { return Err(invalid().into()); }
+                if start <= end { pages.extend(start..=end); } else { pages.extend((end..=start).rev()); }
+            }
+            None => {
+                let n: u32 = part.parse().map_err(|_| invalid())?;
+                if n == 0 { return Err(invalid().into()); }
+                pages.push(n);
+            }
+        }
+    }
+    Ok(pages)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverlayMode { Repeat, Match, FirstOther }
+
+impl OverlayMode {
+    pub const ALL: [OverlayMode; 3] = [OverlayMode::Repeat, OverlayMode::Match, OverlayMode::FirstOther];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OverlayMode::Repeat => "repeat",
+            OverlayMode::Match => "match",
+            OverlayMode::FirstOther => "first-other",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<OverlayMode> {
+        OverlayMode::ALL.into_iter().find(|m| m.name() == name)
+    }
+}
+
+// Draws pages of the stamp PDF, such as a letterhead, over or under the given pages (all pages when empty) and
+// returns how many got one. Repeat uses the stamp's first page everywhere; Match gives each page the stamp page
+// with the same number, while it has one; FirstOther gives the first selected page the stamp's first page and the
+// others its second, if it has one. Stamp pages are scaled to fit pages of another size.
+pub fn overlay(input: &PathBuf, stamp_pdf: &Path, output: PathBuf, mode: OverlayMode, under: bool, pages: &[u32], output_options: &OutputOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut doc = Document::load(input)?;
+    let mut stamp_doc = Document::load(stamp_pdf)?;
+    stamp_doc.renumber_objects_with(doc.max_id + 1);
+    let stamp_pages: Vec<lopdf::ObjectId> = stamp_doc.get_pages().into_values().collect();
+    if stamp_pages.is_empty() { return Err("The stamp PDF has no pages.".into()); }
+    doc.objects.extend(std::mem::take(&mut stamp_doc.objects));
+    doc.max_id = stamp_doc.max_id;
+    let mut forms = HashMap::new();
+    let mut stamped = 0;
+    for (position, (number, page_id)) in selected_pages(&doc, pages)?.into_iter().enumerate() {
+        let index = match mode {
+            OverlayMode::Repeat => 0,
+            OverlayMode::Match => number as usize - 1,
+            OverlayMode::FirstOther => usize::from(position > 0),
+        };
+        let Some(&stamp_page) = stamp_pages.get(index) else { continue };
+        inherit_page_attributes(&mut doc, stamp_page)?;
+        let (form, size) = pages::upright_form(&mut doc, &mut forms, stamp_page)?;
+        stamp::stamp_form(&mut doc, page_id, form, size, under)?;
+        stamped += 1;
+    }
+    // The stamp's own page tree and catalog are left unreferenced.
+    doc.prune_objects();
+    write_document(&mut doc, &output, output_options)?;
+    Ok(stamped)
+}
+
+pub fn insert_pages(base: &PathBuf, to_insert: &PathBuf, output: PathBuf, after_page: u32, output_options: &OutputOptions) -> Result<(), Box<dyn std::error::Error>> {
     let mut doc_base = Document::load(base)?;
     let doc_to_insert = Document::load(to_insert)?;
     
@@ -157,11 +1159,11 @@ pub fn insert_pages(base: &PathBuf, to_insert: &PathBuf, output: PathBuf, after_
     
     update_pages_tree(&mut doc_base, base_page_ids)?;
     doc_base.max_id = doc_to_insert.max_id;
-    doc_base.save(output)?;
+    write_document(&mut doc_base, &output, output_options)?;
     Ok(())
 }
 
-pub fn encrypt_pdf(input: &PathBuf, output: PathBuf, password: String) -> Result<(), Box<dyn std::error::Error>> {
+pub fn encrypt_pdf(input: &PathBuf, output: PathBuf, password: String, output_options: &OutputOptions) -> Result<(), Box<dyn std::error::Error>> {
     let mut doc = Document::load(input)?;
     let version = EncryptionVersion::V2 {
         document: &doc,
@@ -171,17 +1173,75 @@ pub fn encrypt_pdf(input: &PathBuf, output: PathBuf, password: String) -> Result
         permissions: Permissions::default(),
     };
     let state: EncryptionState = version.try_into()?;
+    if output_options.linearize {
+        // Objects are encrypted as they are written, once their final numbers are known.
+        let mut file = BufWriter::new(File::create(output)?);
+        linearize::write_linearized(&doc, &mut file, Some(&state))?;
+        file.flush()?;
+        return Ok(());
+    }
     doc.encrypt(&state)?;
     doc.save(output)?;
     Ok(())
 }
 
+// Writes either packed object streams with a cross-reference stream (PDF 1.5), or a classic xref table.
+// Linearized output always uses classic tables.
+fn save_document(doc: &mut Document, output: &Path, object_streams: bool, output_options: &OutputOptions) -> Result<(), Box<dyn std::error::Error>> {
+    // Leftovers from a source file that used a cross-reference stream.
+    for key in [&b"Type"[..], b"W", b"Index", b"Length", b"Filter", b"DecodeParms", b"XRefStm", b"Prev"] { doc.trailer.remove(key); }
+    if output_options.linearize { return write_document(doc, output, output_options); }
+    let page_count = doc.get_pages().len();
+    let mut bytes = Vec::new();
+    if object_streams {
+        linearize::write_object_streams(doc, &mut bytes)?;
+    } else {
+        doc.reference_table.cross_reference_type = XrefType::CrossReferenceTable;
+        doc.save_to(&mut bytes)?;
+    }
+    // Reading the result back catches objects the cross-reference data cannot reach.
+    let saved = Document::load_mem(&bytes).map_err(|e| format!("The compressed file could not be read back: {}", e))?;
+    if saved.get_pages().len() != page_count { return Err("The compressed file lost pages when read back.".into()); }
+    std::fs::write(output, bytes)?;
+    Ok(())
+}
+
+// A hidden file in the output's folder, so renaming it over the output never crosses file systems.
+fn partial_path(output: &Path) -> PathBuf {
+    let name = output.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    output.with_file_name(format!(".{}.part", name))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// Saves the document as lopdf lays it out, or linearized for fast web view.
+fn write_document(doc: &mut Document, output: &Path, output_options: &OutputOptions) -> Result<(), Box<dyn std::error::Error>> {
+    if !output_options.linearize {
+        doc.save(output)?;
+        return Ok(());
+    }
+    let mut file = BufWriter::new(File::create(output)?);
+    linearize::write_linearized(doc, &mut file, None)?;
+    file.flush()?;
+    Ok(())
+}
+
+pub fn check_linearized(input: &PathBuf) -> Result<linearize::LinearizationReport, Box<dyn std::error::Error>> {
+    linearize::check_linearized(&std::fs::read(input)?)
+}
+
 fn update_pages_tree(doc: &mut Document, page_ids: Vec<lopdf::ObjectId>) -> Result<(), Box<dyn std::error::Error>> {
     let catalog_id = doc.trailer.get(b"Root")?.as_reference()?;
     let pages_id = match doc.get_object(catalog_id)? {
         Object::Dictionary(cat) => cat.get(b"Pages")?.as_reference()?,
         _ => return Err("Invalid catalog.".into()),
     };
+    for &id in &page_ids { inherit_page_attributes(doc, id)?; }
     let count = page_ids.len() as i32;
     let kids: Vec<Object> = page_ids.iter().map(|&id| Object::Reference(id)).collect();
     doc.objects.insert(pages_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Count" => count, "Kids" => kids }));
@@ -190,3 +1250,688 @@ fn update_pages_tree(doc: &mut Document, page_ids: Vec<lopdf::ObjectId>) -> Resu
     }
     Ok(())
 }
+
+// Copies attributes inherited from the old page tree onto the page itself, since the tree gets replaced.
+pub fn inherit_page_attributes(doc: &mut Document, page_id: lopdf::ObjectId) -> Result<(), Box<dyn std::error::Error>> {
+    let inherited = inherited_page_attributes(doc, page_id)?;
+    let page = doc.get_dictionary_mut(page_id)?;
+    page.extend(&inherited);
+    Ok(())
+}
+
+fn inherited_page_attributes(doc: &Document, page_id: lopdf::ObjectId) -> Result<lopdf::Dictionary, Box<dyn std::error::Error>> {
+    let mut inherited = lopdf::Dictionary::new();
+    let page = doc.get_dictionary(page_id)?;
+    let mut parent = page.get(b"Parent").and_then(Object::as_reference).ok();
+    let mut depth = 0;
+    while let Some(parent_id) = parent {
+        let node = match doc.get_dictionary(parent_id) { Ok(node) => node, Err(_) => break };
+        for key in [&b"Resources"[..], b"MediaBox", b"CropBox", b"Rotate"] {
+            if page.has(key) || inherited.has(key) { continue; }
+            if let Ok(value) = node.get(key) { inherited.set(key, value.clone()); }
+        }
+        parent = node.get(b"Parent").and_then(Object::as_reference).ok();
+        depth += 1;
+        if depth > 64 { break; }
+    }
+    Ok(inherited)
+}
+
+// Builds a standalone document holding one page and only the objects it references.
+fn single_page_document(doc: &Document, page_id: lopdf::ObjectId) -> Result<Document, Box<dyn std::error::Error>> {
+    let mut page = doc.get_dictionary(page_id)?.clone();
+    page.extend(&inherited_page_attributes(doc, page_id)?);
+    page.remove(b"Parent");
+    if let Some(resources) = compress::used_page_resources(doc, page_id) { page.set("Resources", resources); }
+    let mut objects = BTreeMap::new();
+    let mut pending = Vec::new();
+    collect_references(&Object::Dictionary(page.clone()), &mut pending);
+    while let Some(id) = pending.pop() {
+        if id == page_id || objects.contains_key(&id) { continue; }
+        let object = match doc.get_object(id) { Ok(o) => o, Err(_) => continue };
+        // Links to other pages would otherwise drag the whole document along.
+        if let Object::Dictionary(dict) = object && (dict.has_type(b"Page") || dict.has_type(b"Pages")) { continue; }
+        collect_references(object, &mut pending);
+        objects.insert(id, object.clone());
+    }
+    let mut out_doc = Document::with_version(doc.version.clone());
+    out_doc.objects = objects;
+    out_doc.max_id = doc.max_id;
+    let pages_id = out_doc.new_object_id();
+    let catalog_id = out_doc.new_object_id();
+    page.set("Parent", pages_id);
+    out_doc.objects.insert(page_id, Object::Dictionary(page));
+    out_doc.objects.insert(pages_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![Object::Reference(page_id)] }));
+    out_doc.objects.insert(catalog_id, Object::Dictionary(dictionary! { "Type" => "Catalog", "Pages" => pages_id }));
+    out_doc.trailer.set("Root", catalog_id);
+    compress::strip_unused_resources(&mut out_doc);
+    out_doc.prune_objects();
+    out_doc.renumber_objects();
+    Ok(out_doc)
+}
+
+fn single_page_bytes(doc: &Document, page_id: lopdf::ObjectId, output_options: &OutputOptions) -> Result<Vec<u8>, String> {
+    let mut out_doc = single_page_document(doc, page_id).map_err(|e| e.to_string())?;
+    let mut bytes = Vec::new();
+    if output_options.linearize {
+        linearize::write_linearized(&out_doc, &mut bytes, None).map_err(|e| e.to_string())?;
+    } else {
+        out_doc.save_to(&mut bytes).map_err(|e| e.to_string())?;
+    }
+    Ok(bytes)
+}
+
+pub fn collect_references(object: &Object, out: &mut Vec<lopdf::ObjectId>) {
+    match object {
+        Object::Reference(id) => out.push(*id),
+        Object::Array(items) => for item in items { collect_references(item, out); },
+        Object::Dictionary(dict) => for (key, value) in dict.iter() {
+            if key != b"Parent" { collect_references(value, out); }
+        },
+        Object::Stream(stream) => collect_references(&Object::Dictionary(stream.dict.clone()), out),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use lopdf::ObjectId;
+
+    // A document of Letter pages, each showing "Page n" in Helvetica.
+    pub(crate) fn sample_document(pages: u32) -> Document {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(stamp::StandardFont::Helvetica.dictionary());
+        let mut kids = Vec::new();
+        for n in 1..=pages {
+            let content = Content { operations: vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 24.into()]),
+                Operation::new("Td", vec![72.into(), 700.into()]),
+                Operation::new("Tj", vec![Object::string_literal(format!("Page {}", n))]),
+                Operation::new("ET", vec![]),
+            ] };
+            let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+            kids.push(Object::Reference(doc.add_object(dictionary! {
+                "Type" => "Page", "Parent" => pages_id, "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                "Contents" => content_id, "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+            })));
+        }
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Count" => pages, "Kids" => kids }));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let id = Object::String(b"rustypdf-sample".to_vec(), lopdf::StringFormat::Hexadecimal);
+        doc.trailer.set("ID", vec![id.clone(), id]);
+        doc
+    }
+
+    // A path in the temp folder that no other test uses.
+    pub(crate) fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rustypdf-{}-{}", std::process::id(), name))
+    }
+
+    pub(crate) fn write_sample(name: &str, pages: u32) -> PathBuf {
+        let path = temp_path(name);
+        sample_document(pages).save(&path).unwrap();
+        path
+    }
+
+    // The "Page n" text of each page, in page order.
+    pub(crate) fn page_texts(doc: &Document) -> Vec<String> {
+        doc.get_pages().into_values().map(|id| {
+            let content = Content::decode(&doc.get_page_content(id).unwrap()).unwrap();
+            content.operations.iter().filter(|op| op.operator == "Tj")
+                .map(|op| String::from_utf8_lossy(op.operands[0].as_str().unwrap()).to_string()).collect()
+        }).collect()
+    }
+
+    #[test]
+    fn object_streams_round_trip_with_many_objects() {
+        let input = write_sample("objstm-in.pdf", 400);
+        let output = temp_path("objstm-out.pdf");
+        let options = CompressOptions { object_streams: true, ..Default::default() };
+        compress_pdf(&input, output.clone(), &options, &OutputOptions::default()).unwrap();
+        let doc = Document::load(&output).unwrap();
+        assert!(doc.objects.values().any(|o| o.type_name().is_ok_and(|name| name == b"ObjStm")));
+        let texts = page_texts(&doc);
+        assert_eq!(texts.len(), 400);
+        assert_eq!(texts[399], "Page 400");
+    }
+
+    #[test]
+    fn splits_objects_over_several_object_streams() {
+        let mut doc = sample_document(2);
+        // More objects than one stream's 16-bit indexes reach, kept reachable from the catalog.
+        let filler: Vec<Object> = (0..70_000).map(|n| doc.add_object(dictionary! { "N" => n }).into()).collect();
+        let filler_id = doc.add_object(filler);
+        let catalog_id = doc.trailer.get(b"Root").unwrap().as_reference().unwrap();
+        doc.get_dictionary_mut(catalog_id).unwrap().set("Filler", filler_id);
+        let output = temp_path("objstm-many.pdf");
+        save_document(&mut doc, &output, true, &OutputOptions::default()).unwrap();
+
+        let saved = Document::load(&output).unwrap();
+        let streams = saved.objects.values().filter(|o| o.type_name().is_ok_and(|name| name == b"ObjStm")).count();
+        assert_eq!(streams, 2);
+        assert_eq!(page_texts(&saved), vec!["Page 1", "Page 2"]);
+        let filler = saved.get_object(filler_id).unwrap().as_array().unwrap();
+        assert_eq!(filler.len(), 70_000);
+        for (n, reference) in filler.iter().enumerate().step_by(997).chain(filler.iter().enumerate().next_back()) {
+            let dict = saved.get_object(reference.as_reference().unwrap()).unwrap().as_dict().unwrap();
+            assert_eq!(dict.get(b"N").unwrap().as_i64().unwrap(), n as i64);
+        }
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn parses_single_pages_and_ranges() {
+        assert_eq!(parse_page_ranges("1, 3-5,8").unwrap(), vec![1, 3, 4, 5, 8]);
+        assert_eq!(parse_page_ranges(" 2 - 4 ").unwrap(), vec![2, 3, 4]);
+        assert_eq!(parse_page_ranges("").unwrap(), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn parses_descending_ranges_in_reverse() {
+        assert_eq!(parse_page_ranges("5-3,1").unwrap(), vec![5, 4, 3, 1]);
+    }
+
+    #[test]
+    fn rejects_invalid_page_ranges() {
+        for text in ["0", "2-0", "a", "1-b", "1-2-3", "-4"] {
+            assert!(parse_page_ranges(text).is_err(), "{:?} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn extracts_pages_in_the_given_order() {
+        let mut doc = sample_document(5);
+        let page_two = doc.get_pages()[&2];
+        let note = doc.new_object_id();
+        let popup = doc.add_object(dictionary! { "Type" => "Annot", "Subtype" => "Popup", "Rect" => vec![0.into(), 0.into(), 9.into(), 9.into()], "Parent" => note, "P" => page_two });
+        doc.objects.insert(note, Object::Dictionary(dictionary! { "Type" => "Annot", "Subtype" => "Text", "Rect" => vec![0.into(), 0.into(), 9.into(), 9.into()], "Popup" => popup, "P" => page_two }));
+        doc.get_dictionary_mut(page_two).unwrap().set("Annots", vec![note.into(), popup.into()]);
+        let input = temp_path("extract-in.pdf");
+        doc.save(&input).unwrap();
+        let output = temp_path("extract-out.pdf");
+        assert_eq!(extract_pages(&input, output.clone(), vec![4, 2, 2], &OutputOptions::default()).unwrap(), 3);
+        let doc = Document::load(&output).unwrap();
+        assert_eq!(page_texts(&doc), ["Page 4", "Page 2", "Page 2"]);
+        // Pages that were not extracted are pruned with their content.
+        assert_eq!(doc.objects.values().filter(|o| o.as_stream().is_ok_and(|s| !s.dict.has_type(b"XRef"))).count(), 2);
+        // Each copy of page 2 has annotations of its own, pointing back at it and at each other.
+        let pages = doc.get_pages();
+        let mut seen = Vec::new();
+        for number in [2, 3] {
+            let annots: Vec<ObjectId> = doc.get_dictionary(pages[&number]).unwrap().get(b"Annots").unwrap().as_array().unwrap()
+                .iter().map(|a| a.as_reference().unwrap()).collect();
+            let [note, popup] = annots[..] else { panic!("page {} has {} annotations", number, annots.len()) };
+            for id in [note, popup] { assert_eq!(doc.get_dictionary(id).unwrap().get(b"P").unwrap().as_reference().unwrap(), pages[&number]); }
+            assert_eq!(doc.get_dictionary(note).unwrap().get(b"Popup").unwrap().as_reference().unwrap(), popup);
+            assert_eq!(doc.get_dictionary(popup).unwrap().get(b"Parent").unwrap().as_reference().unwrap(), note);
+            seen.extend(annots);
+        }
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 4);
+    }
+
+    #[test]
+    fn rejects_extracting_missing_pages() {
+        let input = write_sample("extract-missing.pdf", 2);
+        assert!(extract_pages(&input, temp_path("extract-missing-out.pdf"), vec![3], &OutputOptions::default()).is_err());
+        assert!(extract_pages(&input, temp_path("extract-none-out.pdf"), vec![], &OutputOptions::default()).is_err());
+    }
+
+    #[test]
+    fn paper_sizes_by_name() {
+        assert_eq!(PaperSize::from_name("A4"), Some(PaperSize::A4));
+        assert_eq!(PaperSize::from_name("letter"), Some(PaperSize::Letter));
+        assert_eq!(PaperSize::from_name("500x700"), Some(PaperSize::Custom(500.0, 700.0)));
+        let Some(PaperSize::Custom(width, height)) = PaperSize::from_name("210 x 297mm") else { panic!("millimetres not parsed") };
+        assert!((width - 595.28).abs() < 0.01 && (height - 841.89).abs() < 0.01);
+    }
+
+    #[test]
+    fn rejects_unknown_and_tiny_paper_sizes() {
+        for name in ["b5", "x", "100", "0x500", "0.5x0.5", "ax4"] {
+            assert_eq!(PaperSize::from_name(name), None, "{:?} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn n_up_drops_the_replaced_pages_and_outlines() {
+        let mut doc = sample_document(5);
+        let outline = doc.add_object(dictionary! { "Type" => "Outlines", "Count" => 0 });
+        doc.catalog_mut().unwrap().set("Outlines", outline);
+        let input = temp_path("n-up-in.pdf");
+        doc.save(&input).unwrap();
+        let output = temp_path("n-up-out.pdf");
+        assert_eq!(n_up(&input, output.clone(), &NUpOptions::default(), &[], &OutputOptions::default()).unwrap(), 2);
+        let doc = Document::load(&output).unwrap();
+        assert_eq!(doc.get_pages().len(), 2);
+        assert!(!doc.catalog().unwrap().has(b"Outlines"));
+        assert!(!doc.objects.values().any(|o| o.as_dict().is_ok_and(|d| d.has_type(b"Outlines"))));
+    }
+
+    #[test]
+    fn booklet_order_folds_one_signature() {
+        let order: Vec<usize> = booklet_order(8, 0).into_iter().map(Option::unwrap).collect();
+        assert_eq!(order, [7, 0, 1, 6, 5, 2, 3, 4]);
+    }
+
+    #[test]
+    fn booklet_order_pads_with_blanks() {
+        assert_eq!(booklet_order(5, 0), [None, Some(0), Some(1), None, None, Some(2), Some(3), Some(4)]);
+        assert!(booklet_order(0, 0).is_empty());
+    }
+
+    #[test]
+    fn booklet_order_splits_signatures() {
+        let order: Vec<usize> = booklet_order(8, 4).into_iter().map(Option::unwrap).collect();
+        assert_eq!(order, [3, 0, 1, 2, 7, 4, 5, 6]);
+        // The last signature only gets the pages that are left.
+        assert_eq!(booklet_order(12, 8)[8..], [Some(11), Some(8), Some(9), Some(10)]);
+    }
+
+    #[test]
+    fn numbering_labels_pad_and_fill_the_template() {
+        let options = NumberingOptions { template: "{n} of {total}".to_string(), digits: 6, prefix: "ABC".to_string(), suffix: "-X".to_string(), ..Default::default() };
+        assert_eq!(options.label(42, 120), "ABC000042-X of 120");
+        assert_eq!(NumberingOptions::default().label(7, 9), "7");
+        assert_eq!(NumberingOptions { digits: 2, ..Default::default() }.label(1234, 1234), "1234");
+    }
+
+    #[test]
+    fn number_batch_continues_across_files() {
+        let inputs = [write_sample("bates-a.pdf", 2), write_sample("bates-b.pdf", 3)];
+        let output_dir = temp_path("bates-out");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let options = NumberingOptions { template: "{n}/{total}".to_string(), start: 10, ..Default::default() };
+        let outputs: Vec<PathBuf> = number_batch(&inputs, &output_dir, &options, &OutputOptions::default()).into_iter().map(Result::unwrap).collect();
+        let labels: Vec<String> = outputs.iter().flat_map(|output| page_texts(&Document::load(output).unwrap())).collect();
+        assert_eq!(labels, ["Page 110/14", "Page 211/14", "Page 112/14", "Page 213/14", "Page 314/14"]);
+    }
+
+    #[test]
+    fn number_batch_writes_nothing_when_a_file_cannot_be_read() {
+        let broken = temp_path("bates-broken.pdf");
+        std::fs::write(&broken, b"%PDF-1.5\nnot really a PDF").unwrap();
+        let inputs = [write_sample("bates-good.pdf", 2), broken];
+        let output_dir = temp_path("bates-broken-out");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let results = number_batch(&inputs, &output_dir, &NumberingOptions::default(), &OutputOptions::default());
+        assert!(results.iter().all(Result::is_err));
+        assert_eq!(std::fs::read_dir(&output_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn civil_dates_from_day_counts() {
+        assert_eq!(civil_date(0), "1970-01-01");
+        assert_eq!(civil_date(-1), "1969-12-31");
+        assert_eq!(civil_date(11016), "2000-02-29");
+        assert_eq!(civil_date(11017), "2000-03-01");
+        assert_eq!(civil_date(20745), "2026-10-19");
+        assert_eq!(today().len(), 10);
+    }
+
+    #[test]
+    fn headers_and_footers_fill_in_tokens() {
+        let mut doc = sample_document(3);
+        let info = doc.add_object(dictionary! { "Title" => Object::string_literal("Annual Report") });
+        doc.trailer.set("Info", info);
+        let input = temp_path("headers-in.pdf");
+        doc.save(&input).unwrap();
+        let output = temp_path("headers-out.pdf");
+        let options = HeaderFooterOptions {
+            all: HeaderFooter { header: ["{title}".to_string(), String::new(), "{filename}".to_string()], footer: [String::new(), "{page} of {pages}".to_string(), String::new()] },
+            first: Some(HeaderFooter { header: [String::new(), "{date}".to_string(), String::new()], ..Default::default() }),
+            ..Default::default()
+        };
+        assert_eq!(headers_footers(&input, output.clone(), &options, &[], &OutputOptions::default()).unwrap(), 3);
+        let texts = page_texts(&Document::load(&output).unwrap());
+        let filename = input.file_name().unwrap().to_string_lossy();
+        assert_eq!(texts[0], format!("Page 1{}", today()));
+        assert_eq!(texts[2], format!("Page 3Annual Report{}3 of 3", filename));
+    }
+
+    fn sequential<T: Send>(run: impl FnOnce() -> T + Send) -> T {
+        rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap().install(run)
+    }
+
+    fn folder_contents(dir: &Path) -> BTreeMap<String, Vec<u8>> {
+        std::fs::read_dir(dir).unwrap().map(|entry| {
+            let path = entry.unwrap().path();
+            (path.file_name().unwrap().to_string_lossy().to_string(), std::fs::read(&path).unwrap())
+        }).collect()
+    }
+
+    #[test]
+    fn parallel_split_matches_a_sequential_run() {
+        let input = write_sample("split-determinism.pdf", 12);
+        let options = OutputOptions::default();
+        let (serial_dir, parallel_dir) = (temp_path("split-serial"), temp_path("split-parallel"));
+        for dir in [&serial_dir, &parallel_dir] { std::fs::create_dir_all(dir).unwrap(); }
+        sequential(|| split_pdf(&input, &serial_dir, SplitOutput::Folder, &options).unwrap());
+        split_pdf(&input, &parallel_dir, SplitOutput::Folder, &options).unwrap();
+        assert_eq!(folder_contents(&serial_dir).len(), 12);
+        assert_eq!(folder_contents(&serial_dir), folder_contents(&parallel_dir));
+
+        let (serial_zip, parallel_zip) = (temp_path("split-serial.zip"), temp_path("split-parallel.zip"));
+        sequential(|| split_pdf(&input, &serial_zip, SplitOutput::Zip, &options).unwrap());
+        split_pdf(&input, &parallel_zip, SplitOutput::Zip, &options).unwrap();
+        assert_eq!(std::fs::read(&serial_zip).unwrap(), std::fs::read(&parallel_zip).unwrap());
+        for dir in [&serial_dir, &parallel_dir] { std::fs::remove_dir_all(dir).unwrap(); }
+        for path in [input, serial_zip, parallel_zip] { std::fs::remove_file(path).unwrap(); }
+    }
+
+    #[test]
+    fn parallel_batch_matches_a_sequential_run() {
+        let inputs: Vec<PathBuf> = (1..=6).map(|n| write_sample(&format!("batch-determinism-{}.pdf", n), n)).collect();
+        let (serial_dir, parallel_dir) = (temp_path("batch-serial"), temp_path("batch-parallel"));
+        let compress = |input: &PathBuf, output: PathBuf| -> Result<(), Box<dyn std::error::Error>> {
+            compress_pdf(input, output, &CompressOptions::default(), &OutputOptions::default()).map(|_| ())
+        };
+        for dir in [&serial_dir, &parallel_dir] { std::fs::create_dir_all(dir).unwrap(); }
+        let serial = sequential(|| batch_process(&inputs, &serial_dir, compress));
+        let parallel = batch_process(&inputs, &parallel_dir, compress);
+        assert!(serial.iter().chain(&parallel).all(|result| result.is_ok()));
+        assert_eq!(folder_contents(&serial_dir).len(), 6);
+        assert_eq!(folder_contents(&serial_dir), folder_contents(&parallel_dir));
+        for dir in [&serial_dir, &parallel_dir] { std::fs::remove_dir_all(dir).unwrap(); }
+        for path in inputs { std::fs::remove_file(path).unwrap(); }
+    }
+
+    #[test]
+    fn batch_outputs_never_overwrite_inputs() {
+        let dir = temp_path("batch-names");
+        let other = dir.join("other");
+        std::fs::create_dir_all(&other).unwrap();
+        // `a.pdf` twice, plus an input already called `2_a.pdf` in the output folder itself.
+        let inputs = vec![dir.join("a.pdf"), other.join("a.pdf"), dir.join("2_a.pdf")];
+        for input in &inputs { std::fs::write(input, b"input").unwrap(); }
+        let results = batch_process(&inputs, &dir, |_, output| Ok(std::fs::write(output, b"output")?));
+        let outputs: Vec<PathBuf> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(outputs, vec![dir.join("1_a.pdf"), dir.join("3_a.pdf"), dir.join("3_2_a.pdf")]);
+        for input in &inputs { assert_eq!(std::fs::read(input).unwrap(), b"input"); }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    // A timing comparison rather than a pass/fail check: `cargo test --release -- --ignored --nocapture split_speedup`.
+    #[test]
+    #[ignore]
+    fn split_speedup() {
+        let input = write_sample("split-bench.pdf", 400);
+        let options = OutputOptions::default();
+        let dir = temp_path("split-bench");
+        std::fs::create_dir_all(&dir).unwrap();
+        let time = |run: &dyn Fn()| { let start = std::time::Instant::now(); run(); start.elapsed() };
+        let serial = time(&|| sequential(|| split_pdf(&input, &dir, SplitOutput::Folder, &options).map(|_| ()).unwrap()));
+        let parallel = time(&|| split_pdf(&input, &dir, SplitOutput::Folder, &options).map(|_| ()).unwrap());
+        println!("split of 400 pages: {:?} on one thread, {:?} on {} threads ({:.1}x)", serial, parallel,
+            rayon::current_num_threads(), serial.as_secs_f64() / parallel.as_secs_f64());
+        std::fs::remove_dir_all(dir).unwrap();
+        std::fs::remove_file(input).unwrap();
+    }
+
+    #[test]
+    fn zip_split_holds_one_page_per_entry_in_order() {
+        let input = write_sample("split-zip.pdf", 11);
+        let output = temp_path("split-zip.zip");
+        assert_eq!(split_pdf(&input, &output, SplitOutput::Zip, &OutputOptions::default()).unwrap(), 11);
+        let mut archive = zip::ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        let names: Vec<String> = archive.file_names().map(String::from).collect();
+        // file_names follows the central directory, which keeps the order the entries were written in.
+        assert_eq!(names, (1..=11).map(|n| format!("page_{}.pdf", n)).collect::<Vec<_>>());
+        for n in 1..=11 {
+            let mut bytes = Vec::new();
+            std::io::Read::read_to_end(&mut archive.by_name(&format!("page_{}.pdf", n)).unwrap(), &mut bytes).unwrap();
+            let page = Document::load_mem(&bytes).unwrap();
+            assert_eq!(page_texts(&page), vec![format!("Page {}", n)]);
+        }
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn compresses_a_file_in_place() {
+        let path = write_sample("compress-in-place.pdf", 5);
+        let original = std::fs::read(&path).unwrap();
+        let options = CompressOptions { object_streams: true, ..CompressOptions::default() };
+        let report = compress_pdf(&path, path.clone(), &options, &OutputOptions::default()).unwrap();
+        assert_eq!(report.original_size, original.len() as u64);
+        assert!(!report.kept_original && report.new_size < report.original_size);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), report.new_size);
+        assert_eq!(page_texts(&Document::load(&path).unwrap()), (1..=5).map(|n| format!("Page {}", n)).collect::<Vec<_>>());
+
+        // Written without object streams, the already compressed file comes out larger and is left as it is.
+        let compressed = std::fs::read(&path).unwrap();
+        let options = CompressOptions { metadata: MetadataMode::Keep, ..CompressOptions::default() };
+        let report = compress_pdf(&path, path.clone(), &options, &OutputOptions::default()).unwrap();
+        assert!(report.kept_original);
+        assert_eq!(report.new_size, compressed.len() as u64);
+        assert_eq!(std::fs::read(&path).unwrap(), compressed);
+        assert!(!partial_path(&path).exists());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn rotations(path: &Path) -> Vec<i64> {
+        let doc = Document::load(path).unwrap();
+        doc.get_pages().into_values().map(|id| page_rotation(&doc, id)).collect()
+    }
+
+    #[test]
+    fn rotation_angles_are_normalized() {
+        let mut doc = sample_document(3);
+        let first = doc.get_pages()[&1];
+        doc.get_dictionary_mut(first).unwrap().set("Rotate", 90);
+        let input = temp_path("rotate-normalize.pdf");
+        doc.save(&input).unwrap();
+        let output = temp_path("rotate-normalize-out.pdf");
+        let options = OutputOptions::default();
+        assert_eq!(rotate_pdf(&input, output.clone(), Rotation::By(-90), &[], false, &options).unwrap(), 3);
+        assert_eq!(rotations(&output), [0, 270, 270]);
+        assert_eq!(rotate_pdf(&input, output.clone(), Rotation::By(270), &[], false, &options).unwrap(), 3);
+        assert_eq!(rotations(&output), [0, 270, 270]);
+        assert_eq!(rotate_pdf(&input, output.clone(), Rotation::By(450), &[], false, &options).unwrap(), 3);
+        assert_eq!(rotations(&output), [180, 90, 90]);
+        // A full turn leaves every page as it was.
+        assert_eq!(rotate_pdf(&input, output.clone(), Rotation::By(-360), &[], false, &options).unwrap(), 0);
+        assert_eq!(rotations(&output), [90, 0, 0]);
+        assert!(rotate_pdf(&input, output.clone(), Rotation::By(45), &[], false, &options).is_err());
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn rotates_only_the_selected_pages() {
+        let input = write_sample("rotate-selected.pdf", 4);
+        let output = temp_path("rotate-selected-out.pdf");
+        let options = OutputOptions::default();
+        // Page 3 is listed twice but only turned once.
+        assert_eq!(rotate_pdf(&input, output.clone(), Rotation::By(90), &[3, 1, 3], false, &options).unwrap(), 2);
+        assert_eq!(rotations(&output), [90, 0, 90, 0]);
+        assert!(rotate_pdf(&input, output.clone(), Rotation::By(90), &[5], false, &options).is_err());
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn auto_orient_turns_pages_of_the_other_shape() {
+        let mut doc = sample_document(3);
+        let pages = doc.get_pages();
+        // Page 1 stays portrait, page 2 is landscape and page 3 is portrait paper shown landscape.
+        doc.get_dictionary_mut(pages[&2]).unwrap().set("MediaBox", vec![0.into(), 0.into(), 792.into(), 612.into()]);
+        doc.get_dictionary_mut(pages[&3]).unwrap().set("Rotate", 90);
+        let input = temp_path("auto-orient.pdf");
+        doc.save(&input).unwrap();
+        let output = temp_path("auto-orient-out.pdf");
+        let options = OutputOptions::default();
+        let portrait = Rotation::AutoOrient { target: PageOrientation::Portrait, detect_text: false };
+        assert_eq!(rotate_pdf(&input, output.clone(), portrait, &[], false, &options).unwrap(), 2);
+        assert_eq!(rotations(&output), [0, 90, 180]);
+        let landscape = Rotation::AutoOrient { target: PageOrientation::Landscape, detect_text: false };
+        assert_eq!(rotate_pdf(&input, output.clone(), landscape, &[], false, &options).unwrap(), 1);
+        assert_eq!(rotations(&output), [90, 0, 90]);
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+
+    // The matrix each tile draws its page with.
+    fn tile_matrices(doc: &Document) -> Vec<compress::Matrix> {
+        doc.get_pages().into_values().map(|id| {
+            let content = Content::decode(&doc.get_page_content(id).unwrap()).unwrap();
+            compress::to_matrix(&content.operations.iter().find(|op| op.operator == "cm").unwrap().operands).unwrap()
+        }).collect()
+    }
+
+    #[test]
+    fn poster_tiles_overlap_and_cover_the_page() {
+        let input = write_sample("poster.pdf", 1);
+        let output = temp_path("poster-out.pdf");
+        let options = PosterOptions { paper: PaperSize::A4, scale: 2.0, overlap: 30.0, margin: 20.0, cut_marks: true, labels: true };
+        // The 1224 by 1584 point page takes 2 by 3 landscape sheets with 802 by 555 point tiles, against 3 by 3
+        // portrait ones.
+        assert_eq!(poster(&input, output.clone(), &options, &[], &OutputOptions::default()).unwrap(), 6);
+        let doc = Document::load(&output).unwrap();
+        for id in doc.get_pages().into_values() { assert_eq!(pages::displayed_size(&doc, id), Some((842.0, 595.0))); }
+        let labels: Vec<String> = page_texts(&doc);
+        assert_eq!(labels[0], "Page 1, row 1 of 3, column 1 of 2");
+        assert_eq!(labels[3], "Page 1, row 2 of 3, column 2 of 2");
+        // Neighbouring tiles move on by the tile size less the overlap, and the last row and column reach the edges.
+        let m = tile_matrices(&doc);
+        assert_eq!(m[0][4] - m[1][4], 802.0 - 30.0);
+        assert_eq!(m[2][5] - m[0][5], 555.0 - 30.0);
+        assert_eq!(m[0][5] + 1584.0, 20.0 + 555.0);
+        assert!(m[5][4] - 20.0 + 1224.0 <= 802.0 && m[5][5] >= 20.0);
+
+        let options = PosterOptions { overlap: 100.0, ..options };
+        assert_eq!(poster(&input, output.clone(), &options, &[], &OutputOptions::default()).unwrap(), 8);
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+
+    // A landscape spread with a note on each half.
+    fn write_spread(name: &str) -> (PathBuf, [ObjectId; 2]) {
+        let mut doc = sample_document(3);
+        let page_id = doc.get_pages()[&2];
+        let note = |doc: &mut Document, x: i64| doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Text", "P" => page_id, "Rect" => vec![x.into(), 100.into(), (x + 20).into(), 120.into()],
+        });
+        let notes = [note(&mut doc, 100), note(&mut doc, 900)];
+        let page = doc.get_dictionary_mut(page_id).unwrap();
+        page.set("MediaBox", vec![0.into(), 0.into(), 1200.into(), 800.into()]);
+        page.set("Annots", notes.map(Object::Reference).to_vec());
+        let path = temp_path(name);
+        doc.save(&path).unwrap();
+        (path, notes)
+    }
+
+    fn annotations(doc: &Document, page_id: lopdf::ObjectId) -> Vec<lopdf::ObjectId> {
+        doc.get_dictionary(page_id).unwrap().get(b"Annots").and_then(Object::as_array)
+            .map(|a| a.iter().map(|o| o.as_reference().unwrap()).collect()).unwrap_or_default()
+    }
+
+    #[test]
+    fn splits_spreads_in_reading_order() {
+        let (input, [left_note, right_note]) = write_spread("spread.pdf");
+        let output = temp_path("spread-out.pdf");
+        for right_to_left in [false, true] {
+            let count = split_spreads(&input, output.clone(), SplitDirection::Vertical, right_to_left, &[2], &OutputOptions::default()).unwrap();
+            assert_eq!(count, 4);
+            let doc = Document::load(&output).unwrap();
+            let pages = doc.get_pages();
+            assert_eq!(page_texts(&doc), ["Page 1", "Page 2", "Page 2", "Page 3"]);
+            let (first, second) = if right_to_left { (right_note, left_note) } else { (left_note, right_note) };
+            let left_box = [0.0, 0.0, 600.0, 800.0];
+            let right_box = [600.0, 0.0, 1200.0, 800.0];
+            let (first_box, second_box) = if right_to_left { (right_box, left_box) } else { (left_box, right_box) };
+            // Each half shows its side of the spread and holds the note on that side, pointing back at it.
+            for (page_id, rect, note) in [(pages[&2], first_box, first), (pages[&3], second_box, second)] {
+                assert_eq!(page_box(&doc, page_id), Some(rect));
+                assert_eq!(annotations(&doc, page_id), [note]);
+                assert_eq!(doc.get_dictionary(note).unwrap().get(b"P").unwrap().as_reference().unwrap(), page_id);
+            }
+        }
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn splits_spreads_across_top_then_bottom() {
+        let (input, _) = write_spread("spread-horizontal.pdf");
+        let output = temp_path("spread-horizontal-out.pdf");
+        // Right to left only changes the order of vertical cuts.
+        assert_eq!(split_spreads(&input, output.clone(), SplitDirection::Horizontal, true, &[2], &OutputOptions::default()).unwrap(), 4);
+        let doc = Document::load(&output).unwrap();
+        let pages = doc.get_pages();
+        assert_eq!(page_box(&doc, pages[&2]), Some([0.0, 400.0, 1200.0, 800.0]));
+        assert_eq!(page_box(&doc, pages[&3]), Some([0.0, 0.0, 1200.0, 400.0]));
+        // Both notes sit near the bottom.
+        assert!(annotations(&doc, pages[&2]).is_empty());
+        assert_eq!(annotations(&doc, pages[&3]).len(), 2);
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+
+    fn png(img: image::DynamicImage) -> image::DynamicImage {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+        image::load_from_memory(bytes.get_ref()).unwrap()
+    }
+
+    #[test]
+    fn transparent_images_get_a_soft_mask() {
+        let mut doc = Document::with_version("1.5");
+        let rgba = png(image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(4, 2, |x, _| image::Rgba([200, 10, 10, (x * 80) as u8]))));
+        let xobject = image_xobject(&mut doc, &rgba).unwrap();
+        assert_eq!(xobject.dict.get(b"ColorSpace").unwrap().as_name().unwrap(), b"DeviceRGB");
+        let mask = doc.get_object(xobject.dict.get(b"SMask").unwrap().as_reference().unwrap()).unwrap().as_stream().unwrap();
+        assert_eq!(mask.dict.get(b"ColorSpace").unwrap().as_name().unwrap(), b"DeviceGray");
+        assert_eq!(mask.get_plain_content().unwrap(), [0, 80, 160, 240, 0, 80, 160, 240]);
+        assert_eq!(xobject.get_plain_content().unwrap(), [200, 10, 10].repeat(8));
+    }
+
+    #[test]
+    fn opaque_images_get_no_mask() {
+        let mut doc = Document::with_version("1.5");
+        let opaque = png(image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(4, 2, image::Rgba([1, 2, 3, 255]))));
+        let rgb = png(image::DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 2, image::Rgb([1, 2, 3]))));
+        for img in [opaque, rgb] {
+            assert!(!image_xobject(&mut doc, &img).unwrap().dict.has(b"SMask"));
+        }
+        assert!(doc.objects.is_empty());
+    }
+
+    // The text of the stamp page drawn on each page, if any.
+    fn overlaid(doc: &Document) -> Vec<Option<String>> {
+        doc.get_pages().into_values().map(|id| {
+            let resources = compress::page_resources(doc, id)?;
+            let xobjects = compress::resolve_dict(doc, resources.get(b"XObject").ok()?)?;
+            let form = doc.get_object(xobjects.get(b"StampPage").ok()?.as_reference().ok()?).ok()?.as_stream().ok()?;
+            let content = Content::decode(&form.get_plain_content().ok()?).ok()?;
+            content.operations.iter().find(|op| op.operator == "Tj")
+                .map(|op| String::from_utf8_lossy(op.operands[0].as_str().unwrap()).to_string())
+        }).collect()
+    }
+
+    #[test]
+    fn overlays_stamp_pages_by_mode() {
+        let input = write_sample("overlay-in.pdf", 4);
+        let stamp_pdf = write_sample("overlay-stamp.pdf", 2);
+        let output = temp_path("overlay-out.pdf");
+        let run = |mode, pages: &[u32]| {
+            let stamped = overlay(&input, &stamp_pdf, output.clone(), mode, false, pages, &OutputOptions::default()).unwrap();
+            let doc = Document::load(&output).unwrap();
+            assert_eq!(page_texts(&doc), ["Page 1", "Page 2", "Page 3", "Page 4"]);
+            (stamped, overlaid(&doc))
+        };
+        let page = |n: u32| Some(format!("Page {}", n));
+        assert_eq!(run(OverlayMode::Repeat, &[]), (4, vec![page(1), page(1), page(1), page(1)]));
+        // Pages past the end of the stamp PDF are left alone.
+        assert_eq!(run(OverlayMode::Match, &[]), (2, vec![page(1), page(2), None, None]));
+        assert_eq!(run(OverlayMode::Match, &[2, 3]), (1, vec![None, page(2), None, None]));
+        assert_eq!(run(OverlayMode::FirstOther, &[]), (4, vec![page(1), page(2), page(2), page(2)]));
+        // The first page of the selection gets the first stamp page, whatever its number.
+        assert_eq!(run(OverlayMode::FirstOther, &[3, 2, 4]), (3, vec![None, page(2), page(1), page(2)]));
+    }
+}
@@ -0,0 +1,354 @@
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId};
+use lopdf::content::Operation;
+use crate::compress::{self, Matrix};
+use crate::fonts::WIN_ANSI_HIGH;
+use crate::pages;
+use crate::pdf;
+
+// Advance widths of the printable ASCII characters in the standard fonts, in thousandths of the font size.
+const HELVETICA: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556, 556, 556,
+    556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556,
+    556, 222, 222, 500, 222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+const HELVETICA_BOLD: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556, 556, 556,
+    556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556, 333, 556, 611, 556, 611, 556, 333, 611,
+    611, 278, 278, 556, 278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+const TIMES_ROMAN: [u16; 95] = [
+    250, 333, 408, 500, 500, 833, 778, 180, 333, 333, 500, 564, 250, 333, 250, 278, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 278, 278, 564, 564, 564, 444, 921, 722, 667, 667, 722, 611, 556, 722, 722, 333, 389, 722, 611, 889, 722, 722,
+    556, 722, 667, 556, 611, 722, 722, 944, 722, 722, 611, 333, 278, 333, 469, 500, 333, 444, 500, 444, 500, 444, 333, 500,
+    500, 278, 278, 500, 278, 778, 500, 500, 500, 500, 333, 389, 278, 500, 500, 722, 500, 500, 444, 480, 200, 480, 541,
+];
+const TIMES_BOLD: [u16; 95] = [
+    250, 333, 555, 500, 500, 1000, 833, 278, 333, 333, 500, 570, 250, 333, 250, 278, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 333, 333, 570, 570, 570, 500, 930, 722, 667, 722, 722, 667, 611, 778, 778, 389, 500, 778, 667, 944, 722, 778,
+    611, 778, 722, 556, 667, 722, 722, 1000, 722, 722, 667, 333, 278, 333, 581, 500, 333, 500, 556, 444, 556, 444, 333, 500,
+    556, 278, 333, 556, 278, 833, 556, 500, 556, 556, 444, 389, 333, 556, 500, 722, 500, 500, 444, 394, 220, 394, 520,
+];
+// Capital height as a share of the font size, which text is centered on.
+const CAP_HEIGHT: f32 = 0.7;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StandardFont { Helvetica, HelveticaBold, TimesRoman, TimesBold, Courier, CourierBold }
+
+impl StandardFont {
+    pub const ALL: [StandardFont; 6] = [
+        StandardFont::Helvetica, StandardFont::HelveticaBold, StandardFont::TimesRoman, StandardFont::TimesBold, StandardFont::Courier, StandardFont::CourierBold,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StandardFont::Helvetica => "helvetica",
+            StandardFont::HelveticaBold => "helvetica-bold",
+            StandardFont::TimesRoman => "times",
+            StandardFont::TimesBold => "times-bold",
+            StandardFont::Courier => "courier",
+            StandardFont::CourierBold => "courier-bold",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<StandardFont> {
+        let name = name.to_ascii_lowercase();
+        StandardFont::ALL.into_iter().find(|f| f.name() == name)
+    }
+
+    pub fn base_font(self) -> &'static str {
+        match self {
+            StandardFont::Helvetica => "Helvetica",
+            StandardFont::HelveticaBold => "Helvetica-Bold",
+            StandardFont::TimesRoman => "Times-Roman",
+            StandardFont::TimesBold => "Times-Bold",
+            StandardFont::Courier => "Courier",
+            StandardFont::CourierBold => "Courier-Bold",
+        }
+    }
+
+    // A font dictionary every reader can show without an embedded font program, encoded as WinAnsi.
+    pub fn dictionary(self) -> Dictionary {
+        dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => self.base_font(), "Encoding" => "WinAnsiEncoding" }
+    }
+
+    // Width of the encoded text at the given size. Characters beyond ASCII count as a typical letter.
+    pub fn width(self, encoded: &[u8], size: f32) -> f32 {
+        let (table, typical) = match self {
+            StandardFont::Helvetica => (Some(&HELVETICA), 556),
+            StandardFont::HelveticaBold => (Some(&HELVETICA_BOLD), 611),
+            StandardFont::TimesRoman => (Some(&TIMES_ROMAN), 500),
+            StandardFont::TimesBold => (Some(&TIMES_BOLD), 556),
+            StandardFont::Courier | StandardFont::CourierBold => (None, 600),
+        };
+        let units: u32 = encoded.iter().map(|&c| match table {
+            Some(table) if (32..127).contains(&c) => u32::from(table[c as usize - 32]),
+            _ => typical,
+        }).sum();
+        units as f32 * size / 1000.0
+    }
+}
+
+// The text in WinAnsiEncoding, with characters it cannot show replaced by question marks.
+pub fn encode(text: &str) -> Vec<u8> {
+    text.chars().map(|c| match c as u32 {
+        code @ (0x20..0x7F | 0xA0..=0xFF) => code as u8,
+        code => WIN_ANSI_HIGH.iter().position(|&u| u != 0 && u32::from(u) == code).map_or(b'?', |i| 0x80 + i as u8),
+    }).collect()
+}
+
+// A color given as "#rrggbb" or "rrggbb", as RGB components between 0 and 1.
+pub fn parse_color(text: &str) -> Option<[f32; 3]> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 || !hex.is_ascii() { return None; }
+    let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok().map(|v| v as f32 / 255.0);
+    Some([component(0)?, component(2)?, component(4)?])
+}
+
+// Where on the page, as displayed, a stamp goes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Position { TopLeft, Top, TopRight, Left, Center, Right, BottomLeft, Bottom, BottomRight }
+
+impl Position {
+    pub const ALL: [Position; 9] = [
+        Position::TopLeft, Position::Top, Position::TopRight, Position::Left, Position::Center, Position::Right, Position::BottomLeft, Position::Bottom, Position::BottomRight,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Position::TopLeft => "top-left",
+            Position::Top => "top",
+            Position::TopRight => "top-right",
+            Position::Left => "left",
+            Position::Center => "center",
+            Position::Right => "right",
+            Position::BottomLeft => "bottom-left",
+            Position::Bottom => "bottom",
+            Position::BottomRight => "bottom-right",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Position> {
+        Position::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    // Center of a box of the given size placed here on a page of the given size, `margin` in from the edges.
+    fn center(self, (width, height): (f32, f32), (box_width, box_height): (f32, f32), margin: f32) -> (f32, f32) {
+        let index = Position::ALL.iter().position(|&p| p == self).unwrap_or(4);
+        let along = |i: usize, length: f32, size: f32| match i {
+            0 => margin + size / 2.0,
+            1 => length / 2.0,
+            _ => length - margin - size / 2.0,
+        };
+        (along(index % 3, width, box_width), along(2 - index / 3, height, box_height))
+    }
+}
+
+// A line of text drawn on pages.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextStamp {
+    pub text: String,
+    pub font: StandardFont,
+    pub size: f32,
+    pub color: [f32; 3],
+    // 0 is invisible, 1 opaque.
+    pub opacity: f32,
+    // Degrees counter-clockwise.
+    pub rotation: f32,
+    pub position: Position,
+    // Distance from the page edges for positions other than the center.
+    pub margin: f32,
+    // Draw below the page's content instead of over it.
+    pub under: bool,
+}
+
+impl Default for TextStamp {
+    fn default() -> Self {
+        TextStamp {
+            text: String::new(), font: StandardFont::Helvetica, size: 12.0, color: [0.0; 3], opacity: 1.0,
+            rotation: 0.0, position: Position::Center, margin: 36.0, under: false,
+        }
+    }
+}
+
+// An image drawn on pages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageStamp {
+    // Width on the page in points; the height follows the image's aspect ratio.
+    pub width: f32,
+    pub opacity: f32,
+    // Degrees counter-clockwise.
+    pub rotation: f32,
+    // Corner, edge or center of the page the image is placed at, touching the page edges.
+    pub position: Position,
+    // Moves the image from its position, to the right and up as displayed.
+    pub offset: (f32, f32),
+    pub under: bool,
+}
+
+impl Default for ImageStamp {
+    fn default() -> Self {
+        ImageStamp { width: 144.0, opacity: 1.0, rotation: 0.0, position: Position::BottomRight, offset: (-36.0, 36.0), under: false }
+    }
+}
+
+// Maps the page's visible area as displayed, lower left corner at the origin, onto its user space, along with
+// the displayed size.
+fn display_space(doc: &Document, page_id: ObjectId) -> Option<(Matrix, (f32, f32))> {
+    let visible = pdf::page_box(doc, page_id)?;
+    let size = pages::displayed_size(doc, page_id)?;
+    Some((pages::invert(&pages::upright_matrix(visible, pdf::page_rotation(doc, page_id))), size))
+}
+
+// Matrix that draws a box of the given size, with its lower left corner at the origin, turned and placed as the
+// stamp's position says on the page as displayed.
+fn placement(doc: &Document, page_id: ObjectId, (width, height): (f32, f32), rotation: f32, position: Position, margin: f32, offset: (f32, f32)) -> Option<Matrix> {
+    let (display, page_size) = display_space(doc, page_id)?;
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let turned = (width * cos.abs() + height * sin.abs(), width * sin.abs() + height * cos.abs());
+    let (x, y) = position.center(page_size, turned, margin);
+    let (x, y) = (x + offset.0, y + offset.1);
+    let centered = [1.0, 0.0, 0.0, 1.0, -width / 2.0, -height / 2.0];
+    let turn = [cos, sin, -sin, cos, x, y];
+    Some(compress::multiply(&compress::multiply(&centered, &turn), &display))
+}
+
+// Graphics state resources for the given opacity, or none when opaque.
+fn opacity_state(opacity: f32) -> Option<Dictionary> {
+    (opacity < 1.0).then(|| dictionary! { "Type" => "ExtGState", "ca" => opacity.max(0.0), "CA" => opacity.max(0.0) })
+}
+
+// Draws the stamp's text on the page.
+pub fn stamp_text(doc: &mut Document, page_id: ObjectId, stamp: &TextStamp) -> Result<(), Box<dyn std::error::Error>> {
+    stamp_texts(doc, page_id, std::slice::from_ref(stamp), stamp.under)
+}
+
+// Draws the texts of several stamps on the page in one content stream, over its content or under it.
+pub fn stamp_texts(doc: &mut Document, page_id: ObjectId, stamps: &[TextStamp], under: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut fonts, mut states) = (Dictionary::new(), Dictionary::new());
+    let mut operations = Vec::new();
+    for stamp in stamps {
+        let encoded = encode(&stamp.text);
+        if encoded.is_empty() { continue; }
+        let size = (stamp.font.width(&encoded, stamp.size), stamp.size * CAP_HEIGHT);
+        let matrix = placement(doc, page_id, size, stamp.rotation, stamp.position, stamp.margin, (0.0, 0.0)).ok_or("The page has no MediaBox.")?;
+        let font = format!("Stamp{}", stamp.font.base_font().replace('-', ""));
+        fonts.set(font.as_bytes(), stamp.font.dictionary());
+        operations.push(Operation::new("q", vec![]));
+        if let Some(state) = opacity_state(stamp.opacity) {
+            let name = format!("StampAlpha{}", (stamp.opacity * 100.0).round());
+            states.set(name.as_bytes(), state);
+            operations.push(Operation::new("gs", vec![Object::Name(name.into_bytes())]));
+        }
+        let [r, g, b] = stamp.color;
+        operations.extend([
+            Operation::new("cm", matrix.iter().map(|&v| v.into()).collect()),
+            Operation::new("rg", vec![r.into(), g.into(), b.into()]),
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec![Object::Name(font.into_bytes()), stamp.size.into()]),
+            Operation::new("Tj", vec![Object::String(encoded, lopdf::StringFormat::Literal)]),
+            Operation::new("ET", vec![]),
+            Operation::new("Q", vec![]),
+        ]);
+    }
+    if operations.is_empty() { return Ok(()); }
+    let mut resources = dictionary! { "Font" => fonts };
+    if !states.is_empty() { resources.set("ExtGState", states); }
+    pages::add_content(doc, page_id, operations, &resources, under)
+}
+
+// Draws the image XObject on the page, `aspect` being its height over its width.
+pub fn stamp_image(doc: &mut Document, page_id: ObjectId, image: ObjectId, aspect: f32, stamp: &ImageStamp) -> Result<(), Box<dyn std::error::Error>> {
+    let size = (stamp.width, stamp.width * aspect);
+    let matrix = placement(doc, page_id, size, stamp.rotation, stamp.position, 0.0, stamp.offset).ok_or("The page has no MediaBox.")?;
+    // Images are drawn into the unit square, so the matrix is scaled up to the image's size.
+    let matrix = compress::multiply(&[size.0, 0.0, 0.0, size.1, 0.0, 0.0], &matrix);
+    let mut resources = dictionary! { "XObject" => dictionary! { "StampImage" => image } };
+    let mut operations = Vec::new();
+    if let Some(state) = opacity_state(stamp.opacity) {
+        resources.set("ExtGState", dictionary! { "StampState" => state });
+        operations.push(Operation::new("gs", vec!["StampState".into()]));
+    }
+    operations.push(Operation::new("cm", matrix.iter().map(|&v| v.into()).collect()));
+    operations.push(Operation::new("Do", vec!["StampImage".into()]));
+    pages::add_content(doc, page_id, operations, &resources, stamp.under)
+}
+
+// Draws a Form XObject holding an upright page of the given size, such as a letterhead, over or under the page,
+// scaled to fit its visible area as displayed and centered.
+pub fn stamp_form(doc: &mut Document, page_id: ObjectId, form: ObjectId, size: (f32, f32), under: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let (display, (width, height)) = display_space(doc, page_id).ok_or("The page has no MediaBox.")?;
+    let matrix = compress::multiply(&pages::fit_into(size, [0.0, 0.0, width, height]), &display);
+    let operations = vec![
+        Operation::new("cm", matrix.iter().map(|&v| v.into()).collect()),
+        Operation::new("Do", vec!["StampPage".into()]),
+    ];
+    pages::add_content(doc, page_id, operations, &dictionary! { "XObject" => dictionary! { "StampPage" => form } }, under)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_text_as_win_ansi() {
+        assert_eq!(encode("Draft é"), b"Draft \xE9");
+        assert_eq!(encode("€5 — “ok”"), b"\x805 \x97 \x93ok\x94");
+    }
+
+    #[test]
+    fn replaces_characters_win_ansi_cannot_show() {
+        assert_eq!(encode("中\tx"), b"??x");
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(parse_color("#ff0000"), Some([1.0, 0.0, 0.0]));
+        assert_eq!(parse_color("00FF33"), Some([0.0, 1.0, 0.2]));
+    }
+
+    #[test]
+    fn rejects_malformed_colors() {
+        for text in ["", "#fff", "#ff00000", "gg0000", "#é1234", "red"] {
+            assert_eq!(parse_color(text), None, "{:?} was accepted", text);
+        }
+    }
+
+    fn corners(matrix: Matrix, (width, height): (f32, f32)) -> [(f32, f32); 2] {
+        [pages::transform(&matrix, 0.0, 0.0), pages::transform(&matrix, width, height)]
+    }
+
+    fn assert_near(actual: [(f32, f32); 2], expected: [(f32, f32); 2]) {
+        let near = actual.iter().zip(expected).all(|(a, e)| (a.0 - e.0).abs() < 0.01 && (a.1 - e.1).abs() < 0.01);
+        assert!(near, "{:?} is not {:?}", actual, expected);
+    }
+
+    #[test]
+    fn places_boxes_at_anchors_with_margins_and_offsets() {
+        let doc = crate::pdf::tests::sample_document(1);
+        let page_id = doc.page_iter().next().unwrap();
+        let size = (100.0, 50.0);
+        let at = |position, margin, offset| corners(placement(&doc, page_id, size, 0.0, position, margin, offset).unwrap(), size);
+        // On the 612 by 792 point page.
+        assert_near(at(Position::BottomRight, 0.0, (-36.0, 36.0)), [(476.0, 36.0), (576.0, 86.0)]);
+        assert_near(at(Position::TopLeft, 10.0, (0.0, 0.0)), [(10.0, 732.0), (110.0, 782.0)]);
+        assert_near(at(Position::Center, 10.0, (5.0, -5.0)), [(261.0, 366.0), (361.0, 416.0)]);
+        assert_near(at(Position::Right, 20.0, (0.0, 0.0)), [(492.0, 371.0), (592.0, 421.0)]);
+        // A quarter turn counter-clockwise keeps the box centered, now standing upright.
+        let turned = corners(placement(&doc, page_id, size, 90.0, Position::Center, 0.0, (0.0, 0.0)).unwrap(), size);
+        assert_near(turned, [(331.0, 346.0), (281.0, 446.0)]);
+    }
+
+    #[test]
+    fn places_boxes_on_rotated_pages_as_displayed() {
+        let mut doc = crate::pdf::tests::sample_document(1);
+        let page_id = doc.page_iter().next().unwrap();
+        doc.get_dictionary_mut(page_id).unwrap().set("Rotate", 90);
+        let size = (100.0, 50.0);
+        // Turned a quarter clockwise, the page shows the right end of its bottom edge at the lower left, and its
+        // right edge runs along the bottom.
+        let matrix = placement(&doc, page_id, size, 0.0, Position::BottomLeft, 0.0, (0.0, 0.0)).unwrap();
+        assert_near(corners(matrix, size), [(612.0, 0.0), (562.0, 100.0)]);
+    }
+}
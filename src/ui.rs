@@ -1,12 +1,17 @@
 use gtk::prelude::*;
 use gtk::{
     Button, FileChooserAction, FileChooserDialog, FileFilter, Orientation, ResponseType, Window,
-    Notebook, Entry, Label,
+    Notebook, Entry, Label, CheckButton, SpinButton, ComboBoxText, DrawingArea,
 };
-use std::cell::RefCell;
+use gtk::cairo::ImageSurface;
+use gtk::gdk::EventMask;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::rc::Rc;
+use crate::bounds::Mark;
 use crate::pdf;
+use crate::stamp::{self, Position, StandardFont};
 
 pub fn create_merge_tab(notebook: &Notebook, window: &Window) {
     let box_container = gtk::Box::new(Orientation::Vertical, 10);
@@ -47,8 +52,8 @@ pub fn create_merge_tab(notebook: &Notebook, window: &Window) {
     action_btn.connect_clicked(move |_| {
         let window = match window_weak.upgrade() { Some(w) => w, None => return };
         let files = files_clone.borrow();
-        if let Some(output) = save_dialog(&window, "merged.pdf") {
-            match pdf::merge_pdfs(&files, output) {
+        if let Some((output, output_options)) = save_dialog(&window, "merged.pdf") {
+            match pdf::merge_pdfs(&files, output, &output_options) {
                 Ok(_) => show_message(&window, gtk::MessageType::Info, "Success", "PDFs merged successfully!"),
                 Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
             }
@@ -68,13 +73,35 @@ pub fn create_split_tab(notebook: &Notebook, window: &Window) {
     let select_btn = Button::with_label("Select PDF");
     box_container.pack_start(&select_btn, false, false, 0);
 
+    let zip_check = CheckButton::with_label("Save pages as one ZIP archive");
+    box_container.pack_start(&zip_check, false, false, 0);
+
     let action_btn = Button::with_label("Split All Pages");
     action_btn.set_sensitive(false);
     box_container.pack_start(&action_btn, false, false, 0);
 
+    // Split Spreads Section
+    box_container.pack_start(&Label::new(Some("Split double-page spreads into single pages")), false, false, 0);
+    let pages_label = Label::new(Some("Pages to split (e.g. 1, 3-5; leave empty for all pages)"));
+    box_container.pack_start(&pages_label, false, false, 0);
+    let pages_entry = Entry::new();
+    box_container.pack_start(&pages_entry, false, false, 0);
+    let spread_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let direction_combo = ComboBoxText::new();
+    direction_combo.append(Some("vertical"), "Left and right halves");
+    direction_combo.append(Some("horizontal"), "Top and bottom halves");
+    direction_combo.set_active_id(Some("vertical"));
+    spread_row.pack_start(&direction_combo, false, false, 0);
+    let right_to_left_check = CheckButton::with_label("Right-to-left (right half first)");
+    spread_row.pack_start(&right_to_left_check, false, false, 0);
+    box_container.pack_start(&spread_row, false, false, 0);
+    let spreads_btn = Button::with_label("Split Spreads");
+    spreads_btn.set_sensitive(false);
+    box_container.pack_start(&spreads_btn, false, false, 0);
+
     let selected_file: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
     let label_clone = label.clone();
-    let action_btn_clone = action_btn.clone();
+    let buttons = [action_btn.clone(), spreads_btn.clone()];
     let file_clone = Rc::clone(&selected_file);
     let window_weak = window.downgrade();
 
@@ -86,25 +113,38 @@ pub fn create_split_tab(notebook: &Notebook, window: &Window) {
             *file = dialog.filename();
             if let Some(f) = &*file {
                 label_clone.set_text(&format!("Selected: {}", f.file_name().unwrap().to_string_lossy()));
-                action_btn_clone.set_sensitive(true);
+                for btn in &buttons { btn.set_sensitive(true); }
             }
         }
         dialog.close();
     });
 
     let file_clone = Rc::clone(&selected_file);
+    let zip_check_clone = zip_check.clone();
     let window_weak = window.downgrade();
     action_btn.connect_clicked(move |_| {
         let window = match window_weak.upgrade() { Some(w) => w, None => return };
         let file = file_clone.borrow();
         if let Some(input) = &*file {
+            if zip_check_clone.is_active() {
+                if let Some((output, output_options)) = save_dialog(&window, "pages.zip") {
+                    match pdf::split_pdf(input, &output, pdf::SplitOutput::Zip, &output_options) {
+                        Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("Split into {} pages.", c)),
+                        Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
+                    }
+                }
+                return;
+            }
             let dialog = FileChooserDialog::with_buttons(
                 Some("Select Output Folder"), Some(&window), FileChooserAction::SelectFolder,
                 &[("_Cancel", ResponseType::Cancel), ("_Select", ResponseType::Accept)]
             );
+            let linearize_check = CheckButton::with_label("Linearize for fast web view");
+            dialog.set_extra_widget(&linearize_check);
             if dialog.run() == ResponseType::Accept {
                 if let Some(output_dir) = dialog.filename() {
-                    match pdf::split_pdf(input, &output_dir) {
+                    let output_options = pdf::OutputOptions { linearize: linearize_check.is_active() };
+                    match pdf::split_pdf(input, &output_dir, pdf::SplitOutput::Folder, &output_options) {
                         Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("Split into {} pages.", c)),
                         Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
                     }
@@ -113,6 +153,26 @@ pub fn create_split_tab(notebook: &Notebook, window: &Window) {
             dialog.close();
         }
     });
+
+    let file_clone = Rc::clone(&selected_file);
+    let window_weak = window.downgrade();
+    spreads_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let file = file_clone.borrow();
+        if let Some(input) = &*file {
+            let pages = match pdf::parse_page_ranges(&pages_entry.text()) {
+                Ok(p) => p,
+                Err(e) => { show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)); return; }
+            };
+            let direction = direction_combo.active_id().and_then(|id| pdf::SplitDirection::from_name(&id)).unwrap_or(pdf::SplitDirection::Vertical);
+            if let Some((output, output_options)) = save_dialog(&window, "single_pages.pdf") {
+                match pdf::split_spreads(input, output, direction, right_to_left_check.is_active(), &pages, &output_options) {
+                    Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("The document now has {} pages.", c)),
+                    Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
+                }
+            }
+        }
+    });
 }
 
 pub fn create_compress_tab(notebook: &Notebook, window: &Window) {
@@ -127,6 +187,56 @@ pub fn create_compress_tab(notebook: &Notebook, window: &Window) {
     let select_btn = Button::with_label("Select PDF");
     box_container.pack_start(&select_btn, false, false, 0);
 
+    let preset_row = gtk::Box::new(Orientation::Horizontal, 10);
+    preset_row.pack_start(&Label::new(Some("Preset")), false, false, 0);
+    let preset_combo = ComboBoxText::new();
+    preset_combo.append(Some("screen"), "Screen (72 dpi, smallest)");
+    preset_combo.append(Some("ebook"), "Ebook (150 dpi)");
+    preset_combo.append(Some("print"), "Print (300 dpi)");
+    preset_combo.append(Some("lossless"), "Lossless");
+    preset_combo.append(Some("compatibility"), "Compatibility (PDF 1.4 readers)");
+    preset_row.pack_end(&preset_combo, false, false, 0);
+    box_container.pack_start(&preset_row, false, false, 0);
+
+    let images_check = CheckButton::with_label("Downsample and recompress images");
+    box_container.pack_start(&images_check, false, false, 0);
+    let dpi_spin = labeled_spin(&box_container, "Image resolution (dpi)", 36.0, 600.0, 150.0);
+    let quality_spin = labeled_spin(&box_container, "JPEG quality", 10.0, 100.0, 75.0);
+    let fonts_check = CheckButton::with_label("Subset embedded fonts and remove unused ones");
+    box_container.pack_start(&fonts_check, false, false, 0);
+    let metadata_row = gtk::Box::new(Orientation::Horizontal, 10);
+    metadata_row.pack_start(&Label::new(Some("Metadata")), false, false, 0);
+    let metadata_combo = ComboBoxText::new();
+    metadata_combo.append(Some("keep"), "Keep");
+    metadata_combo.append(Some("info"), "Remove document info (title, author)");
+    metadata_combo.append(Some("all"), "Remove all (info, XMP, thumbnails)");
+    metadata_row.pack_end(&metadata_combo, false, false, 0);
+    box_container.pack_start(&metadata_row, false, false, 0);
+    let deflate_check = CheckButton::with_label("Maximum lossless stream compression (slow)");
+    box_container.pack_start(&deflate_check, false, false, 0);
+    let objstm_check = CheckButton::with_label("Pack objects into object streams (PDF 1.5+)");
+    box_container.pack_start(&objstm_check, false, false, 0);
+
+    let images_check_clone = images_check.clone();
+    let dpi_spin_clone = dpi_spin.clone();
+    let quality_spin_clone = quality_spin.clone();
+    let fonts_check_clone = fonts_check.clone();
+    let metadata_combo_clone = metadata_combo.clone();
+    let deflate_check_clone = deflate_check.clone();
+    let objstm_check_clone = objstm_check.clone();
+    preset_combo.connect_changed(move |combo| {
+        let preset = match combo.active_id().and_then(|id| pdf::CompressPreset::from_name(&id)) { Some(p) => p, None => return };
+        let options = preset.options();
+        images_check_clone.set_active(options.optimize_images);
+        dpi_spin_clone.set_value(options.image_dpi as f64);
+        quality_spin_clone.set_value(options.jpeg_quality as f64);
+        fonts_check_clone.set_active(options.subset_fonts);
+        metadata_combo_clone.set_active_id(Some(options.metadata.name()));
+        deflate_check_clone.set_active(options.max_deflate);
+        objstm_check_clone.set_active(options.object_streams);
+    });
+    preset_combo.set_active_id(Some("ebook"));
+
     let action_btn = Button::with_label("Compress");
     action_btn.set_sensitive(false);
     box_container.pack_start(&action_btn, false, false, 0);
@@ -157,9 +267,20 @@ pub fn create_compress_tab(notebook: &Notebook, window: &Window) {
         let window = match window_weak.upgrade() { Some(w) => w, None => return };
         let file = file_clone.borrow();
         if let Some(input) = &*file {
-            if let Some(output) = save_dialog(&window, "compressed.pdf") {
-                match pdf::compress_pdf(input, output) {
-                    Ok(_) => show_message(&window, gtk::MessageType::Info, "Success", "Compressed successfully!"),
+            let dpi = dpi_spin.value_as_int() as u32;
+            let options = pdf::CompressOptions {
+                optimize_images: images_check.is_active(),
+                image_dpi: dpi,
+                image_dpi_threshold: dpi * 3 / 2,
+                jpeg_quality: quality_spin.value_as_int() as u8,
+                subset_fonts: fonts_check.is_active(),
+                metadata: metadata_combo.active_id().and_then(|id| pdf::MetadataMode::from_name(&id)).unwrap_or(pdf::MetadataMode::StripInfo),
+                max_deflate: deflate_check.is_active(),
+                object_streams: objstm_check.is_active(),
+            };
+            if let Some((output, output_options)) = save_dialog(&window, "compressed.pdf") {
+                match pdf::compress_pdf(input, output, &options, &output_options) {
+                    Ok(report) => show_message(&window, gtk::MessageType::Info, "Success", &report.summary()),
                     Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
                 }
             }
@@ -179,13 +300,49 @@ pub fn create_rotate_tab(notebook: &Notebook, window: &Window) {
     let select_btn = Button::with_label("Select PDF");
     box_container.pack_start(&select_btn, false, false, 0);
 
-    let action_btn = Button::with_label("Rotate 90°");
-    action_btn.set_sensitive(false);
-    box_container.pack_start(&action_btn, false, false, 0);
+    let pages_label = Label::new(Some("Pages to rotate (e.g. 1, 3-5; leave empty for all pages)"));
+    box_container.pack_start(&pages_label, false, false, 0);
+    let pages_entry = Entry::new();
+    box_container.pack_start(&pages_entry, false, false, 0);
+
+    let button_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let buttons = [
+        (Button::with_label("Rotate Clockwise (90°)"), Some(90)),
+        (Button::with_label("Rotate Counter-clockwise (90°)"), Some(-90)),
+        (Button::with_label("Rotate 180°"), Some(180)),
+        (Button::with_label("Auto-Orient Pages"), None),
+        (Button::with_label("Bake Current Rotation"), Some(0)),
+    ];
+    for (btn, _) in &buttons[..3] {
+        btn.set_sensitive(false);
+        button_row.pack_start(btn, true, true, 0);
+    }
+    box_container.pack_start(&button_row, false, false, 0);
+
+    // Auto-orient Section
+    let orient_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let orientation_combo = ComboBoxText::new();
+    orientation_combo.append(Some("portrait"), "Portrait");
+    orientation_combo.append(Some("landscape"), "Landscape");
+    orientation_combo.set_active_id(Some("portrait"));
+    orient_row.pack_start(&orientation_combo, false, false, 0);
+    let detect_check = CheckButton::with_label("Detect text direction on scanned pages");
+    orient_row.pack_start(&detect_check, false, false, 0);
+    buttons[3].0.set_sensitive(false);
+    orient_row.pack_end(&buttons[3].0, false, false, 0);
+    box_container.pack_start(&orient_row, false, false, 0);
+
+    // Bake Section
+    let bake_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let bake_check = CheckButton::with_label("Bake rotation into page content (for tools that ignore page rotation)");
+    bake_row.pack_start(&bake_check, false, false, 0);
+    buttons[4].0.set_sensitive(false);
+    bake_row.pack_end(&buttons[4].0, false, false, 0);
+    box_container.pack_start(&bake_row, false, false, 0);
 
     let selected_file: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
     let label_clone = label.clone();
-    let action_btn_clone = action_btn.clone();
+    let buttons_clone: Vec<Button> = buttons.iter().map(|(btn, _)| btn.clone()).collect();
     let file_clone = Rc::clone(&selected_file);
     let window_weak = window.downgrade();
 
@@ -197,26 +354,1001 @@ pub fn create_rotate_tab(notebook: &Notebook, window: &Window) {
             *file = dialog.filename();
             if let Some(f) = &*file {
                 label_clone.set_text(&format!("Selected: {}", f.file_name().unwrap().to_string_lossy()));
-                action_btn_clone.set_sensitive(true);
+                for btn in &buttons_clone { btn.set_sensitive(true); }
             }
         }
         dialog.close();
     });
 
+    for (btn, degrees) in buttons {
+        let file_clone = Rc::clone(&selected_file);
+        let pages_entry_clone = pages_entry.clone();
+        let orientation_combo_clone = orientation_combo.clone();
+        let detect_check_clone = detect_check.clone();
+        let bake_check_clone = bake_check.clone();
+        let window_weak = window.downgrade();
+        btn.connect_clicked(move |_| {
+            let window = match window_weak.upgrade() { Some(w) => w, None => return };
+            let file = file_clone.borrow();
+            if let Some(input) = &*file {
+                let pages = match pdf::parse_page_ranges(&pages_entry_clone.text()) {
+                    Ok(p) => p,
+                    Err(e) => { show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)); return; }
+                };
+                let rotation = match degrees {
+                    Some(degrees) => pdf::Rotation::By(degrees),
+                    None => pdf::Rotation::AutoOrient {
+                        target: if orientation_combo_clone.active_id().as_deref() == Some("landscape") { pdf::PageOrientation::Landscape } else { pdf::PageOrientation::Portrait },
+                        detect_text: detect_check_clone.is_active(),
+                    },
+                };
+                let bake = bake_check_clone.is_active() || degrees == Some(0);
+                if let Some((output, output_options)) = save_dialog(&window, "rotated.pdf") {
+                    match pdf::rotate_pdf(input, output, rotation, &pages, bake, &output_options) {
+                        Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("Rotated {} pages.", c)),
+                        Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
+                    }
+                }
+            }
+        });
+    }
+}
+
+pub fn create_crop_tab(notebook: &Notebook, window: &Window) {
+    let box_container = gtk::Box::new(Orientation::Vertical, 10);
+    box_container.set_border_width(10);
+    let tab_label = Label::new(Some("Crop"));
+    notebook.append_page(&box_container, Some(&tab_label));
+
+    let label = Label::new(Some("Select a PDF to crop"));
+    box_container.pack_start(&label, false, false, 0);
+
+    let select_btn = Button::with_label("Select PDF");
+    box_container.pack_start(&select_btn, false, false, 0);
+
+    let pages_label = Label::new(Some("Pages to crop (e.g. 1, 3-5; leave empty for all pages)"));
+    box_container.pack_start(&pages_label, false, false, 0);
+    let pages_entry = Entry::new();
+    box_container.pack_start(&pages_entry, false, false, 0);
+
+    // Preview Section
+    let preview_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let area = DrawingArea::new();
+    area.set_size_request(300, 360);
+    area.add_events(EventMask::BUTTON_PRESS_MASK | EventMask::BUTTON_RELEASE_MASK | EventMask::BUTTON_MOTION_MASK);
+    preview_row.pack_start(&area, true, true, 0);
+    let side = gtk::Box::new(Orientation::Vertical, 5);
+    let preview_spin = labeled_spin(&side, "Preview page:", 1.0, 1.0, 1.0);
+    side.pack_start(&Label::new(Some("Margins in points, or drag a box on the preview")), false, false, 0);
+    let margin_spins = [
+        labeled_spin(&side, "Left:", 0.0, 5000.0, 0.0),
+        labeled_spin(&side, "Bottom:", 0.0, 5000.0, 0.0),
+        labeled_spin(&side, "Right:", 0.0, 5000.0, 0.0),
+        labeled_spin(&side, "Top:", 0.0, 5000.0, 0.0),
+    ];
+    let crop_btn = Button::with_label("Crop Pages");
+    crop_btn.set_sensitive(false);
+    side.pack_start(&crop_btn, false, false, 0);
+    preview_row.pack_start(&side, false, false, 0);
+    box_container.pack_start(&preview_row, true, true, 0);
+
+    // Auto-trim Section
+    let auto_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let pixels_check = CheckButton::with_label("Also trim white borders inside images (scans)");
+    auto_row.pack_start(&pixels_check, false, false, 0);
+    let auto_btn = Button::with_label("Auto-Trim Pages");
+    auto_btn.set_sensitive(false);
+    auto_row.pack_end(&auto_btn, false, false, 0);
+    box_container.pack_start(&auto_row, false, false, 0);
+    let padding_spin = labeled_spin(&box_container, "Space to keep around the content when auto-trimming (points):", 0.0, 500.0, 0.0);
+
+    let media_check = CheckButton::with_label("Also set MediaBox (discard the trimmed area instead of hiding it)");
+    box_container.pack_start(&media_check, false, false, 0);
+
+    let selected_file: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+    let preview: Rc<RefCell<Option<CropPreview>>> = Rc::new(RefCell::new(None));
+    // Where a drag on the preview started, in points from the page's top left corner.
+    let drag_start: Rc<Cell<Option<(f64, f64)>>> = Rc::new(Cell::new(None));
+
+    let load_preview = {
+        let file_clone = Rc::clone(&selected_file);
+        let preview_clone = Rc::clone(&preview);
+        let area_clone = area.clone();
+        let preview_spin_clone = preview_spin.clone();
+        move |window: &Window| {
+            let Some(input) = file_clone.borrow().clone() else { return };
+            match pdf::page_preview(&input, preview_spin_clone.value() as u32) {
+                Ok(page) => {
+                    preview_spin_clone.set_range(1.0, page.page_count.max(1) as f64);
+                    let surfaces = page.images.iter().filter_map(|(&id, img)| Some((id, image_surface(img)?))).collect();
+                    *preview_clone.borrow_mut() = Some(CropPreview { page, surfaces });
+                }
+                Err(e) => {
+                    *preview_clone.borrow_mut() = None;
+                    show_message(window, gtk::MessageType::Error, "Error", &format!("Error: {}", e));
+                }
+            }
+            area_clone.queue_draw();
+        }
+    };
+
+    let label_clone = label.clone();
+    let buttons = [crop_btn.clone(), auto_btn.clone()];
     let file_clone = Rc::clone(&selected_file);
+    let preview_spin_clone = preview_spin.clone();
+    let load = load_preview.clone();
     let window_weak = window.downgrade();
-    action_btn.connect_clicked(move |_| {
+    select_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let dialog = create_file_chooser(&window, FileChooserAction::Open, "Select PDF File", false);
+        if dialog.run() == ResponseType::Accept {
+            *file_clone.borrow_mut() = dialog.filename();
+            if let Some(f) = &*file_clone.borrow() {
+                label_clone.set_text(&format!("Selected: {}", f.file_name().unwrap().to_string_lossy()));
+                for btn in &buttons { btn.set_sensitive(true); }
+            }
+            // Moving the spin back to the first page reloads the preview by itself.
+            if preview_spin_clone.value() == 1.0 { load(&window); } else { preview_spin_clone.set_value(1.0); }
+        }
+        dialog.close();
+    });
+
+    let window_weak = window.downgrade();
+    preview_spin.connect_value_changed(move |_| {
+        if let Some(window) = window_weak.upgrade() { load_preview(&window); }
+    });
+
+    for spin in &margin_spins {
+        let area_clone = area.clone();
+        spin.connect_value_changed(move |_| area_clone.queue_draw());
+    }
+
+    let preview_clone = Rc::clone(&preview);
+    let margin_spins_clone = margin_spins.clone();
+    area.connect_draw(move |area, cr| {
+        cr.set_source_rgb(0.6, 0.6, 0.6);
+        cr.paint().ok();
+        let preview = preview_clone.borrow();
+        let Some(preview) = &*preview else { return gtk::glib::Propagation::Proceed };
+        let (scale, x, y) = preview_view(area, &preview.page);
+        let (width, height) = preview.page.size();
+        let (width, height) = (width as f64, height as f64);
+        cr.translate(x, y);
+        cr.scale(scale, scale);
+        cr.set_source_rgb(1.0, 1.0, 1.0);
+        cr.rectangle(0.0, 0.0, width, height);
+        cr.fill().ok();
+        draw_page(cr, preview, scale);
+
+        // Everything outside the crop box is shaded.
+        let [left, bottom, right, top] = margin_spins_clone.clone().map(|s| s.value());
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.35);
+        cr.set_fill_rule(gtk::cairo::FillRule::EvenOdd);
+        cr.rectangle(0.0, 0.0, width, height);
+        cr.rectangle(left, top, (width - left - right).max(0.0), (height - top - bottom).max(0.0));
+        cr.fill().ok();
+        cr.set_source_rgb(0.85, 0.1, 0.1);
+        cr.set_line_width(1.5 / scale);
+        cr.rectangle(left, top, (width - left - right).max(0.0), (height - top - bottom).max(0.0));
+        cr.stroke().ok();
+        gtk::glib::Propagation::Proceed
+    });
+
+    let preview_clone = Rc::clone(&preview);
+    let drag_clone = Rc::clone(&drag_start);
+    area.connect_button_press_event(move |area, event| {
+        if let Some(preview) = &*preview_clone.borrow() { drag_clone.set(Some(page_point(area, &preview.page, event.position()))); }
+        gtk::glib::Propagation::Stop
+    });
+
+    let preview_clone = Rc::clone(&preview);
+    let drag_clone = Rc::clone(&drag_start);
+    let margin_spins_clone = margin_spins.clone();
+    area.connect_motion_notify_event(move |area, event| {
+        let (Some(preview), Some((u0, v0))) = (&*preview_clone.borrow(), drag_clone.get()) else { return gtk::glib::Propagation::Proceed };
+        let (u1, v1) = page_point(area, &preview.page, event.position());
+        let (width, height) = preview.page.size();
+        let margins = [u0.min(u1), height as f64 - v0.max(v1), width as f64 - u0.max(u1), v0.min(v1)];
+        for (spin, margin) in margin_spins_clone.iter().zip(margins) { spin.set_value(margin.round()); }
+        gtk::glib::Propagation::Stop
+    });
+
+    let drag_clone = Rc::clone(&drag_start);
+    area.connect_button_release_event(move |_, _| {
+        drag_clone.set(None);
+        gtk::glib::Propagation::Stop
+    });
+
+    for (btn, auto) in [(crop_btn, false), (auto_btn, true)] {
+        let file_clone = Rc::clone(&selected_file);
+        let pages_entry_clone = pages_entry.clone();
+        let margin_spins_clone = margin_spins.clone();
+        let pixels_check_clone = pixels_check.clone();
+        let padding_spin_clone = padding_spin.clone();
+        let media_check_clone = media_check.clone();
+        let window_weak = window.downgrade();
+        btn.connect_clicked(move |_| {
+            let window = match window_weak.upgrade() { Some(w) => w, None => return };
+            let file = file_clone.borrow();
+            if let Some(input) = &*file {
+                let pages = match pdf::parse_page_ranges(&pages_entry_clone.text()) {
+                    Ok(p) => p,
+                    Err(e) => { show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)); return; }
+                };
+                let crop = if auto {
+                    pdf::Crop::Auto { padding: padding_spin_clone.value() as f32, pixels: pixels_check_clone.is_active() }
+                } else {
+                    pdf::Crop::Margins(margin_spins_clone.clone().map(|s| s.value() as f32))
+                };
+                if let Some((output, output_options)) = save_dialog(&window, "cropped.pdf") {
+                    match pdf::crop_pdf(input, output, crop, &pages, media_check_clone.is_active(), &output_options) {
+                        Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("Cropped {} pages.", c)),
+                        Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
+                    }
+                }
+            }
+        });
+    }
+}
+
+pub fn create_layout_tab(notebook: &Notebook, window: &Window) {
+    let box_container = gtk::Box::new(Orientation::Vertical, 10);
+    box_container.set_border_width(10);
+    let tab_label = Label::new(Some("Layout"));
+    // The layout tools need more room than the window has.
+    let scrolled = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scrolled.add(&box_container);
+    notebook.append_page(&scrolled, Some(&tab_label));
+
+    let label = Label::new(Some("Select a PDF to lay out"));
+    box_container.pack_start(&label, false, false, 0);
+
+    let select_btn = Button::with_label("Select PDF");
+    box_container.pack_start(&select_btn, false, false, 0);
+
+    let pages_label = Label::new(Some("Pages to use (e.g. 1, 3-5; leave empty for all pages)"));
+    box_container.pack_start(&pages_label, false, false, 0);
+    let pages_entry = Entry::new();
+    box_container.pack_start(&pages_entry, false, false, 0);
+
+    // Fit to Paper Section
+    box_container.pack_start(&Label::new(Some("Fit to Paper")), false, false, 0);
+    let paper_row = gtk::Box::new(Orientation::Horizontal, 10);
+    paper_row.pack_start(&Label::new(Some("Paper")), false, false, 0);
+    let paper_combo = ComboBoxText::new();
+    for (id, text) in [("a3", "A3"), ("a4", "A4"), ("a5", "A5"), ("letter", "Letter"), ("legal", "Legal"), ("custom", "Custom")] {
+        paper_combo.append(Some(id), text);
+    }
+    paper_combo.set_active_id(Some("a4"));
+    paper_row.pack_start(&paper_combo, false, false, 0);
+    let fit_combo = ComboBoxText::new();
+    fit_combo.append(Some("fit"), "Fit (scale to fit whole)");
+    fit_combo.append(Some("fill"), "Fill (scale to cover, cutting off overflow)");
+    fit_combo.append(Some("center"), "Center (keep size)");
+    fit_combo.set_active_id(Some("fit"));
+    paper_row.pack_end(&fit_combo, false, false, 0);
+    box_container.pack_start(&paper_row, false, false, 0);
+    let custom_width_spin = labeled_spin(&box_container, "Custom width (mm):", 10.0, 5000.0, 210.0);
+    let custom_height_spin = labeled_spin(&box_container, "Custom height (mm):", 10.0, 5000.0, 297.0);
+    let fit_btn = Button::with_label("Resize Pages");
+    fit_btn.set_sensitive(false);
+    box_container.pack_start(&fit_btn, false, false, 0);
+
+    // N-up Section
+    box_container.pack_start(&Label::new(Some("N-up")), false, false, 0);
+    let grid_row = gtk::Box::new(Orientation::Horizontal, 10);
+    grid_row.pack_start(&Label::new(Some("Pages per sheet")), false, false, 0);
+    let per_sheet_combo = ComboBoxText::new();
+    for n in ["2", "4", "6", "8", "9", "16"] { per_sheet_combo.append(Some(n), n); }
+    grid_row.pack_start(&per_sheet_combo, false, false, 0);
+    let sheet_combo = ComboBoxText::new();
+    sheet_combo.append(Some("same"), "Sheet: same as pages");
+    for (id, text) in [("a3", "Sheet: A3"), ("a4", "Sheet: A4"), ("a5", "Sheet: A5"), ("letter", "Sheet: Letter"), ("legal", "Sheet: Legal")] {
+        sheet_combo.append(Some(id), text);
+    }
+    sheet_combo.set_active_id(Some("same"));
+    grid_row.pack_end(&sheet_combo, false, false, 0);
+    box_container.pack_start(&grid_row, false, false, 0);
+    let columns_spin = labeled_spin(&box_container, "Columns:", 1.0, 10.0, 2.0);
+    let rows_spin = labeled_spin(&box_container, "Rows:", 1.0, 10.0, 2.0);
+    let order_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let order_combo = ComboBoxText::new();
+    order_combo.append(Some("rows"), "Across, then down");
+    order_combo.append(Some("columns"), "Down, then across");
+    order_combo.set_active_id(Some("rows"));
+    order_row.pack_start(&order_combo, false, false, 0);
+    let borders_check = CheckButton::with_label("Draw borders");
+    order_row.pack_start(&borders_check, false, false, 0);
+    box_container.pack_start(&order_row, false, false, 0);
+    let margin_spin = labeled_spin(&box_container, "Sheet margin (points):", 0.0, 500.0, 18.0);
+    let gutter_spin = labeled_spin(&box_container, "Gutter between pages (points):", 0.0, 500.0, 9.0);
+    let n_up_btn = Button::with_label("Make N-up Sheets");
+    n_up_btn.set_sensitive(false);
+    box_container.pack_start(&n_up_btn, false, false, 0);
+
+    // Booklet Section
+    box_container.pack_start(&Label::new(Some("Booklet")), false, false, 0);
+    let booklet_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let booklet_sheet_combo = ComboBoxText::new();
+    booklet_sheet_combo.append(Some("same"), "Sheet: two pages side by side");
+    for (id, text) in [("a3", "Sheet: A3"), ("a4", "Sheet: A4"), ("letter", "Sheet: Letter"), ("legal", "Sheet: Legal")] {
+        booklet_sheet_combo.append(Some(id), text);
+    }
+    booklet_sheet_combo.set_active_id(Some("same"));
+    booklet_row.pack_start(&booklet_sheet_combo, false, false, 0);
+    box_container.pack_start(&booklet_row, false, false, 0);
+    let signature_spin = labeled_spin(&box_container, "Pages per signature (multiple of 4, 0 for one signature):", 0.0, 256.0, 0.0);
+    signature_spin.set_increments(4.0, 16.0);
+    let creep_spin = labeled_spin(&box_container, "Creep at the innermost sheet (points):", 0.0, 50.0, 0.0);
+    creep_spin.set_digits(1);
+    creep_spin.set_increments(0.1, 1.0);
+    let booklet_btn = Button::with_label("Make Booklet");
+    booklet_btn.set_sensitive(false);
+    box_container.pack_start(&booklet_btn, false, false, 0);
+
+    // Poster Section
+    box_container.pack_start(&Label::new(Some("Poster")), false, false, 0);
+    let poster_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let poster_sheet_combo = ComboBoxText::new();
+    for (id, text) in [("a3", "Sheet: A3"), ("a4", "Sheet: A4"), ("a5", "Sheet: A5"), ("letter", "Sheet: Letter"), ("legal", "Sheet: Legal")] {
+        poster_sheet_combo.append(Some(id), text);
+    }
+    poster_sheet_combo.set_active_id(Some("a4"));
+    poster_row.pack_start(&poster_sheet_combo, false, false, 0);
+    let cut_marks_check = CheckButton::with_label("Cut marks");
+    cut_marks_check.set_active(true);
+    poster_row.pack_start(&cut_marks_check, false, false, 0);
+    let labels_check = CheckButton::with_label("Tile labels");
+    labels_check.set_active(true);
+    poster_row.pack_start(&labels_check, false, false, 0);
+    box_container.pack_start(&poster_row, false, false, 0);
+    let scale_spin = labeled_spin(&box_container, "Scale (1 for actual size):", 0.1, 20.0, 2.0);
+    scale_spin.set_digits(1);
+    scale_spin.set_increments(0.1, 1.0);
+    let overlap_spin = labeled_spin(&box_container, "Overlap between tiles (points):", 0.0, 200.0, 18.0);
+    let poster_margin_spin = labeled_spin(&box_container, "Sheet margin (points):", 0.0, 200.0, 18.0);
+    let poster_btn = Button::with_label("Make Poster");
+    poster_btn.set_sensitive(false);
+    box_container.pack_start(&poster_btn, false, false, 0);
+
+    let columns_spin_clone = columns_spin.clone();
+    let rows_spin_clone = rows_spin.clone();
+    per_sheet_combo.connect_changed(move |combo| {
+        let Some((columns, rows)) = combo.active_id().and_then(|id| id.parse().ok()).and_then(pdf::NUpOptions::grid) else { return };
+        columns_spin_clone.set_value(columns as f64);
+        rows_spin_clone.set_value(rows as f64);
+    });
+    per_sheet_combo.set_active_id(Some("4"));
+
+    let selected_file: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+    let label_clone = label.clone();
+    let buttons = [fit_btn.clone(), n_up_btn.clone(), booklet_btn.clone(), poster_btn.clone()];
+    let file_clone = Rc::clone(&selected_file);
+    let window_weak = window.downgrade();
+    select_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let dialog = create_file_chooser(&window, FileChooserAction::Open, "Select PDF File", false);
+        if dialog.run() == ResponseType::Accept {
+            let mut file = file_clone.borrow_mut();
+            *file = dialog.filename();
+            if let Some(f) = &*file {
+                label_clone.set_text(&format!("Selected: {}", f.file_name().unwrap().to_string_lossy()));
+                for btn in &buttons { btn.set_sensitive(true); }
+            }
+        }
+        dialog.close();
+    });
+
+    let file_clone = Rc::clone(&selected_file);
+    let pages_entry_clone = pages_entry.clone();
+    let window_weak = window.downgrade();
+    fit_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let file = file_clone.borrow();
+        if let Some(input) = &*file {
+            let pages = match pdf::parse_page_ranges(&pages_entry_clone.text()) {
+                Ok(p) => p,
+                Err(e) => { show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)); return; }
+            };
+            let paper = match paper_combo.active_id().and_then(|id| pdf::PaperSize::from_name(&id)) {
+                Some(paper) => paper,
+                None => pdf::PaperSize::Custom(custom_width_spin.value() as f32 * 72.0 / 25.4, custom_height_spin.value() as f32 * 72.0 / 25.4),
+            };
+            let mode = fit_combo.active_id().and_then(|id| pdf::FitMode::from_name(&id)).unwrap_or(pdf::FitMode::Fit);
+            if let Some((output, output_options)) = save_dialog(&window, "resized.pdf") {
+                match pdf::fit_to_paper(input, output, paper, mode, &pages, &output_options) {
+                    Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("Resized {} pages.", c)),
+                    Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
+                }
+            }
+        }
+    });
+
+    let file_clone = Rc::clone(&selected_file);
+    let pages_entry_clone = pages_entry.clone();
+    let window_weak = window.downgrade();
+    n_up_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let file = file_clone.borrow();
+        if let Some(input) = &*file {
+            let pages = match pdf::parse_page_ranges(&pages_entry_clone.text()) {
+                Ok(p) => p,
+                Err(e) => { show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)); return; }
+            };
+            let options = pdf::NUpOptions {
+                columns: columns_spin.value() as u32,
+                rows: rows_spin.value() as u32,
+                column_major: order_combo.active_id().as_deref() == Some("columns"),
+                margin: margin_spin.value() as f32,
+                gutter: gutter_spin.value() as f32,
+                borders: borders_check.is_active(),
+                paper: sheet_combo.active_id().and_then(|id| pdf::PaperSize::from_name(&id)),
+            };
+            if let Some((output, output_options)) = save_dialog(&window, "n-up.pdf") {
+                match pdf::n_up(input, output, &options, &pages, &output_options) {
+                    Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("Wrote {} sheets.", c)),
+                    Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
+                }
+            }
+        }
+    });
+
+    let file_clone = Rc::clone(&selected_file);
+    let pages_entry_clone = pages_entry.clone();
+    let window_weak = window.downgrade();
+    booklet_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let file = file_clone.borrow();
+        if let Some(input) = &*file {
+            let pages = match pdf::parse_page_ranges(&pages_entry_clone.text()) {
+                Ok(p) => p,
+                Err(e) => { show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)); return; }
+            };
+            let options = pdf::BookletOptions {
+                signature: signature_spin.value() as u32,
+                creep: creep_spin.value() as f32,
+                paper: booklet_sheet_combo.active_id().and_then(|id| pdf::PaperSize::from_name(&id)),
+            };
+            if let Some((output, output_options)) = save_dialog(&window, "booklet.pdf") {
+                match pdf::booklet(input, output, &options, &pages, &output_options) {
+                    Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("Wrote {} sheets. Print double-sided, flipping on the short edge.", c)),
+                    Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
+                }
+            }
+        }
+    });
+
+    let file_clone = Rc::clone(&selected_file);
+    let pages_entry_clone = pages_entry.clone();
+    let window_weak = window.downgrade();
+    poster_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let file = file_clone.borrow();
+        if let Some(input) = &*file {
+            let pages = match pdf::parse_page_ranges(&pages_entry_clone.text()) {
+                Ok(p) => p,
+                Err(e) => { show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)); return; }
+            };
+            let options = pdf::PosterOptions {
+                paper: poster_sheet_combo.active_id().and_then(|id| pdf::PaperSize::from_name(&id)).unwrap_or(pdf::PaperSize::A4),
+                scale: scale_spin.value() as f32,
+                overlap: overlap_spin.value() as f32,
+                margin: poster_margin_spin.value() as f32,
+                cut_marks: cut_marks_check.is_active(),
+                labels: labels_check.is_active(),
+            };
+            if let Some((output, output_options)) = save_dialog(&window, "poster.pdf") {
+                match pdf::poster(input, output, &options, &pages, &output_options) {
+                    Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("Wrote {} tiles.", c)),
+                    Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
+                }
+            }
+        }
+    });
+}
+
+pub fn create_stamp_tab(notebook: &Notebook, window: &Window) {
+    let box_container = gtk::Box::new(Orientation::Vertical, 10);
+    box_container.set_border_width(10);
+    let tab_label = Label::new(Some("Stamp"));
+    let scrolled = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scrolled.add(&box_container);
+    notebook.append_page(&scrolled, Some(&tab_label));
+
+    let label = Label::new(Some("Select a PDF to stamp"));
+    box_container.pack_start(&label, false, false, 0);
+
+    let select_btn = Button::with_label("Select PDF");
+    box_container.pack_start(&select_btn, false, false, 0);
+
+    let pages_label = Label::new(Some("Pages to stamp (e.g. 1, 3-5; leave empty for all pages)"));
+    box_container.pack_start(&pages_label, false, false, 0);
+    let pages_entry = Entry::new();
+    box_container.pack_start(&pages_entry, false, false, 0);
+
+    // Watermark Section
+    box_container.pack_start(&Label::new(Some("Text Watermark")), false, false, 0);
+    let watermark_entry = Entry::new();
+    watermark_entry.set_text("CONFIDENTIAL");
+    box_container.pack_start(&watermark_entry, false, false, 0);
+    let style_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let font_combo = standard_font_combo();
+    style_row.pack_start(&font_combo, false, false, 0);
+    let color_btn = gtk::ColorButton::with_rgba(&gtk::gdk::RGBA::new(0.5, 0.5, 0.5, 1.0));
+    style_row.pack_start(&color_btn, false, false, 0);
+    let position_combo = stamp_position_combo(Position::Center);
+    style_row.pack_start(&position_combo, false, false, 0);
+    let under_check = CheckButton::with_label("Behind content");
+    style_row.pack_start(&under_check, false, false, 0);
+    box_container.pack_start(&style_row, false, false, 0);
+    let size_spin = labeled_spin(&box_container, "Font size (points):", 4.0, 500.0, 72.0);
+    let opacity_spin = labeled_spin(&box_container, "Opacity (%):", 0.0, 100.0, 30.0);
+    let angle_spin = labeled_spin(&box_container, "Angle (degrees counter-clockwise):", -180.0, 180.0, 45.0);
+    let margin_spin = labeled_spin(&box_container, "Distance from the edges (points):", 0.0, 500.0, 36.0);
+    let watermark_btn = Button::with_label("Add Watermark");
+    watermark_btn.set_sensitive(false);
+    box_container.pack_start(&watermark_btn, false, false, 0);
+
+    // Image Stamp Section
+    box_container.pack_start(&Label::new(Some("Image Stamp")), false, false, 0);
+    let image_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let image_btn = Button::with_label("Choose Image (JPG/PNG)");
+    image_row.pack_start(&image_btn, false, false, 0);
+    let image_label = Label::new(Some("No image chosen"));
+    image_row.pack_start(&image_label, false, false, 0);
+    let image_position_combo = stamp_position_combo(Position::BottomRight);
+    image_row.pack_end(&image_position_combo, false, false, 0);
+    box_container.pack_start(&image_row, false, false, 0);
+    let image_width_spin = labeled_spin(&box_container, "Width (points):", 1.0, 5000.0, 144.0);
+    let offset_x_spin = labeled_spin(&box_container, "Move right (points):", -5000.0, 5000.0, -36.0);
+    let offset_y_spin = labeled_spin(&box_container, "Move up (points):", -5000.0, 5000.0, 36.0);
+    let image_opacity_spin = labeled_spin(&box_container, "Opacity (%):", 0.0, 100.0, 100.0);
+    let image_angle_spin = labeled_spin(&box_container, "Angle (degrees counter-clockwise):", -180.0, 180.0, 0.0);
+    let image_under_check = CheckButton::with_label("Behind content");
+    box_container.pack_start(&image_under_check, false, false, 0);
+    let image_stamp_btn = Button::with_label("Add Image Stamp");
+    image_stamp_btn.set_sensitive(false);
+    box_container.pack_start(&image_stamp_btn, false, false, 0);
+
+    // Page Numbers Section
+    box_container.pack_start(&Label::new(Some("Page Numbers")), false, false, 0);
+    let template_row = gtk::Box::new(Orientation::Horizontal, 10);
+    template_row.pack_start(&Label::new(Some("Text ({n} is the number, {total} the last one)")), false, false, 0);
+    let template_entry = Entry::new();
+    template_entry.set_text("Page {n} of {total}");
+    template_row.pack_end(&template_entry, false, false, 0);
+    box_container.pack_start(&template_row, false, false, 0);
+    let affix_row = gtk::Box::new(Orientation::Horizontal, 10);
+    affix_row.pack_start(&Label::new(Some("Prefix")), false, false, 0);
+    let prefix_entry = Entry::new();
+    prefix_entry.set_placeholder_text(Some("e.g. ACME-"));
+    affix_row.pack_start(&prefix_entry, false, false, 0);
+    affix_row.pack_start(&Label::new(Some("Suffix")), false, false, 0);
+    let suffix_entry = Entry::new();
+    affix_row.pack_start(&suffix_entry, false, false, 0);
+    box_container.pack_start(&affix_row, false, false, 0);
+    let number_style_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let number_font_combo = standard_font_combo();
+    number_style_row.pack_start(&number_font_combo, false, false, 0);
+    let number_color_btn = gtk::ColorButton::with_rgba(&gtk::gdk::RGBA::new(0.0, 0.0, 0.0, 1.0));
+    number_style_row.pack_start(&number_color_btn, false, false, 0);
+    let number_position_combo = stamp_position_combo(Position::Bottom);
+    number_style_row.pack_start(&number_position_combo, false, false, 0);
+    box_container.pack_start(&number_style_row, false, false, 0);
+    let start_spin = labeled_spin(&box_container, "Start at:", 0.0, 1e9, 1.0);
+    let digits_spin = labeled_spin(&box_container, "Pad with zeros to digits (6 for Bates numbers):", 0.0, 12.0, 0.0);
+    let number_size_spin = labeled_spin(&box_container, "Font size (points):", 4.0, 200.0, 10.0);
+    let number_margin_spin = labeled_spin(&box_container, "Distance from the edges (points):", 0.0, 500.0, 36.0);
+    let number_btn = Button::with_label("Number Pages");
+    number_btn.set_sensitive(false);
+    box_container.pack_start(&number_btn, false, false, 0);
+
+    // Headers and Footers Section
+    box_container.pack_start(&Label::new(Some("Headers and Footers")), false, false, 0);
+    box_container.pack_start(&Label::new(Some("Tokens: {filename}, {title}, {date}, {page}, {pages}")), false, false, 0);
+    let all_entries = header_footer_entries(&box_container);
+    all_entries[5].set_text("Page {page} of {pages}");
+    let first_check = CheckButton::with_label("Different first page");
+    box_container.pack_start(&first_check, false, false, 0);
+    let first_entries = header_footer_entries(&box_container);
+    let even_check = CheckButton::with_label("Different even pages (the texts above are for odd pages)");
+    box_container.pack_start(&even_check, false, false, 0);
+    let even_entries = header_footer_entries(&box_container);
+    let header_style_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let header_font_combo = standard_font_combo();
+    header_style_row.pack_start(&header_font_combo, false, false, 0);
+    let header_color_btn = gtk::ColorButton::with_rgba(&gtk::gdk::RGBA::new(0.0, 0.0, 0.0, 1.0));
+    header_style_row.pack_start(&header_color_btn, false, false, 0);
+    box_container.pack_start(&header_style_row, false, false, 0);
+    let header_size_spin = labeled_spin(&box_container, "Font size (points):", 4.0, 200.0, 9.0);
+    let header_margin_spin = labeled_spin(&box_container, "Distance from the edges (points):", 0.0, 500.0, 36.0);
+    let headers_btn = Button::with_label("Add Headers and Footers");
+    headers_btn.set_sensitive(false);
+    box_container.pack_start(&headers_btn, false, false, 0);
+
+    // Overlay Section
+    box_container.pack_start(&Label::new(Some("Overlay or Underlay a PDF (e.g. letterhead)")), false, false, 0);
+    let overlay_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let overlay_btn = Button::with_label("Choose Stamp PDF");
+    overlay_row.pack_start(&overlay_btn, false, false, 0);
+    let overlay_label = Label::new(Some("No PDF chosen"));
+    overlay_row.pack_start(&overlay_label, false, false, 0);
+    box_container.pack_start(&overlay_row, false, false, 0);
+    let overlay_options_row = gtk::Box::new(Orientation::Horizontal, 10);
+    let overlay_mode_combo = ComboBoxText::new();
+    overlay_mode_combo.append(Some("repeat"), "Its first page on every page");
+    overlay_mode_combo.append(Some("match"), "Page by page");
+    overlay_mode_combo.append(Some("first-other"), "Page 1 on the first page, page 2 on the others");
+    overlay_mode_combo.set_active_id(Some("repeat"));
+    overlay_options_row.pack_start(&overlay_mode_combo, false, false, 0);
+    let overlay_under_check = CheckButton::with_label("Behind content");
+    overlay_under_check.set_active(true);
+    overlay_options_row.pack_start(&overlay_under_check, false, false, 0);
+    box_container.pack_start(&overlay_options_row, false, false, 0);
+    let apply_overlay_btn = Button::with_label("Apply Overlay");
+    apply_overlay_btn.set_sensitive(false);
+    box_container.pack_start(&apply_overlay_btn, false, false, 0);
+
+    let selected_overlay: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+    let overlay_clone = Rc::clone(&selected_overlay);
+    let window_weak = window.downgrade();
+    overlay_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let dialog = create_file_chooser(&window, FileChooserAction::Open, "Select Stamp PDF", false);
+        if dialog.run() == ResponseType::Accept {
+            let mut overlay = overlay_clone.borrow_mut();
+            *overlay = dialog.filename();
+            if let Some(f) = &*overlay { overlay_label.set_text(&f.file_name().unwrap().to_string_lossy()); }
+        }
+        dialog.close();
+    });
+
+    let selected_image: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+    let image_clone = Rc::clone(&selected_image);
+    let window_weak = window.downgrade();
+    image_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let dialog = FileChooserDialog::with_buttons(
+            Some("Select Image"), Some(&window), FileChooserAction::Open,
+            &[("_Cancel", ResponseType::Cancel), ("_Open", ResponseType::Accept)]
+        );
+        let filter = FileFilter::new();
+        filter.add_pattern("*.jpg");
+        filter.add_pattern("*.jpeg");
+        filter.add_pattern("*.png");
+        filter.set_name(Some("Images"));
+        dialog.add_filter(filter);
+        if dialog.run() == ResponseType::Accept {
+            let mut image = image_clone.borrow_mut();
+            *image = dialog.filename();
+            if let Some(f) = &*image { image_label.set_text(&f.file_name().unwrap().to_string_lossy()); }
+        }
+        dialog.close();
+    });
+
+    let selected_file: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+    let label_clone = label.clone();
+    let buttons = [watermark_btn.clone(), image_stamp_btn.clone(), number_btn.clone(), headers_btn.clone(), apply_overlay_btn.clone()];
+    let file_clone = Rc::clone(&selected_file);
+    let window_weak = window.downgrade();
+    select_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let dialog = create_file_chooser(&window, FileChooserAction::Open, "Select PDF File", false);
+        if dialog.run() == ResponseType::Accept {
+            let mut file = file_clone.borrow_mut();
+            *file = dialog.filename();
+            if let Some(f) = &*file {
+                label_clone.set_text(&format!("Selected: {}", f.file_name().unwrap().to_string_lossy()));
+                for btn in &buttons { btn.set_sensitive(true); }
+            }
+        }
+        dialog.close();
+    });
+
+    let file_clone = Rc::clone(&selected_file);
+    let pages_entry_clone = pages_entry.clone();
+    let window_weak = window.downgrade();
+    watermark_btn.connect_clicked(move |_| {
         let window = match window_weak.upgrade() { Some(w) => w, None => return };
         let file = file_clone.borrow();
         if let Some(input) = &*file {
-            if let Some(output) = save_dialog(&window, "rotated.pdf") {
-                match pdf::rotate_pdf(input, output, 90) {
-                    Ok(_) => show_message(&window, gtk::MessageType::Info, "Success", "Rotated successfully!"),
+            let pages = match pdf::parse_page_ranges(&pages_entry_clone.text()) {
+                Ok(p) => p,
+                Err(e) => { show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)); return; }
+            };
+            let watermark = stamp::TextStamp {
+                text: watermark_entry.text().to_string(),
+                font: selected_font(&font_combo),
+                size: size_spin.value() as f32,
+                color: selected_color(&color_btn),
+                opacity: opacity_spin.value() as f32 / 100.0,
+                rotation: angle_spin.value() as f32,
+                position: selected_position(&position_combo),
+                margin: margin_spin.value() as f32,
+                under: under_check.is_active(),
+            };
+            if let Some((output, output_options)) = save_dialog(&window, "watermarked.pdf") {
+                match pdf::watermark(input, output, &watermark, &pages, &output_options) {
+                    Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("Watermarked {} pages.", c)),
                     Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
                 }
             }
         }
     });
+
+    let file_clone = Rc::clone(&selected_file);
+    let pages_entry_clone = pages_entry.clone();
+    let window_weak = window.downgrade();
+    image_stamp_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let file = file_clone.borrow();
+        if let Some(input) = &*file {
+            let Some(image) = selected_image.borrow().clone() else {
+                show_message(&window, gtk::MessageType::Error, "Error", "Choose an image to stamp first.");
+                return;
+            };
+            let pages = match pdf::parse_page_ranges(&pages_entry_clone.text()) {
+                Ok(p) => p,
+                Err(e) => { show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)); return; }
+            };
+            let image_stamp = stamp::ImageStamp {
+                width: image_width_spin.value() as f32,
+                opacity: image_opacity_spin.value() as f32 / 100.0,
+                rotation: image_angle_spin.value() as f32,
+                position: selected_position(&image_position_combo),
+                offset: (offset_x_spin.value() as f32, offset_y_spin.value() as f32),
+                under: image_under_check.is_active(),
+            };
+            if let Some((output, output_options)) = save_dialog(&window, "stamped.pdf") {
+                match pdf::image_stamp(input, output, &image, &image_stamp, &pages, &output_options) {
+                    Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("Stamped {} pages.", c)),
+                    Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
+                }
+            }
+        }
+    });
+
+    let file_clone = Rc::clone(&selected_file);
+    let pages_entry_clone = pages_entry.clone();
+    let window_weak = window.downgrade();
+    number_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let file = file_clone.borrow();
+        if let Some(input) = &*file {
+            let pages = match pdf::parse_page_ranges(&pages_entry_clone.text()) {
+                Ok(p) => p,
+                Err(e) => { show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)); return; }
+            };
+            let options = pdf::NumberingOptions {
+                template: template_entry.text().to_string(),
+                start: start_spin.value() as u64,
+                digits: digits_spin.value() as usize,
+                prefix: prefix_entry.text().to_string(),
+                suffix: suffix_entry.text().to_string(),
+                style: stamp::TextStamp {
+                    font: selected_font(&number_font_combo),
+                    size: number_size_spin.value() as f32,
+                    color: selected_color(&number_color_btn),
+                    position: selected_position(&number_position_combo),
+                    margin: number_margin_spin.value() as f32,
+                    ..Default::default()
+                },
+            };
+            if let Some((output, output_options)) = save_dialog(&window, "numbered.pdf") {
+                match pdf::number_pages(input, output, &options, &pages, &output_options) {
+                    Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("Numbered {} pages.", c)),
+                    Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
+                }
+            }
+        }
+    });
+
+    let file_clone = Rc::clone(&selected_file);
+    let pages_entry_clone = pages_entry.clone();
+    let window_weak = window.downgrade();
+    headers_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let file = file_clone.borrow();
+        if let Some(input) = &*file {
+            let pages = match pdf::parse_page_ranges(&pages_entry_clone.text()) {
+                Ok(p) => p,
+                Err(e) => { show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)); return; }
+            };
+            let options = pdf::HeaderFooterOptions {
+                all: header_footer(&all_entries),
+                first: first_check.is_active().then(|| header_footer(&first_entries)),
+                even: even_check.is_active().then(|| header_footer(&even_entries)),
+                style: stamp::TextStamp {
+                    font: selected_font(&header_font_combo),
+                    size: header_size_spin.value() as f32,
+                    color: selected_color(&header_color_btn),
+                    margin: header_margin_spin.value() as f32,
+                    ..Default::default()
+                },
+            };
+            if let Some((output, output_options)) = save_dialog(&window, "headers.pdf") {
+                match pdf::headers_footers(input, output, &options, &pages, &output_options) {
+                    Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("Added headers and footers to {} pages.", c)),
+                    Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
+                }
+            }
+        }
+    });
+
+    let file_clone = Rc::clone(&selected_file);
+    let pages_entry_clone = pages_entry.clone();
+    let window_weak = window.downgrade();
+    apply_overlay_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let file = file_clone.borrow();
+        if let Some(input) = &*file {
+            let Some(stamp_pdf) = selected_overlay.borrow().clone() else {
+                show_message(&window, gtk::MessageType::Error, "Error", "Choose the PDF to overlay first.");
+                return;
+            };
+            let pages = match pdf::parse_page_ranges(&pages_entry_clone.text()) {
+                Ok(p) => p,
+                Err(e) => { show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)); return; }
+            };
+            let mode = overlay_mode_combo.active_id().and_then(|id| pdf::OverlayMode::from_name(&id)).unwrap_or(pdf::OverlayMode::Repeat);
+            if let Some((output, output_options)) = save_dialog(&window, "overlaid.pdf") {
+                match pdf::overlay(input, &stamp_pdf, output, mode, overlay_under_check.is_active(), &pages, &output_options) {
+                    Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("Stamped {} pages.", c)),
+                    Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
+                }
+            }
+        }
+    });
+}
+
+// Left, center and right entries for a header, then for a footer.
+fn header_footer_entries(container: &gtk::Box) -> [Entry; 6] {
+    let entries: [Entry; 6] = std::array::from_fn(|_| Entry::new());
+    for (row_entries, text) in entries.chunks(3).zip(["Header", "Footer"]) {
+        let row = gtk::Box::new(Orientation::Horizontal, 10);
+        row.pack_start(&Label::new(Some(text)), false, false, 0);
+        for (entry, placeholder) in row_entries.iter().zip(["Left", "Center", "Right"]) {
+            entry.set_placeholder_text(Some(placeholder));
+            row.pack_start(entry, true, true, 0);
+        }
+        container.pack_start(&row, false, false, 0);
+    }
+    entries
+}
+
+fn header_footer(entries: &[Entry; 6]) -> pdf::HeaderFooter {
+    let texts: [String; 6] = std::array::from_fn(|i| entries[i].text().to_string());
+    let [a, b, c, d, e, f] = texts;
+    pdf::HeaderFooter { header: [a, b, c], footer: [d, e, f] }
+}
+
+fn standard_font_combo() -> ComboBoxText {
+    let combo = ComboBoxText::new();
+    for (font, text) in StandardFont::ALL.into_iter().zip(["Helvetica", "Helvetica Bold", "Times", "Times Bold", "Courier", "Courier Bold"]) {
+        combo.append(Some(font.name()), text);
+    }
+    combo.set_active_id(Some(StandardFont::Helvetica.name()));
+    combo
+}
+
+fn selected_font(combo: &ComboBoxText) -> StandardFont {
+    combo.active_id().and_then(|id| StandardFont::from_name(&id)).unwrap_or(StandardFont::Helvetica)
+}
+
+fn stamp_position_combo(active: Position) -> ComboBoxText {
+    let combo = ComboBoxText::new();
+    let texts = ["Top left", "Top", "Top right", "Left", "Center", "Right", "Bottom left", "Bottom", "Bottom right"];
+    for (position, text) in Position::ALL.into_iter().zip(texts) { combo.append(Some(position.name()), text); }
+    combo.set_active_id(Some(active.name()));
+    combo
+}
+
+fn selected_position(combo: &ComboBoxText) -> Position {
+    combo.active_id().and_then(|id| Position::from_name(&id)).unwrap_or(Position::Center)
+}
+
+fn selected_color(button: &gtk::ColorButton) -> [f32; 3] {
+    let rgba = button.rgba();
+    [rgba.red() as f32, rgba.green() as f32, rgba.blue() as f32]
+}
+
+// A loaded crop preview page with its images ready to paint.
+struct CropPreview {
+    page: pdf::PagePreview,
+    surfaces: HashMap<lopdf::ObjectId, ImageSurface>,
+}
+
+// Scale and offset that fit the displayed page into the preview area.
+fn preview_view(area: &DrawingArea, page: &pdf::PagePreview) -> (f64, f64, f64) {
+    let (width, height) = page.size();
+    let (area_width, area_height) = (area.allocated_width() as f64, area.allocated_height() as f64);
+    let scale = ((area_width - 20.0) / width as f64).min((area_height - 20.0) / height as f64).max(0.01);
+    (scale, (area_width - width as f64 * scale) / 2.0, (area_height - height as f64 * scale) / 2.0)
+}
+
+// A position in the preview area as points from the displayed page's top left corner, kept on the page.
+fn page_point(area: &DrawingArea, page: &pdf::PagePreview, (x, y): (f64, f64)) -> (f64, f64) {
+    let (scale, left, top) = preview_view(area, page);
+    let (width, height) = page.size();
+    (((x - left) / scale).clamp(0.0, width as f64), ((y - top) / scale).clamp(0.0, height as f64))
+}
+
+// Paints the page's marks in gray, with text as blocks and the detected content outlined, onto a context set up in
+// displayed points.
+fn draw_page(cr: &gtk::cairo::Context, preview: &CropPreview, scale: f64) {
+    let page = &preview.page;
+    let to_cairo = |m: &[f32; 6]| gtk::cairo::Matrix::new(m[0] as f64, m[1] as f64, m[2] as f64, m[3] as f64, m[4] as f64, m[5] as f64);
+    cr.save().ok();
+    cr.transform(to_cairo(&page.display_matrix()));
+    let [x0, y0, x1, y1] = page.rect.map(|v| v as f64);
+    cr.rectangle(x0, y0, x1 - x0, y1 - y0);
+    cr.clip();
+    for mark in &page.marks {
+        match mark {
+            Mark::Path { subpaths, fill, stroke } => {
+                for subpath in subpaths {
+                    let Some(&(x, y)) = subpath.first() else { continue };
+                    cr.move_to(x as f64, y as f64);
+                    for &(x, y) in &subpath[1..] { cr.line_to(x as f64, y as f64); }
+                }
+                cr.set_source_rgb(0.35, 0.35, 0.35);
+                if *fill { cr.fill_preserve().ok(); }
+                if let Some(width) = stroke {
+                    cr.set_line_width((*width as f64).max(0.5 / scale));
+                    cr.stroke_preserve().ok();
+                }
+                cr.new_path();
+            }
+            Mark::Text(corners) => {
+                for &(x, y) in corners { cr.line_to(x as f64, y as f64); }
+                cr.close_path();
+                cr.set_source_rgb(0.55, 0.6, 0.75);
+                cr.fill().ok();
+            }
+            Mark::Image { matrix, id } => {
+                cr.save().ok();
+                cr.transform(to_cairo(matrix));
+                match id.and_then(|id| preview.surfaces.get(&id)) {
+                    Some(surface) => {
+                        let (width, height) = (surface.width() as f64, surface.height() as f64);
+                        // Image rows run from the top of the unit square down.
+                        cr.transform(gtk::cairo::Matrix::new(1.0 / width, 0.0, 0.0, -1.0 / height, 0.0, 1.0));
+                        cr.set_source_surface(surface, 0.0, 0.0).ok();
+                        cr.rectangle(0.0, 0.0, width, height);
+                    }
+                    None => {
+                        cr.set_source_rgb(0.75, 0.75, 0.75);
+                        cr.rectangle(0.0, 0.0, 1.0, 1.0);
+                    }
+                }
+                cr.fill().ok();
+                cr.restore().ok();
+            }
+        }
+    }
+    if let Some([x0, y0, x1, y1]) = page.content.map(|r| r.map(|v| v as f64)) {
+        cr.set_source_rgb(0.1, 0.6, 0.2);
+        cr.set_line_width(1.0 / scale);
+        cr.set_dash(&[4.0 / scale, 3.0 / scale], 0.0);
+        cr.rectangle(x0, y0, x1 - x0, y1 - y0);
+        cr.stroke().ok();
+    }
+    cr.restore().ok();
+}
+
+fn image_surface(img: &image::RgbImage) -> Option<ImageSurface> {
+    let mut surface = ImageSurface::create(gtk::cairo::Format::Rgb24, img.width() as i32, img.height() as i32).ok()?;
+    let stride = surface.stride() as usize;
+    {
+        let mut data = surface.data().ok()?;
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let [r, g, b] = pixel.0;
+            let i = y as usize * stride + x as usize * 4;
+            // Native-endian 0x00RRGGBB words.
+            data[i..i + 4].copy_from_slice(&u32::from_be_bytes([0, r, g, b]).to_ne_bytes());
+        }
+    }
+    Some(surface)
 }
 
 pub fn create_tools_tab(notebook: &Notebook, window: &Window) {
@@ -271,14 +1403,37 @@ pub fn create_tools_tab(notebook: &Notebook, window: &Window) {
         let window = match window_weak.upgrade() { Some(w) => w, None => return };
         let file = file_clone.borrow();
         if let Some(input) = &*file {
-            if let Some(output) = save_dialog(&window, "image.pdf") {
-                match pdf::image_to_pdf(input, output) {
+            if let Some((output, output_options)) = save_dialog(&window, "image.pdf") {
+                match pdf::image_to_pdf(input, output, &output_options) {
                     Ok(_) => show_message(&window, gtk::MessageType::Info, "Success", "Converted successfully!"),
                     Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
                 }
             }
         }
     });
+
+    let sep = gtk::Separator::new(Orientation::Horizontal);
+    box_container.pack_start(&sep, false, false, 5);
+
+    // Fast Web View Section
+    let check_label = Label::new(Some("Check whether a PDF is linearized for fast web view"));
+    box_container.pack_start(&check_label, false, false, 0);
+    let check_btn = Button::with_label("Check PDF");
+    box_container.pack_start(&check_btn, false, false, 0);
+
+    let window_weak = window.downgrade();
+    check_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let dialog = create_file_chooser(&window, FileChooserAction::Open, "Select PDF File", false);
+        let file = if dialog.run() == ResponseType::Accept { dialog.filename() } else { None };
+        dialog.close();
+        if let Some(input) = file {
+            match pdf::check_linearized(&input) {
+                Ok(report) => show_message(&window, gtk::MessageType::Info, "Fast Web View", &report.summary()),
+                Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
+            }
+        }
+    });
 }
 
 pub fn create_organize_tab(notebook: &Notebook, window: &Window) {
@@ -320,6 +1475,18 @@ pub fn create_organize_tab(notebook: &Notebook, window: &Window) {
     let sep3 = gtk::Separator::new(Orientation::Horizontal);
     box_container.pack_start(&sep3, false, false, 5);
 
+    // Extract Section
+    let ex_label = Label::new(Some("Extract Pages (e.g. 4, 9, 12-15)"));
+    box_container.pack_start(&ex_label, false, false, 0);
+    let ex_entry = Entry::new();
+    box_container.pack_start(&ex_entry, false, false, 0);
+    let ex_btn = Button::with_label("Extract Pages");
+    ex_btn.set_sensitive(false);
+    box_container.pack_start(&ex_btn, false, false, 0);
+
+    let sep4 = gtk::Separator::new(Orientation::Horizontal);
+    box_container.pack_start(&sep4, false, false, 5);
+
     // Insert Section
     let ins_label = Label::new(Some("Insert PDF at Position"));
     box_container.pack_start(&ins_label, false, false, 0);
@@ -334,6 +1501,7 @@ pub fn create_organize_tab(notebook: &Notebook, window: &Window) {
     let label_clone = label.clone();
     let del_btn_clone = del_btn.clone();
     let re_btn_clone = re_btn.clone();
+    let ex_btn_clone = ex_btn.clone();
     let ins_btn_clone = ins_btn.clone();
     let file_clone = Rc::clone(&selected_file);
     let window_weak = window.downgrade();
@@ -348,6 +1516,7 @@ pub fn create_organize_tab(notebook: &Notebook, window: &Window) {
                 label_clone.set_text(&format!("Selected: {}", f.file_name().unwrap().to_string_lossy()));
                 del_btn_clone.set_sensitive(true);
                 re_btn_clone.set_sensitive(true);
+                ex_btn_clone.set_sensitive(true);
                 ins_btn_clone.set_sensitive(true);
             }
         }
@@ -366,8 +1535,8 @@ pub fn create_organize_tab(notebook: &Notebook, window: &Window) {
                 .filter_map(|s| s.trim().parse::<u32>().ok())
                 .collect();
             if !pages.is_empty() {
-                if let Some(output) = save_dialog(&window, "modified.pdf") {
-                    match pdf::delete_pages(input, output, pages) {
+                if let Some((output, output_options)) = save_dialog(&window, "modified.pdf") {
+                    match pdf::delete_pages(input, output, pages, &output_options) {
                         Ok(_) => show_message(&window, gtk::MessageType::Info, "Success", "Pages deleted!"),
                         Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
                     }
@@ -388,8 +1557,8 @@ pub fn create_organize_tab(notebook: &Notebook, window: &Window) {
                 .filter_map(|s| s.trim().parse::<u32>().ok())
                 .collect();
             if !order.is_empty() {
-                if let Some(output) = save_dialog(&window, "reordered.pdf") {
-                    match pdf::reorder_pages(input, output, order) {
+                if let Some((output, output_options)) = save_dialog(&window, "reordered.pdf") {
+                    match pdf::reorder_pages(input, output, order, &output_options) {
                         Ok(_) => show_message(&window, gtk::MessageType::Info, "Success", "Pages reordered!"),
                         Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
                     }
@@ -398,6 +1567,27 @@ pub fn create_organize_tab(notebook: &Notebook, window: &Window) {
         }
     });
 
+    let file_clone = Rc::clone(&selected_file);
+    let ex_entry_clone = ex_entry.clone();
+    let window_weak = window.downgrade();
+    ex_btn.connect_clicked(move |_| {
+        let window = match window_weak.upgrade() { Some(w) => w, None => return };
+        let file = file_clone.borrow();
+        if let Some(input) = &*file {
+            let pages = match pdf::parse_page_ranges(&ex_entry_clone.text()) {
+                Ok(p) => p,
+                Err(e) => { show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)); return; }
+            };
+            if pages.is_empty() { return; }
+            if let Some((output, output_options)) = save_dialog(&window, "extracted.pdf") {
+                match pdf::extract_pages(input, output, pages, &output_options) {
+                    Ok(c) => show_message(&window, gtk::MessageType::Info, "Success", &format!("Extracted {} pages.", c)),
+                    Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
+                }
+            }
+        }
+    });
+
     let file_clone = Rc::clone(&selected_file);
     let ins_pos_entry_clone = ins_pos_entry.clone();
     let window_weak = window.downgrade();
@@ -410,8 +1600,8 @@ pub fn create_organize_tab(notebook: &Notebook, window: &Window) {
             if dialog.run() == ResponseType::Accept {
                 if let Some(to_insert) = dialog.filename() {
                     dialog.close();
-                    if let Some(output) = save_dialog(&window, "inserted.pdf") {
-                        match pdf::insert_pages(input, &to_insert, output, pos) {
+                    if let Some((output, output_options)) = save_dialog(&window, "inserted.pdf") {
+                        match pdf::insert_pages(input, &to_insert, output, pos, &output_options) {
                             Ok(_) => show_message(&window, gtk::MessageType::Info, "Success", "PDF inserted successfully!"),
                             Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
                         }
@@ -480,8 +1670,8 @@ pub fn create_security_tab(notebook: &Notebook, window: &Window) {
                 show_message(&window, gtk::MessageType::Warning, "Warning", "Password cannot be empty.");
                 return;
             }
-            if let Some(output) = save_dialog(&window, "protected.pdf") {
-                match pdf::encrypt_pdf(input, output, password) {
+            if let Some((output, output_options)) = save_dialog(&window, "protected.pdf") {
+                match pdf::encrypt_pdf(input, output, password, &output_options) {
                     Ok(_) => show_message(&window, gtk::MessageType::Info, "Success", "Password applied!"),
                     Err(e) => show_message(&window, gtk::MessageType::Error, "Error", &format!("Error: {}", e)),
                 }
@@ -501,11 +1691,32 @@ fn create_file_chooser(window: &Window, action: FileChooserAction, title: &str,
     dialog
 }
 
-fn save_dialog(window: &Window, default_name: &str) -> Option<PathBuf> {
+fn labeled_spin(container: &gtk::Box, text: &str, min: f64, max: f64, value: f64) -> SpinButton {
+    let row = gtk::Box::new(Orientation::Horizontal, 10);
+    row.pack_start(&Label::new(Some(text)), false, false, 0);
+    let spin = SpinButton::with_range(min, max, 1.0);
+    spin.set_value(value);
+    row.pack_end(&spin, false, false, 0);
+    container.pack_start(&row, false, false, 0);
+    spin
+}
+
+fn save_dialog(window: &Window, default_name: &str) -> Option<(PathBuf, pdf::OutputOptions)> {
     let dialog = FileChooserDialog::with_buttons(Some("Save File"), Some(window), FileChooserAction::Save,
         &[("_Cancel", ResponseType::Cancel), ("_Save", ResponseType::Accept)]);
     dialog.set_current_name(default_name);
-    let res = if dialog.run() == ResponseType::Accept { dialog.filename() } else { None };
+    let filter = FileFilter::new();
+    if default_name.ends_with(".zip") {
+        filter.add_pattern("*.zip");
+        filter.set_name(Some("Zip archives"));
+    } else {
+        filter.add_pattern("*.pdf");
+        filter.set_name(Some("PDF files"));
+    }
+    dialog.add_filter(filter);
+    let linearize_check = CheckButton::with_label("Linearize for fast web view");
+    dialog.set_extra_widget(&linearize_check);
+    let res = if dialog.run() == ResponseType::Accept { dialog.filename().map(|f| (f, pdf::OutputOptions { linearize: linearize_check.is_active() })) } else { None };
     dialog.close();
     res
 }